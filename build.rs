@@ -0,0 +1,64 @@
+// Packs the core data files into one compressed bundle for the embedded_assets feature
+fn main() {
+    #[cfg(feature = "embedded_assets")]
+    bundle::write_core_assets();
+}
+
+#[cfg(feature = "embedded_assets")]
+mod bundle {
+    use std::{
+        env,
+        fs,
+        path::{ Path, PathBuf },
+    };
+
+    // Relative to assets/.  Directories are packed recursively.
+    const CORE_FILES: [&str; 4] = [
+        "base.obj",
+        "basemesh_vertex_groups.json",
+        "rigs",
+        "targets",
+    ];
+    // Packed when present
+    const OPTIONAL_FILES: [&str; 1] = ["measurements.json"];
+    const EXTENSIONS: [&str; 3] = ["obj", "json", "target"];
+
+    pub(super) fn write_core_assets() {
+        let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets");
+        let mut files = Vec::<PathBuf>::new();
+        for name in CORE_FILES {
+            println!("cargo:rerun-if-changed=assets/{}", name);
+            collect_files(&root.join(name), &mut files);
+        }
+        for name in OPTIONAL_FILES {
+            println!("cargo:rerun-if-changed=assets/{}", name);
+            if root.join(name).is_file() { files.push(root.join(name)); }
+        }
+        files.sort();
+
+        // Per file: path length, path, data length, data.  Lengths are u32 LE.
+        let mut archive = Vec::<u8>::new();
+        for path in files.iter() {
+            let relative = path.strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/");
+            let data = fs::read(path).expect("FAILED TO READ CORE ASSET");
+            archive.extend((relative.len() as u32).to_le_bytes());
+            archive.extend(relative.as_bytes());
+            archive.extend((data.len() as u32).to_le_bytes());
+            archive.extend(data);
+        }
+
+        let compressed = miniz_oxide::deflate::compress_to_vec(&archive, 9);
+        let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("core_assets.bin");
+        fs::write(out, compressed).expect("FAILED TO WRITE CORE ASSET BUNDLE");
+    }
+
+    fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+        if path.is_dir() {
+            for entry in fs::read_dir(path).unwrap().filter_map(Result::ok) {
+                collect_files(&entry.path(), files);
+            }
+        } else if path.extension().and_then(|e| e.to_str()).is_some_and(|e| EXTENSIONS.contains(&e)) {
+            files.push(path.to_path_buf());
+        }
+    }
+}
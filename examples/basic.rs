@@ -129,11 +129,7 @@ fn setup_env(
             _  => { panic!{"uninitialized human"}; }
         }
         let transform = Transform::from_xyz(*i as f32 - 1.5, 0.0, 0.0);
-        commands.spawn((
-            SpawnTransform(transform),
-            config,
-            AnimationPlayer::default(),
-        ));
+        commands.spawn_human(config).insert(transform);
     }
 }
 
@@ -148,8 +144,8 @@ fn main() {
                     rig_type: RigType::Mixamo,
                 })
         )
-        .add_plugins(Humentity{ debug: true })
-        .add_systems(OnEnter(HumentityState::Ready), setup_env)
+        .add_plugins(HumentityPlugins)
+        .add_systems(Startup, setup_env)
         .run();
 }
 
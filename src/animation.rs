@@ -4,10 +4,10 @@ use bevy::{
 };
 use std::{
     collections::HashMap,
-    fs::read_dir,
     path::PathBuf,
 };
 use crate::{
+    has_extension,
     HumentityGlobalConfig,
     LoadingPhase,
     LoadingState,
@@ -51,25 +51,12 @@ impl FromWorld for AnimationLibrarySet {
         let mut handles = HashMap::<String, Handle<Gltf>>::new();
 
         // We will search the folder(s) provided for glb/gltf files 
-        for path in config.animation_libraries.paths.iter() {
-            for entry in read_dir(path.clone()).unwrap() {
-                let file = entry.expect("Unspecified file Error");
-                if !file.file_type().unwrap().is_file() { continue; }
-                let path = file.path();
-                let Some(extension) = path.extension() else { continue };
-                if !(extension == "glb" || extension == "gltf") { continue }
+        for dir in config.animation_libraries.paths.iter() {
+            for path in config.source.files(dir) {
+                if !has_extension(&path, &["glb", "gltf"]) { continue }
                 let name = path.file_stem().unwrap().to_string_lossy();
-
-                // We have to feed the path relative to "assets" into the asset_loader
-                let components: Vec<&str> = path.components().map(|c| c.as_os_str().to_str().unwrap()).collect();
-                if let Some(index) = components.iter().position(|&comp| comp == "assets") {
-                    // Create a relative path starting from assets
-                    let relative_path: PathBuf = components[index + 1..].iter().collect::<PathBuf>();
-                    let handle = asset_server.load(relative_path);
-                    handles.insert(name.to_string(), handle);
-                } else {
-                    println!("The directory '{}' was not found in the path.", "assets");
-                }
+                let handle = asset_server.load(config.source.asset_path(&path));
+                handles.insert(name.to_string(), handle);
             }
         }
 
@@ -90,14 +77,13 @@ pub(crate) fn load_animations(
 ) {
     if let Some(&done) = loading_state.0.get(&LoadingPhase::SetUpAnimationLibraries) { if done { return; } }
     for (_name, handle) in animations.gltf_handles.iter() {
-        let Some(_gltf) = gltfs.get(&*handle) else { return; };
+        let Some(_gltf) = gltfs.get(handle) else { return; };
     }
     for (name, handle) in animations.gltf_handles.clone().iter_mut() {
         let gltf = gltfs.get(handle).unwrap();
         let animation_clips: Vec<(&Box<str>, &Handle<AnimationClip>)> = gltf.named_animations.iter()
-            .map(|animation| animation.clone())
             .collect();
-        let library: HashMap<String, Handle<AnimationClip>> = animation_clips.iter().map(|(s, &ref c)| (s.to_string(), c.clone())).collect();
+        let library: HashMap<String, Handle<AnimationClip>> = animation_clips.iter().map(|(s, c)| (s.to_string(), (*c).clone())).collect();
         animations.libraries.insert(name.to_string(), AnimationLibrary(library));
     }
     loading_state.0.insert(LoadingPhase::SetUpAnimationLibraries, true);
@@ -0,0 +1,240 @@
+#[cfg(feature = "embedded_assets")]
+use bevy::{
+    asset::io::embedded::EmbeddedAssetRegistry,
+    prelude::*,
+};
+use bevy::asset::{
+    io::file::FileAssetReader,
+    AssetPath,
+};
+use std::{
+    io,
+    path::{ Path, PathBuf },
+};
+#[cfg(feature = "embedded_assets")]
+use std::{
+    collections::HashMap,
+    sync::OnceLock,
+};
+use walkdir::WalkDir;
+
+/*----------+
+ |  Traits  |
+ +----------*/
+// Where humentity reads its data from.  Paths in HumentityGlobalConfig are
+// relative to the source's root unless absolute.
+pub trait HumanAssetSource: Send + Sync + 'static {
+    // Whole contents of one file
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    // Every file under dir, recursively.  Missing dirs are empty.
+    fn files(&self, dir: &Path) -> Vec<PathBuf>;
+    // Path handed to the AssetServer for meshes and textures
+    fn asset_path(&self, path: &Path) -> AssetPath<'static>;
+}
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Plain directory on disk
+pub struct FileHumanAssetSource {
+    root: PathBuf,
+    // Bevy's asset folder.  Files inside it are handed to the AssetServer relative to it.
+    assets: PathBuf,
+}
+
+impl FileHumanAssetSource {
+    // Assumes bevy's default asset folder, see with_asset_folder
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileHumanAssetSource {
+            root: root.into(),
+            assets: FileAssetReader::get_base_path().join("assets"),
+        }
+    }
+
+    // AssetPlugin::file_path, relative to bevy's base path (BEVY_ASSET_ROOT,
+    // CARGO_MANIFEST_DIR or the executable's folder)
+    pub fn with_asset_folder(self, file_path: impl AsRef<Path>) -> Self {
+        FileHumanAssetSource {
+            assets: FileAssetReader::get_base_path().join(file_path),
+            ..self
+        }
+    }
+
+    // The humentity folder of bevy's asset folder, or the asset folder itself when
+    // base.obj sits right in it like in this crate
+    pub fn from_asset_folder(file_path: impl AsRef<Path>) -> Self {
+        let source = FileHumanAssetSource::new("").with_asset_folder(file_path);
+        let nested = source.assets.join("humentity");
+        let root = if !nested.join("base.obj").is_file() && source.assets.join("base.obj").is_file() {
+            source.assets.clone()
+        } else {
+            nested
+        };
+        FileHumanAssetSource { root, ..source }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl HumanAssetSource for FileHumanAssetSource {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(self.root.join(path))
+    }
+
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = WalkDir::new(self.root.join(dir))
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    // The default asset source only sees files relative to the app's assets
+    // folder.  Anything outside it is passed as an absolute path.
+    fn asset_path(&self, path: &Path) -> AssetPath<'static> {
+        let path = self.root.join(path);
+        match path.strip_prefix(&self.assets) {
+            Ok(relative) => AssetPath::from(relative.to_path_buf()),
+            Err(_) => AssetPath::from(path),
+        }
+    }
+}
+
+// Core data compiled into the library, with files on disk layered on top.
+// Body parts, clothes, proxies and skins still come from disk.
+#[cfg(feature = "embedded_assets")]
+pub struct EmbeddedHumanAssetSource {
+    disk: FileHumanAssetSource,
+}
+
+#[cfg(feature = "embedded_assets")]
+impl EmbeddedHumanAssetSource {
+    pub fn new(disk: FileHumanAssetSource) -> Self {
+        EmbeddedHumanAssetSource { disk }
+    }
+
+    // Embedded paths are relative to the asset root
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(self.disk.root()).unwrap_or(path)
+    }
+}
+
+#[cfg(feature = "embedded_assets")]
+impl HumanAssetSource for EmbeddedHumanAssetSource {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.disk.read(path).or_else(|err| {
+            embedded_core_assets().get(self.relative(path)).cloned().ok_or(err)
+        })
+    }
+
+    // Disk files come last so they replace embedded ones with the same name
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        let dir = self.relative(dir);
+        let disk = self.disk.files(dir);
+        let mut files: Vec<PathBuf> = embedded_core_assets().keys()
+            .filter(|path| path.starts_with(dir))
+            .filter(|path| !disk.contains(&self.disk.root().join(path)))
+            .cloned()
+            .collect();
+        files.sort();
+        files.extend(disk);
+        files
+    }
+
+    fn asset_path(&self, path: &Path) -> AssetPath<'static> {
+        let relative = self.relative(path);
+        if self.disk.root().join(relative).is_file() || !embedded_core_assets().contains_key(relative) {
+            return self.disk.asset_path(path);
+        }
+        AssetPath::from(Path::new("humentity").join(relative))
+            .with_source("embedded")
+            .into_owned()
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+// Unpacked on first use.  See build.rs for the format.
+#[cfg(feature = "embedded_assets")]
+fn embedded_core_assets() -> &'static HashMap<PathBuf, Vec<u8>> {
+    static FILES: OnceLock<HashMap<PathBuf, Vec<u8>>> = OnceLock::new();
+    FILES.get_or_init(|| {
+        let compressed = include_bytes!(concat!(env!("OUT_DIR"), "/core_assets.bin"));
+        let archive = miniz_oxide::inflate::decompress_to_vec(compressed)
+            .expect("CORRUPT EMBEDDED CORE ASSETS");
+        let mut files = HashMap::<PathBuf, Vec<u8>>::new();
+        let mut rest = archive.as_slice();
+        while !rest.is_empty() {
+            let path = take_chunk(&mut rest);
+            let data = take_chunk(&mut rest);
+            files.insert(PathBuf::from(String::from_utf8_lossy(path).as_ref()), data.to_vec());
+        }
+        files
+    })
+}
+
+// u32 LE length followed by that many bytes
+#[cfg(feature = "embedded_assets")]
+fn take_chunk<'a>(rest: &mut &'a [u8]) -> &'a [u8] {
+    let (len, tail) = rest.split_at(4);
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    let (chunk, tail) = tail.split_at(len);
+    *rest = tail;
+    chunk
+}
+
+// base.obj is loaded through the AssetServer, so it has to be in bevy's embedded source
+#[cfg(feature = "embedded_assets")]
+pub(crate) fn register_embedded_assets(app: &mut App) {
+    let registry = app.world().resource::<EmbeddedAssetRegistry>();
+    for (path, data) in embedded_core_assets().iter() {
+        if !has_extension(path, &["obj"]) { continue; }
+        registry.insert_asset(path.clone(), &Path::new("humentity").join(path), data.clone());
+    }
+}
+
+pub(crate) fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| extensions.contains(&e))
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HumentityGlobalConfig;
+
+    #[test]
+    fn follows_asset_folder() {
+        let base = FileAssetReader::get_base_path();
+        // This crate keeps base.obj right in its assets
+        let source = FileHumanAssetSource::from_asset_folder("assets");
+        assert_eq!(source.root(), base.join("assets"));
+        assert_eq!(source.asset_path(Path::new("base.obj")), AssetPath::from("base.obj"));
+
+        let source = FileHumanAssetSource::from_asset_folder("game_assets");
+        assert_eq!(source.root(), base.join("game_assets/humentity"));
+        assert_eq!(source.asset_path(Path::new("rigs/x.mhskel")), AssetPath::from("humentity/rigs/x.mhskel"));
+    }
+
+    #[test]
+    fn outside_files_are_absolute() {
+        let source = FileHumanAssetSource::new("/opt/humans").with_asset_folder("assets");
+        assert_eq!(source.asset_path(Path::new("base.obj")), AssetPath::from(PathBuf::from("/opt/humans/base.obj")));
+    }
+
+    #[test]
+    #[should_panic(expected = "NO base.obj")]
+    fn roots_need_base_mesh() {
+        HumentityGlobalConfig::default()
+            .with_asset_root("/nonexistent")
+            .resolve_asset_folder(Path::new("assets"));
+    }
+}
@@ -9,30 +9,37 @@ use::bevy::{
     },
 };
 use::std::{
-    io::{ BufRead, BufReader, },
-    fs::File,
     path::PathBuf,
     collections::{ HashMap, HashSet },
 };
-use walkdir::WalkDir;
+#[cfg(feature = "clothing")]
+use std::{
+    io::BufRead,
+    path::Path,
+    sync::Arc,
+};
 use crate::{
-    generate_vertex_map,
     generate_inverse_vertex_map,
     get_vertex_positions,
-    parse_obj_vertices,
     get_vertex_normals, 
     get_uv_coords,
+};
+#[cfg(feature = "clothing")]
+use crate::{
+    has_extension,
+    generate_vertex_map,
+    parse_obj_vertices,
+    HumanAssetSource,
     HumentityGlobalConfig,
-    HumentityState,
     LoadingPhase,
     LoadingState,
-    BaseMesh,
 };
 
 /*---------+
  |  Types  |
  +---------*/
  #[allow(dead_code)]
+#[derive(Clone)]
 pub struct HumanMeshAsset {
    pub name: String,
    pub(crate) mesh_handle: Handle<Mesh>,
@@ -47,37 +54,48 @@ pub struct HumanMeshAsset {
 }
 
 impl HumanMeshAsset {
-    pub(crate) fn get_offset_scale(&self, helpers: &Vec<Vec3>) -> Vec3 {
+    pub(crate) fn get_offset_scale(&self, helpers: &[Vec3]) -> Vec3 {
         Vec3::new(
             (helpers[self.scale_data[0].max as usize] - helpers[self.scale_data[0].min as usize]).x / self.scale_data[0].scale,
             (helpers[self.scale_data[1].max as usize] - helpers[self.scale_data[1].min as usize]).y / self.scale_data[1].scale,
             (helpers[self.scale_data[2].max as usize] - helpers[self.scale_data[2].min as usize]).z / self.scale_data[2].scale,
         )
     }
+
+    // Takes the parsed data of an edited .mhclo.  Keeps the mesh, vertex map and slots.
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn reload_from(&mut self, reloaded: &HumanMeshAsset) {
+        self.helper_maps = reloaded.helper_maps.clone();
+        self.delete_verts = reloaded.delete_verts.clone();
+        self.tags = reloaded.tags.clone();
+        self.z_depth = reloaded.z_depth;
+        self.scale_data = reloaded.scale_data.clone();
+    }
 }
 
 // Each vertex is mapped to either a single helper vertex
 // or triangulated by 3 of them
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub(crate) struct HelperMap {
     pub(crate) single_vertex: Option<u16>,
     pub(crate) triangle: Option<Triangle>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub(crate) struct Triangle {
    pub(crate) helper_verts: [u16; 3],
    pub(crate) helper_weights: [f32; 3],
    pub(crate) helper_offset: Vec3,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct ScaleData {
    min: u16,
    max: u16,
    scale: f32,
 }
 
+#[cfg(feature = "clothing")]
 #[derive(Eq, PartialEq)]
 enum FileSection {
    Header,
@@ -88,6 +106,7 @@ enum FileSection {
 /*-------------+
  |  Resources  |
  +-------------*/
+#[cfg(feature = "clothing")]
 #[allow(dead_code)]
 #[derive(Resource)]
 pub struct HumanAssetTextures {
@@ -96,6 +115,7 @@ pub struct HumanAssetTextures {
     pub ao_map: HashMap<String, Handle<Image>>,
 }
 
+#[cfg(feature = "clothing")]
 #[allow(dead_code)]
 #[derive(Resource)]
 pub struct HumanAssetRegistry {
@@ -103,8 +123,13 @@ pub struct HumanAssetRegistry {
     pub equipment: HashMap<String, HumanMeshAsset>,
     pub slot_body_parts: HashMap<String, Vec<String>>,
     pub slot_equipment: HashMap<String, Vec<String>>,
+    // Albedo file names under skin_textures/albedo
+    pub skins: Vec<String>,
+    // Low poly bodies from proxies, used for LODs
+    pub proxies: HashMap<String, HumanMeshAsset>,
 }
 
+#[cfg(feature = "clothing")]
 impl FromWorld for HumanAssetRegistry {
     fn from_world(world: &mut World) -> Self{
         let mut body_parts = HashMap::<String, HumanMeshAsset>::new();
@@ -116,16 +141,16 @@ impl FromWorld for HumanAssetRegistry {
         let body_part_paths = config.body_part_paths.clone();
         let equipment_paths = config.equipment_paths.clone();
         let body_part_slots = config.body_part_slots.clone();
-        let equipment_slots = config.body_part_slots.clone();
+        let equipment_slots = config.equipment_slots.clone();
+        let source = config.source.clone();
+        let skins_path = Path::new("skin_textures/albedo");
+        let proxies_path = Path::new("proxies");
 
         for dir in body_part_paths {
-            for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
-                let path = entry.path();
-                if !path.is_file() { continue; }
-                let Some(extension) = path.extension().and_then(|e| e.to_str()) else { continue };
-                if extension == "mhclo" {
+            for path in source.files(&dir) {
+                if has_extension(&path, &["mhclo"]) {
                     // parse
-                    let mut bp = parse_human_asset(path.to_path_buf(), world);
+                    let mut bp = parse_human_asset(path, &source, world);
                     // set slots
                     let mut slots = Vec::<String>::new();
                     for tag in &bp.tags {
@@ -134,7 +159,7 @@ impl FromWorld for HumanAssetRegistry {
                     bp.slots = slots.clone();
                     // insert into slots hashmap
                     for slot in slots.iter() {
-                        let bp_vec = slot_body_parts.entry(slot.to_string()).or_insert(Vec::<String>::new());
+                        let bp_vec = slot_body_parts.entry(slot.to_string()).or_default();
                         bp_vec.push(bp.name.clone());
                     }
                     // insert into name hashmap
@@ -144,19 +169,40 @@ impl FromWorld for HumanAssetRegistry {
         }
 
         for dir in equipment_paths {
-            for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
-                let path = entry.path();
+            for path in source.files(&dir) {
                 //let stem = path.file_stem().unwrap().to_str().unwrap();
                 //if stem.eq_ignore_ascii_case("eyes") { slot = BodyPartSlot::Eyes; }
-                if !path.is_file() { continue; }
-                let Some(extension) = path.extension().and_then(|e| e.to_str()) else { continue };
-                if extension == "mhclo" {
-                    let eq = parse_human_asset(path.to_path_buf(), world);
+                if has_extension(&path, &["mhclo"]) {
+                    let mut eq = parse_human_asset(path, &source, world);
+                    let mut slots = Vec::<String>::new();
+                    for tag in &eq.tags {
+                        if equipment_slots.contains(tag) { slots.push(tag.to_string()) };
+                    }
+                    eq.slots = slots.clone();
+                    for slot in slots.iter() {
+                        let eq_vec = slot_equipment.entry(slot.to_string()).or_default();
+                        eq_vec.push(eq.name.clone());
+                    }
                     equipment.insert(eq.name.clone(), eq);
                 }
             }
         }
 
+        let mut proxies = HashMap::<String, HumanMeshAsset>::new();
+        for path in source.files(proxies_path) {
+            if !has_extension(&path, &["proxy"]) { continue; }
+            let proxy = parse_human_asset(path, &source, world);
+            proxies.insert(proxy.name.clone(), proxy);
+        }
+
+        let mut skins = Vec::<String>::new();
+        for path in source.files(skins_path) {
+            if !has_extension(&path, &["png"]) { continue; }
+            let Some(file) = path.file_name().and_then(|s| s.to_str()) else { continue };
+            skins.push(file.to_string());
+        }
+        skins.sort();
+
         // Load textures
         // It is assumed:
         // normal maps end with _normal.png
@@ -169,19 +215,15 @@ impl FromWorld for HumanAssetRegistry {
         for (name, asset) in equipment.iter().chain(body_parts.iter()) {
             let dir = asset.obj_file.parent().unwrap();
             let mut asset_albedos = Vec::<Handle<Image>>::new();
-            for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
-                let path = entry.path().to_path_buf();
-                if path.is_file() {
-                    let Some(extension) = path.extension().and_then(|e| e.to_str()) else { continue };
-                    if extension == "png" {
-                        let image = asset_server.load(path.clone());
-                        if let Some(file) = path.file_name().and_then(|s| s.to_str()) {
-                            if file.ends_with("_bump.png") { continue; }
-                            if !file.starts_with("overlay_") {
-                                if file.ends_with("_normal.png") { normal_texture.insert(name.to_string(), image); }
-                                else if file.ends_with("_ao.png") { ao_texture.insert(name.to_string(), image); }
-                                else { asset_albedos.push(image); }
-                            }
+            for path in source.files(dir) {
+                if has_extension(&path, &["png"]) {
+                    let image = asset_server.load(source.asset_path(&path));
+                    if let Some(file) = path.file_name().and_then(|s| s.to_str()) {
+                        if file.ends_with("_bump.png") { continue; }
+                        if !file.starts_with("overlay_") {
+                            if file.ends_with("_normal.png") { normal_texture.insert(name.to_string(), image); }
+                            else if file.ends_with("_ao.png") { ao_texture.insert(name.to_string(), image); }
+                            else { asset_albedos.push(image); }
                         }
                     }
                 }
@@ -195,10 +237,12 @@ impl FromWorld for HumanAssetRegistry {
         });
 
         HumanAssetRegistry {
-            body_parts: body_parts,
-            equipment: equipment,
-            slot_body_parts: slot_body_parts,
-            slot_equipment: slot_equipment,
+            body_parts,
+            equipment,
+            slot_body_parts,
+            slot_equipment,
+            skins,
+            proxies,
         }
     }
 }
@@ -206,10 +250,12 @@ impl FromWorld for HumanAssetRegistry {
 /*-----------+
  |  Systems  |
  +-----------*/
+ #[cfg(feature = "clothing")]
  pub(crate) fn generate_asset_vertex_maps(
     mut registry: ResMut<HumanAssetRegistry>,
     mut loading_state: ResMut<LoadingState>,
     meshes: Res<Assets<Mesh>>,
+    global_config: Res<HumentityGlobalConfig>,
  ) {
     if *loading_state.0.get(&LoadingPhase::GenerateAssetVertexMap).unwrap() { return };
     for (_name, asset) in registry.body_parts.iter_mut() {
@@ -218,32 +264,74 @@ impl FromWorld for HumanAssetRegistry {
     for (_name, asset) in registry.equipment.iter_mut() {
         let Some(_mesh) = meshes.get(&asset.mesh_handle) else { return };
     }
+    for (_name, asset) in registry.proxies.iter_mut() {
+        let Some(_mesh) = meshes.get(&asset.mesh_handle) else { return };
+    }
 
-    for (name, asset) in registry.body_parts.iter_mut() {
-        //println!("Importing body part: {name}");
-        let mh_verts = parse_obj_vertices(&asset.obj_file);
+    for (_name, asset) in registry.body_parts.iter_mut() {
+        let mh_verts = read_obj_vertices(&global_config.source, &asset.obj_file);
         let mesh = meshes.get(&asset.mesh_handle).unwrap();
-        let verts = get_vertex_positions(&mesh);
+        let verts = get_vertex_positions(mesh);
         let vertex_map = generate_vertex_map(&mh_verts, &verts);
         asset.vertex_map = vertex_map;
     }
-    for (name, asset) in registry.equipment.iter_mut() {
-        //println!("Importing equipment: {name}");
-        let mh_verts = parse_obj_vertices(&asset.obj_file);
+    for (_name, asset) in registry.equipment.iter_mut() {
+        let mh_verts = read_obj_vertices(&global_config.source, &asset.obj_file);
         let mesh = meshes.get(&asset.mesh_handle).unwrap();
-        let verts = get_vertex_positions(&mesh);
+        let verts = get_vertex_positions(mesh);
         let vertex_map = generate_vertex_map(&mh_verts, &verts);
         asset.vertex_map = vertex_map;
     }
+    for (_name, asset) in registry.proxies.iter_mut() {
+        let mh_verts = read_obj_vertices(&global_config.source, &asset.obj_file);
+        let mesh = meshes.get(&asset.mesh_handle).unwrap();
+        let verts = get_vertex_positions(mesh);
+        asset.vertex_map = generate_vertex_map(&mh_verts, &verts);
+    }
     loading_state.0.insert(LoadingPhase::GenerateAssetVertexMap, true);
  }
 
 /*------------+
  |  Funtions  |
  +------------*/
- fn parse_human_asset(path: PathBuf, world: &mut World) -> HumanMeshAsset {
+ #[cfg(feature = "clothing")]
+ fn parse_human_asset(path: PathBuf, source: &Arc<dyn HumanAssetSource>, world: &mut World) -> HumanMeshAsset {
+    let err_msg = format!("Couldn't open target file {}", path.to_string_lossy());
+    let bytes = source.read(&path).expect(&err_msg);
+    let mut asset = parse_mhclo(bytes.as_slice(), &path);
+    let asset_server = world.get_resource::<AssetServer>().unwrap();
+    asset.mesh_handle = asset_server.load(source.asset_path(&asset.obj_file));
+    asset
+ }
+
+// Textures found next to the asset's obj file
+#[cfg(feature = "clothing")]
+pub(crate) fn asset_material(asset: &HumanMeshAsset, textures: &HumanAssetTextures) -> StandardMaterial {
+    let mut material = StandardMaterial::default();
+    if let Some(albedos) = textures.albedo_maps.get(&asset.name) {
+        if !albedos.is_empty() { material.base_color_texture = Some(albedos[0].clone()); }
+    }
+    if let Some(normal) = textures.normal_map.get(&asset.name) {
+        material.normal_map_texture = Some(normal.clone());
+    }
+    if let Some(ao) = textures.ao_map.get(&asset.name) {
+        material.occlusion_texture = Some(ao.clone());
+    }
+    material
+}
+
+#[cfg(feature = "clothing")]
+fn read_obj_vertices(source: &Arc<dyn HumanAssetSource>, path: &Path) -> Vec<Vec3> {
+    let err_msg = format!("Couldn't open obj file {}", path.to_string_lossy());
+    let bytes = source.read(path).expect(&err_msg);
+    parse_obj_vertices(bytes.as_slice())
+}
+
+// Mesh handle is left for the caller to load
+#[cfg(feature = "clothing")]
+pub(crate) fn parse_mhclo<R: BufRead>(reader: R, path: &Path) -> HumanMeshAsset {
     let mut tags = Vec::<String>::new();
-    let mut z_depth = 0 as i8;
+    let mut z_depth = 0_i8;
     let mut delete_verts = HashSet::<u16>::new();
     let mut helper_map = Vec::<HelperMap>::new();
     let mut x_scale = ScaleData::default();
@@ -252,13 +340,9 @@ impl FromWorld for HumanAssetRegistry {
     let mut name: String = "".to_string();
     
     let mut obj_file = PathBuf::default();
-    let asset_server = world.get_resource::<AssetServer>().unwrap();
     let mut section = FileSection::Header;
 
-
-    let err_msg = format!("Couldn't open target file {}", path.to_string_lossy());
-    let file = File::open(&path).expect(&err_msg);
-    for line_result in BufReader::new(file).lines() {
+    for line_result in reader.lines() {
 
         let Ok(line) = line_result else { break };
         if line.starts_with("#") { continue; }
@@ -266,12 +350,12 @@ impl FromWorld for HumanAssetRegistry {
         if line.starts_with("verts 0") { section = FileSection::Vertices; continue; }
         if line.starts_with("delete_verts") { section = FileSection::DeleteVertices; continue; }
 
-        let line_vec: Vec<&str> = line.trim().split_whitespace().collect();
+        let line_vec: Vec<&str> = line.split_whitespace().collect();
 
         if section == FileSection::Header {
             if *line_vec.first().unwrap() == "obj_file" {
                 let filename = line_vec.last().unwrap();
-                obj_file = path.clone();
+                obj_file = path.to_path_buf();
                 obj_file.set_file_name(filename);
             } else if *line_vec.first().unwrap() == "x_scale" {
                 x_scale.min = line_vec[1].parse().unwrap();
@@ -316,9 +400,9 @@ impl FromWorld for HumanAssetRegistry {
                 );
                 helper_map.push(HelperMap{
                     triangle: Some(Triangle {
-                        helper_verts: helper_verts,
-                        helper_weights: helper_weights,
-                        helper_offset: helper_offset,
+                        helper_verts,
+                        helper_weights,
+                        helper_offset,
                     }),
                     single_vertex: None
                 });
@@ -354,34 +438,32 @@ impl FromWorld for HumanAssetRegistry {
         }
     }
 
-    let mesh_handle = asset_server.load(obj_file.clone());
     let vertex_map = HashMap::<u16, Vec<u16>>::new();
 
     HumanMeshAsset {
-        name: name,
-        obj_file: obj_file,
-        tags: tags,
-        z_depth: z_depth,
+        name,
+        obj_file,
+        tags,
+        z_depth,
         helper_maps: helper_map,
-        delete_verts: delete_verts,
+        delete_verts,
         scale_data: [x_scale, y_scale, z_scale],
-        mesh_handle: mesh_handle,
-        vertex_map: vertex_map,
+        mesh_handle: Handle::default(),
+        vertex_map,
         slots: vec![],
     }
 }
 
 pub(crate) fn delete_mesh_verts(
-    meshes: &mut ResMut<Assets<Mesh>>,
-    base_mesh: &Res<BaseMesh>,
+    mesh: &Mesh,
+    base_vertex_map: &HashMap<u16, Vec<u16>>,
     delete_verts: HashSet<u16>,
 ) -> Mesh {
-    let mesh = meshes.get(&base_mesh.mesh_handle).unwrap().clone();
-    let inv_vertex_map = generate_inverse_vertex_map(&base_mesh.vertex_map);
+    let inv_vertex_map = generate_inverse_vertex_map(base_vertex_map);
 
-    let vertices = get_vertex_positions(&mesh);
-    let normals = get_vertex_normals(&mesh);
-    let uv = get_uv_coords(&mesh);
+    let vertices = get_vertex_positions(mesh);
+    let normals = get_vertex_normals(mesh);
+    let uv = get_uv_coords(mesh);
     let indices = mesh.indices().expect("FAILED TO GET MESH FACES");
 
     // Set up new storage for the new mesh
@@ -407,7 +489,7 @@ pub(crate) fn delete_mesh_verts(
     // Find new face indices
     for face in indices_vec.chunks(3) {
         // Check if all vertices still exist in new mesh verts
-        if !face.iter().all(|&i| indices_map.contains_key(&(i as u16))) { continue; }
+        if !face.iter().all(|&i| indices_map.contains_key(&i)) { continue; }
         // Map face to new vertex indices
         new_indices.extend_from_slice(face);
     }
@@ -0,0 +1,399 @@
+use bevy::{
+    prelude::*,
+    render::mesh::skinning::SkinnedMesh,
+    tasks::{
+        block_on,
+        futures_lite::future,
+        AsyncComputeTaskPool,
+        Task,
+    },
+};
+use std::{
+    collections::{ HashMap, HashSet },
+    sync::Arc,
+};
+#[cfg(feature = "rig")]
+use crate::rigs::{ set_asset_rig_arrays, set_basemesh_rig_arrays, BoneWeights };
+use crate::{
+    assets::delete_mesh_verts,
+    cache::CachedHuman,
+    skinning::HumanMaterial,
+    morphs::{ bake_asset_morphs, bake_body_morphs },
+    decimate_mesh,
+    generate_vertex_map,
+    get_vertex_positions,
+    HumanLod,
+    HumanMeshAsset,
+    HumanMeshCache,
+    HumanPart,
+    LodSettings,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Everything a bake task needs, owned so it can leave the main thread
+pub(crate) struct BakeInput {
+    pub(crate) helpers: Arc<Vec<Vec3>>,
+    // None for RigType::None, the meshes stay unskinned
+    #[cfg(feature = "rig")]
+    pub(crate) weights: Option<Arc<BoneWeights>>,
+    #[cfg(feature = "rig")]
+    pub(crate) influences: usize,
+    pub(crate) sorted_bones: Arc<Vec<String>>,
+    // (unmorphed mesh, asset) for body parts then equipment
+    pub(crate) assets: Vec<(Mesh, HumanMeshAsset)>,
+    pub(crate) body: Mesh,
+    pub(crate) body_vertices: Vec<Vec3>,
+    pub(crate) body_vertex_map: HashMap<u16, Vec<u16>>,
+    pub(crate) delete_verts: HashSet<u16>,
+    pub(crate) proxy: Option<(Mesh, HumanMeshAsset)>,
+    pub(crate) lod: Option<LodSettings>,
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+// Present while the human's meshes are baked off the main thread.
+// The skeleton already exists, the meshes are spawned once the task is done.
+#[derive(Component)]
+pub struct HumanBaking {
+    task: Task<Vec<Vec<Mesh>>>,
+    key: u64,
+    helpers: Vec<Vec3>,
+    skinned_mesh: Option<SkinnedMesh>,
+    sorted_bones: Vec<String>,
+    // Matches the mesh order of each level: body parts, equipment, then the body
+    materials: Vec<HumanMaterial>,
+    lod: Option<LodSettings>,
+}
+
+impl HumanBaking {
+    pub(crate) fn new(
+        input: BakeInput,
+        key: u64,
+        skinned_mesh: Option<SkinnedMesh>,
+        materials: Vec<HumanMaterial>,
+    ) -> Self {
+        HumanBaking {
+            key,
+            helpers: input.helpers.to_vec(),
+            skinned_mesh,
+            sorted_bones: input.sorted_bones.to_vec(),
+            materials,
+            lod: input.lod.clone(),
+            task: spawn_bake_task(input),
+        }
+    }
+}
+
+/*----------+
+ |  Events  |
+ +----------*/
+// Sent once a human's meshes are spawned
+#[derive(Event)]
+pub struct HumanReady(pub Entity);
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+pub(crate) fn finish_human_baking(
+    mut humans: Query<(Entity, &mut HumanBaking)>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut cache: ResMut<HumanMeshCache>,
+    mut ready: EventWriter<HumanReady>,
+) {
+    for (human, mut baking) in humans.iter_mut() {
+        let Some(baked) = block_on(future::poll_once(&mut baking.task)) else { continue };
+
+        // Another human with the same config may have finished first
+        let levels = match cache.get(baking.key).map(|cached| cached.levels.clone()) {
+            Some(levels) => levels,
+            None => {
+                let levels: Vec<Vec<(Handle<Mesh>, HumanMaterial)>> = baked.into_iter()
+                    .map(|level| level.into_iter()
+                        .map(|mesh| meshes.add(mesh))
+                        .zip(baking.materials.iter().cloned())
+                        .collect())
+                    .collect();
+                cache.insert(baking.key, CachedHuman {
+                    helpers: baking.helpers.clone(),
+                    #[cfg(feature = "rig")]
+                    inverse_bindposes: baking.skinned_mesh.as_ref().map(|s| s.inverse_bindposes.clone()),
+                    levels: levels.clone(),
+                });
+                levels
+            }
+        };
+        spawn_human_meshes(
+            &mut commands,
+            human,
+            levels,
+            baking.skinned_mesh.as_ref(),
+            &baking.sorted_bones,
+            baking.lod.as_ref(),
+        );
+        commands.entity(human).remove::<HumanBaking>();
+        ready.send(HumanReady(human));
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+// Meshes are children of the human so despawning it cleans everything up.
+// Only the first level starts visible.
+pub(crate) fn spawn_human_meshes(
+    commands: &mut Commands,
+    human: Entity,
+    levels: Vec<Vec<(Handle<Mesh>, HumanMaterial)>>,
+    skinned_mesh: Option<&SkinnedMesh>,
+    sorted_bones: &[String],
+    lod: Option<&LodSettings>,
+) {
+    let mut mesh_entities = Vec::<Entity>::new();
+    let mut lod_entities: [Vec<Entity>; 3] = default();
+    for (i, level) in levels.into_iter().enumerate() {
+        for (mesh, material) in level {
+            let visibility = if i == 0 { Visibility::Inherited } else { Visibility::Hidden };
+            let mut entity = match material {
+                HumanMaterial::Standard(material) => commands.spawn(PbrBundle {
+                    mesh,
+                    material,
+                    visibility,
+                    ..default()
+                }),
+                HumanMaterial::Skinned(material) => commands.spawn(MaterialMeshBundle {
+                    mesh,
+                    material,
+                    visibility,
+                    ..default()
+                }),
+            };
+            entity.insert(HumanPart(human));
+            if let Some(skinned_mesh) = skinned_mesh { entity.insert(skinned_mesh.clone()); }
+            let entity = entity.id();
+            lod_entities[i].push(entity);
+            mesh_entities.push(entity);
+        }
+    }
+    if let Some(settings) = lod {
+        let bones: Vec<(String, Entity)> = sorted_bones.iter().cloned()
+            .zip(skinned_mesh.iter().flat_map(|s| s.joints.iter().copied()))
+            .collect();
+        commands.entity(human).insert(HumanLod::new(settings.clone(), lod_entities, &bones));
+    }
+    commands.entity(human).push_children(&mesh_entities);
+}
+
+// Every asset, the body and the proxy get their own task.
+// Each returns its meshes for every LOD level, which are joined per level.
+fn spawn_bake_task(input: BakeInput) -> Task<Vec<Vec<Mesh>>> {
+    let pool = AsyncComputeTaskPool::get();
+    #[cfg(feature = "rig")]
+    let influences = input.influences;
+    let asset_ratios: Vec<f32> = match input.lod.as_ref() {
+        Some(settings) => vec![settings.proxy_ratio, settings.decimation],
+        None => vec![],
+    };
+    let proxy_ratios: Vec<f32> = match input.lod.as_ref() {
+        Some(settings) => vec![1.0, settings.decimation],
+        None => vec![],
+    };
+
+    let mut asset_tasks = Vec::<Task<Vec<Mesh>>>::with_capacity(input.assets.len());
+    for (mesh, asset) in input.assets {
+        let helpers = input.helpers.clone();
+        #[cfg(feature = "rig")]
+        let (weights, sorted_bones) = (input.weights.clone(), input.sorted_bones.clone());
+        let ratios = asset_ratios.clone();
+        asset_tasks.push(pool.spawn(async move {
+            let mesh = bake_asset_morphs(&mesh, &helpers, &asset);
+            #[cfg(feature = "rig")]
+            let mesh = match weights {
+                Some(weights) => set_asset_rig_arrays(mesh, &weights, &asset.vertex_map, &asset.helper_maps, &sorted_bones, influences),
+                None => mesh,
+            };
+            with_decimated_levels(mesh, &ratios)
+        }));
+    }
+
+    // Lower body levels come from the proxy, or the base body without one
+    let mut body_tasks = Vec::<Task<Vec<Mesh>>>::with_capacity(2);
+    let body_ratios = if input.proxy.is_some() { vec![] } else { proxy_ratios.clone() };
+    {
+        let helpers = input.helpers.clone();
+        #[cfg(feature = "rig")]
+        let (weights, sorted_bones) = (input.weights.clone(), input.sorted_bones.clone());
+        let (body, body_vertices, body_vertex_map, delete_verts) =
+            (input.body, input.body_vertices, input.body_vertex_map, input.delete_verts);
+        body_tasks.push(pool.spawn(async move {
+            let mesh = delete_mesh_verts(&body, &body_vertex_map, delete_verts);
+            let vertex_map = generate_vertex_map(&body_vertices, &get_vertex_positions(&mesh));
+            let mesh = bake_body_morphs(&mesh, &vertex_map, &helpers);
+            #[cfg(feature = "rig")]
+            let mesh = match weights {
+                Some(weights) => set_basemesh_rig_arrays(mesh, &weights, &vertex_map, &sorted_bones, influences),
+                None => mesh,
+            };
+            with_decimated_levels(mesh, &body_ratios)
+        }));
+    }
+    if let Some((mesh, proxy)) = input.proxy {
+        let helpers = input.helpers.clone();
+        #[cfg(feature = "rig")]
+        let (weights, sorted_bones) = (input.weights.clone(), input.sorted_bones.clone());
+        body_tasks.push(pool.spawn(async move {
+            let mesh = bake_asset_morphs(&mesh, &helpers, &proxy);
+            #[cfg(feature = "rig")]
+            let mesh = match weights {
+                Some(weights) => set_asset_rig_arrays(mesh, &weights, &proxy.vertex_map, &proxy.helper_maps, &sorted_bones, influences),
+                None => mesh,
+            };
+            proxy_ratios.iter().map(|&ratio| decimate_mesh(&mesh, ratio)).collect()
+        }));
+    }
+
+    pool.spawn(async move {
+        let mut levels = vec![Vec::<Mesh>::new(); asset_ratios.len() + 1];
+        for task in asset_tasks {
+            for (level, mesh) in levels.iter_mut().zip(task.await) { level.push(mesh); }
+        }
+        let mut body = Vec::<Mesh>::with_capacity(levels.len());
+        for task in body_tasks { body.extend(task.await); }
+        for (level, mesh) in levels.iter_mut().zip(body) { level.push(mesh); }
+        levels
+    })
+}
+
+// The full mesh followed by one simplified copy per ratio
+fn with_decimated_levels(mesh: Mesh, ratios: &[f32]) -> Vec<Mesh> {
+    let mut levels: Vec<Mesh> = ratios.iter().map(|&ratio| decimate_mesh(&mesh, ratio)).collect();
+    levels.insert(0, mesh);
+    levels
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::system::RunSystemOnce,
+        render::{
+            mesh::{ Indices, PrimitiveTopology },
+            render_asset::RenderAssetUsages,
+        },
+        tasks::TaskPool,
+    };
+
+    const SIZE: usize = 8;
+
+    // Flat grid on XZ standing in for the base body, one helper per vertex
+    fn body() -> (Mesh, Vec<Vec3>) {
+        let mut vertices = Vec::<Vec3>::new();
+        let mut uvs = Vec::<[f32; 2]>::new();
+        for x in 0..SIZE {
+            for z in 0..SIZE {
+                vertices.push(Vec3::new(x as f32, 0.0, z as f32) * 0.1);
+                uvs.push([x as f32 / SIZE as f32, z as f32 / SIZE as f32]);
+            }
+        }
+        let mut indices = Vec::<u16>::new();
+        for x in 0..SIZE - 1 {
+            for z in 0..SIZE - 1 {
+                let i = (x * SIZE + z) as u16;
+                let s = SIZE as u16;
+                indices.extend([i, i + 1, i + s + 1, i, i + s + 1, i + s]);
+            }
+        }
+        let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices.clone())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, vec![Vec3::Y; vertices.len()])
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+            .with_inserted_indices(Indices::U16(indices));
+        (mesh, vertices)
+    }
+
+    fn input(lod: Option<LodSettings>) -> BakeInput {
+        let (body, body_vertices) = body();
+        BakeInput {
+            helpers: Arc::new(body_vertices.iter().map(|v| *v + Vec3::Y).collect()),
+            #[cfg(feature = "rig")]
+            weights: None,
+            #[cfg(feature = "rig")]
+            influences: 4,
+            sorted_bones: Arc::new(vec![]),
+            assets: vec![],
+            body_vertex_map: generate_vertex_map(&body_vertices, &body_vertices),
+            body,
+            body_vertices,
+            delete_verts: HashSet::new(),
+            proxy: None,
+            lod,
+        }
+    }
+
+    fn baking(lod: Option<LodSettings>) -> HumanBaking {
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        HumanBaking::new(input(lod), 7, None, vec![HumanMaterial::Standard(Handle::default())])
+    }
+
+    fn bake(world: &mut World) {
+        for _ in 0..1000 {
+            world.run_system_once(finish_human_baking);
+            if world.query::<&HumanBaking>().iter(world).next().is_none() { return; }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        panic!("BAKE TASKS NEVER FINISHED");
+    }
+
+    #[test]
+    fn humans_share_finished_bakes() {
+        let mut world = World::new();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<HumanMeshCache>();
+        world.init_resource::<Events<HumanReady>>();
+        let humans = [world.spawn(baking(None)).id(), world.spawn(baking(None)).id()];
+        bake(&mut world);
+
+        assert_eq!(world.resource::<HumanMeshCache>().len(), 1);
+        assert_eq!(world.resource::<Assets<Mesh>>().len(), 1);
+        assert_eq!(world.resource::<Events<HumanReady>>().len(), 2);
+        let mut handles = Vec::<Handle<Mesh>>::new();
+        for human in humans {
+            let children = world.get::<Children>(human).expect("NO MESHES SPAWNED");
+            assert_eq!(children.len(), 1);
+            let part = world.entity(children[0]);
+            assert_eq!(part.get::<HumanPart>().unwrap().0, human);
+            handles.push(part.get::<Handle<Mesh>>().unwrap().clone());
+        }
+        assert_eq!(handles[0], handles[1]);
+
+        // Morphed onto the helpers, which sit one unit up
+        let mesh = world.resource::<Assets<Mesh>>().get(&handles[0]).unwrap();
+        assert!(get_vertex_positions(mesh).iter().all(|v| (v.y - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn lod_levels_start_hidden() {
+        let mut world = World::new();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<HumanMeshCache>();
+        world.init_resource::<Events<HumanReady>>();
+        let human = world.spawn(baking(Some(LodSettings::default()))).id();
+        bake(&mut world);
+
+        assert!(world.get::<HumanLod>(human).is_some());
+        let children = world.get::<Children>(human).unwrap().to_vec();
+        let visibility: Vec<Visibility> = children.iter().map(|&c| *world.get::<Visibility>(c).unwrap()).collect();
+        assert_eq!(visibility, [Visibility::Inherited, Visibility::Hidden, Visibility::Hidden]);
+        let counts: Vec<usize> = children.iter()
+            .map(|&c| world.get::<Handle<Mesh>>(c).unwrap())
+            .map(|h| get_vertex_positions(world.resource::<Assets<Mesh>>().get(h).unwrap()).len())
+            .collect();
+        assert!(counts[1] == counts[0] && counts[2] < counts[0], "{:?}", counts);
+    }
+}
@@ -1,25 +1,28 @@
 use bevy::prelude::*;
+#[cfg(feature = "render")]
 use bevy::render::mesh::{
     Mesh, Indices,
 };
 use std::{
-    io::BufReader,
-    fs::File,
     collections::HashMap,
+    io::BufRead,
+    path::Path,
 };
+use crate::{
+    HumanOrigin,
+    HumentityGlobalConfig,
+};
+#[cfg(feature = "render")]
 use crate::{
     generate_inverse_vertex_map,
     generate_vertex_map,
     get_uv_coords,
     get_vertex_normals,
     get_vertex_positions,
-    parse_obj_vertices,
     LoadingState,
     LoadingPhase,
-    HumentityGlobalConfig,
 }; 
 use serde::Deserialize;
-use serde_json;
 
 pub(crate) const BODY_VERTICES: u16 = 13380u16;
 pub(crate) const BODY_SCALE: f32 = 0.1;
@@ -32,36 +35,44 @@ pub(crate) struct VertexGroups(pub(crate) HashMap<String, Vec<[usize; 2]>>);
 
 #[derive(Resource, Debug)]
 pub(crate) struct BaseMesh{
+    #[cfg(feature = "render")]
     pub(crate) mesh_handle: Handle<Mesh>,
     pub(crate) vertices: Vec<Vec3>,
+    #[cfg(feature = "render")]
     pub(crate) vertex_map: HashMap<u16, Vec<u16>>,
 }
 
+#[cfg(feature = "render")]
 #[derive(Resource, Debug)]
 pub(crate) struct HelperMeshHandle(Handle<Mesh>);
 
 impl FromWorld for BaseMesh {
     fn from_world(world: &mut World) -> Self {
         let config = world.get_resource::<HumentityGlobalConfig>().expect("NO CONFIG LOADED");
-        let path = config.core_assets_path.clone();
+        let source = config.source.clone();
         // Get mh vertices from base mesh and helper files
-        let mh_vertices = parse_obj_vertices(path.join("base.obj"));
-
-        // Load obj into asset server
-        let asset_server = world.resource::<AssetServer>();
-        let base_handle: Handle<Mesh> = asset_server.load(path.join("base.obj"));
+        let obj = source.read(Path::new("base.obj")).expect("FAILED TO OPEN base.obj");
+        let mh_vertices = parse_obj_vertices(obj.as_slice());
 
         let err_msg = "FAILED TO LOAD VERTEX GROUOPS";
-        let file = File::open(path.join("basemesh_vertex_groups.json")).expect(&err_msg);
-        let reader = BufReader::new(file);
-        let vg: VertexGroups = serde_json::from_reader(reader).unwrap();
-
+        let json = source.read(Path::new("basemesh_vertex_groups.json")).expect(err_msg);
+        let vg: VertexGroups = serde_json::from_slice(&json).unwrap();
         world.insert_resource(vg);
-        world.insert_resource(HelperMeshHandle(base_handle.clone()));
+
+        // Load obj into asset server
+        #[cfg(feature = "render")]
+        let base_handle: Handle<Mesh> = {
+            let asset_server = world.resource::<AssetServer>();
+            let handle = asset_server.load(source.asset_path(Path::new("base.obj")));
+            world.insert_resource(HelperMeshHandle(handle.clone()));
+            handle
+        };
 
         BaseMesh{
+            #[cfg(feature = "render")]
             mesh_handle: base_handle,
             vertices: mh_vertices,
+            #[cfg(feature = "render")]
             vertex_map: HashMap::<u16, Vec<u16>>::new(),
         }
 
@@ -72,6 +83,7 @@ impl FromWorld for BaseMesh {
  |  Systems  |
  +-----------*/
 // Remove helper vertices to generate body only mesh
+#[cfg(feature = "render")]
 pub(crate) fn create_body_mesh(
     mut base_mesh: ResMut<BaseMesh>,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -85,9 +97,9 @@ pub(crate) fn create_body_mesh(
 
     // Get mesh arrays
     let Some(raw_indices) = mesh.indices() else { panic!("FAILED TO LOAD MESH INDICES") };
-    let vtx_data = get_vertex_positions(&mesh);
-    let normal_data = get_vertex_normals(&mesh); 
-    let uv_data = get_uv_coords(&mesh);
+    let vtx_data = get_vertex_positions(mesh);
+    let normal_data = get_vertex_normals(mesh); 
+    let uv_data = get_uv_coords(mesh);
 
     let vertex_map = generate_vertex_map(&base_mesh.vertices, &vtx_data);
     
@@ -111,6 +123,7 @@ pub(crate) fn create_body_mesh(
 } 
 
 // Load body mesh to calculate vertex maps
+#[cfg(feature = "render")]
 pub(crate) fn create_body_vertex_map(
     mut base_mesh: ResMut<BaseMesh>,
     meshes: Res<Assets<Mesh>>,
@@ -119,7 +132,7 @@ pub(crate) fn create_body_vertex_map(
     if !*loading_state.0.get(&LoadingPhase::CreateBodyMesh).unwrap() { return; }
     if *loading_state.0.get(&LoadingPhase::GenerateBodyVertexMap).unwrap() { return; }
     let Some(body_mesh) = meshes.get(&base_mesh.mesh_handle) else { return };
-    let vertices = get_vertex_positions(&body_mesh);
+    let vertices = get_vertex_positions(body_mesh);
     let body_vertex_map = generate_vertex_map(&base_mesh.vertices, &vertices);
     base_mesh.vertex_map = body_vertex_map;
     loading_state.0.insert(LoadingPhase::GenerateBodyVertexMap, true);
@@ -128,6 +141,41 @@ pub(crate) fn create_body_vertex_map(
 /*---------------------+
  |  Utility Functions  |
  +---------------------*/
+// Moves the morphed helpers up or down so the origin's point is at y = 0
+pub(crate) fn align_to_origin(helpers: &mut [Vec3], vg: &VertexGroups, origin: HumanOrigin) {
+    let ground = match origin {
+        HumanOrigin::BaseMesh => return,
+        HumanOrigin::Feet => helpers[..BODY_VERTICES as usize].iter()
+            .map(|v| v.y)
+            .fold(f32::INFINITY, f32::min),
+        HumanOrigin::JointGround => {
+            let Some(ranges) = vg.0.get("joint-ground") else {
+                warn!("No joint-ground vertex group, keeping the base mesh origin");
+                return;
+            };
+            let heights: Vec<f32> = ranges.iter().flat_map(|r| r[0]..=r[1]).map(|i| helpers[i].y).collect();
+            heights.iter().sum::<f32>() / heights.len() as f32
+        }
+    };
+    helpers.iter_mut().for_each(|v| v.y -= ground);
+}
+
+pub(crate) fn parse_obj_vertices<R: BufRead>(reader: R) -> Vec<Vec3> {
+    let mut vertices = Vec::<Vec3>::new();
+    for line_result in reader.lines() {
+        let Ok(line) = line_result else { break };
+        if line.starts_with("v ") {
+            let coords: Vec<f32> = line.split_whitespace()
+                             .skip(1)
+                             .filter_map(|x| x.parse().ok())
+                             .collect();
+            vertices.push(Vec3::new(coords[0], coords[1], coords[2]));
+        }
+    }
+    vertices
+}
+
+#[cfg(feature = "render")]
 fn generate_mesh_without_helpers(
     original_mesh: &Mesh,
     vertex_map: &HashMap<u16, Vec<u16>>,
@@ -174,4 +222,56 @@ fn generate_mesh_without_helpers(
     .with_inserted_indices(Indices::U16(u16indices));
     let _ = body_mesh.generate_tangents();
     body_mesh
-}
\ No newline at end of file
+}
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Body vertices from y = 0.5 to 1.5, with a helper hanging below the feet
+    fn helpers() -> Vec<Vec3> {
+        let mut helpers: Vec<Vec3> = (0..BODY_VERTICES)
+            .map(|i| Vec3::new(0.0, 0.5 + i as f32 / BODY_VERTICES as f32, 0.0))
+            .collect();
+        helpers.push(Vec3::new(0.0, -2.0, 0.0));
+        helpers
+    }
+
+    fn ground_group() -> VertexGroups {
+        VertexGroups(HashMap::from([("joint-ground".to_string(), vec![[0, 1], [10, 10]])]))
+    }
+
+    #[test]
+    fn feet_stand_on_the_origin() {
+        let mut aligned = helpers();
+        align_to_origin(&mut aligned, &ground_group(), HumanOrigin::Feet);
+        let lowest = aligned[..BODY_VERTICES as usize].iter().map(|v| v.y).fold(f32::INFINITY, f32::min);
+        assert_eq!(lowest, 0.0);
+        // Helpers outside the body don't count, but still move along
+        assert_eq!(aligned[BODY_VERTICES as usize].y, -2.5);
+    }
+
+    #[test]
+    fn joint_ground_uses_the_group_mean() {
+        let original = helpers();
+        let mut aligned = original.clone();
+        align_to_origin(&mut aligned, &ground_group(), HumanOrigin::JointGround);
+        let mean = (original[0].y + original[1].y + original[10].y) / 3.0;
+        for (a, o) in aligned.iter().zip(original.iter()) {
+            assert!((a.y - (o.y - mean)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn base_mesh_origin_keeps_helpers() {
+        let original = helpers();
+        let mut aligned = original.clone();
+        align_to_origin(&mut aligned, &ground_group(), HumanOrigin::BaseMesh);
+        assert_eq!(aligned, original);
+        // Without the group there is nothing to align to
+        align_to_origin(&mut aligned, &VertexGroups(HashMap::new()), HumanOrigin::JointGround);
+        assert_eq!(aligned, original);
+    }
+}
@@ -0,0 +1,207 @@
+use bevy::{
+    prelude::*,
+    render::primitives::Aabb,
+};
+#[cfg(feature = "rig")]
+use std::collections::HashMap;
+use crate::basemesh::BODY_VERTICES;
+
+// Room for clothes and hair around the body, in meters
+const CLOTHING_MARGIN: f32 = 0.05;
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+// World space bounds of the posed human, updated every frame
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct HumanBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl HumanBounds {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+}
+
+// Rest pose extents the bounds are built from
+#[derive(Component, Clone, Debug)]
+pub(crate) struct HumanExtents {
+    // Joint and the distance to its farthest weighted vertex.  Empty for static humans.
+    bones: Vec<(Entity, f32)>,
+    // In the human's space
+    rest_min: Vec3,
+    rest_max: Vec3,
+}
+
+impl HumanExtents {
+    // Static humans never leave their rest pose
+    pub(crate) fn rest(helpers: &[Vec3]) -> Self {
+        let body = &helpers[..BODY_VERTICES as usize];
+        let min = body.iter().fold(Vec3::INFINITY, |min, v| min.min(*v));
+        let max = body.iter().fold(Vec3::NEG_INFINITY, |max, v| max.max(*v));
+        HumanExtents {
+            bones: vec![],
+            rest_min: min - Vec3::splat(CLOTHING_MARGIN),
+            rest_max: max + Vec3::splat(CLOTHING_MARGIN),
+        }
+    }
+
+    // Every vertex stays within its bones' spheres however the joints move
+    #[cfg(feature = "rig")]
+    pub(crate) fn skinned<'a>(
+        helpers: &[Vec3],
+        joints: impl Iterator<Item = (Entity, Vec3, Option<&'a HashMap<u16, f32>>)>,
+    ) -> Self {
+        let mut extents = HumanExtents::rest(helpers);
+        for (joint, head, weights) in joints {
+            let Some(weights) = weights else { continue };
+            let radius = weights.iter()
+                .filter(|(_, weight)| **weight > 0.0)
+                .map(|(&vertex, _)| helpers[vertex as usize].distance(head))
+                .fold(0.0, f32::max);
+            if radius > 0.0 { extents.bones.push((joint, radius + CLOTHING_MARGIN)); }
+        }
+        extents
+    }
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+// Runs after transform propagation so culling sees this frame's pose.  Every mesh of
+// the human gets the same box, in the human's space.
+pub(crate) fn update_human_bounds(
+    mut humans: Query<(Entity, &HumanExtents, &GlobalTransform, &Children, Option<&mut HumanBounds>)>,
+    joints: Query<&GlobalTransform, Without<HumanExtents>>,
+    mut parts: Query<Option<&mut Aabb>, With<Handle<Mesh>>>,
+    mut commands: Commands,
+) {
+    for (human, extents, human_transform, children, bounds) in humans.iter_mut() {
+        let to_local = human_transform.affine().inverse();
+        let (mut min, mut max) = (Vec3::INFINITY, Vec3::NEG_INFINITY);
+        for (joint, radius) in extents.bones.iter() {
+            let Ok(transform) = joints.get(*joint) else { continue };
+            let position = to_local.transform_point3(transform.translation());
+            min = min.min(position - Vec3::splat(*radius));
+            max = max.max(position + Vec3::splat(*radius));
+        }
+        if extents.bones.is_empty() || min.cmpgt(max).any() {
+            (min, max) = (extents.rest_min, extents.rest_max);
+        }
+
+        let aabb = Aabb::from_min_max(min, max);
+        for &child in children.iter() {
+            match parts.get_mut(child) {
+                Ok(Some(mut part)) => *part = aabb,
+                Ok(None) => { commands.entity(child).insert(aabb); }
+                Err(_) => {}
+            }
+        }
+
+        // Corners of the local box in world space
+        let (mut world_min, mut world_max) = (Vec3::INFINITY, Vec3::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let corner = human_transform.transform_point(corner);
+            world_min = world_min.min(corner);
+            world_max = world_max.max(corner);
+        }
+        let world = HumanBounds { min: world_min, max: world_max };
+        match bounds {
+            Some(mut bounds) => *bounds = world,
+            None => { commands.entity(human).insert(world); }
+        }
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    const HALF: usize = BODY_VERTICES as usize / 2;
+    const UPPER_HEAD: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    const LOWER_HEAD: Vec3 = Vec3::new(0.5, 1.0, 0.0);
+
+    // An arm along +X, the first half of the vertices on the upper bone and the rest on the lower
+    fn helpers() -> Vec<Vec3> {
+        (0..BODY_VERTICES as usize).map(|i| {
+            let t = (i % HALF) as f32 / HALF as f32 * 0.5;
+            if i < HALF { UPPER_HEAD + Vec3::X * t } else { LOWER_HEAD + Vec3::X * t + Vec3::Z * 0.05 }
+        }).collect()
+    }
+
+    // Bounds of a human placed in the world, with a mesh child to receive the Aabb
+    fn bounds(world: &mut World, extents: HumanExtents, human_transform: Transform) -> (HumanBounds, Aabb) {
+        let mesh = world.spawn(Handle::<Mesh>::default()).id();
+        let human = world.spawn((extents, GlobalTransform::from(human_transform))).push_children(&[mesh]).id();
+        world.run_system_once(update_human_bounds);
+        (*world.get::<HumanBounds>(human).unwrap(), *world.get::<Aabb>(mesh).unwrap())
+    }
+
+    fn contains(aabb: &Aabb, point: Vec3) -> bool {
+        let (min, max) = (Vec3::from(aabb.min()), Vec3::from(aabb.max()));
+        point.cmpge(min - 1e-5).all() && point.cmple(max + 1e-5).all()
+    }
+
+    #[test]
+    fn rest_bounds_hold_the_body() {
+        let helpers = helpers();
+        let mut world = World::new();
+        let (bounds, aabb) = bounds(&mut world, HumanExtents::rest(&helpers), Transform::IDENTITY);
+        assert!(helpers.iter().all(|&v| bounds.contains(v) && contains(&aabb, v)));
+        assert_eq!(bounds.min, UPPER_HEAD - Vec3::splat(CLOTHING_MARGIN));
+    }
+
+    #[cfg(feature = "rig")]
+    #[test]
+    fn skinned_bounds_follow_the_pose() {
+        let helpers = helpers();
+        let mut world = World::new();
+        let human_transform = Transform::from_xyz(3.0, 0.0, -2.0).with_rotation(Quat::from_rotation_y(0.8));
+
+        // Upper arm raised straight up, lower arm bent forward
+        let upper_rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let lower_rotation = upper_rotation * Quat::from_rotation_y(-1.2);
+        let upper = Transform::from_translation(UPPER_HEAD).with_rotation(upper_rotation);
+        let lower = Transform::from_translation(UPPER_HEAD + upper_rotation * (LOWER_HEAD - UPPER_HEAD))
+            .with_rotation(lower_rotation);
+        let upper_joint = world.spawn(GlobalTransform::from(human_transform * upper)).id();
+        let lower_joint = world.spawn(GlobalTransform::from(human_transform * lower)).id();
+
+        let weights: [HashMap<u16, f32>; 2] = [
+            (0..HALF as u16).map(|v| (v, 1.0)).collect(),
+            (HALF as u16..BODY_VERTICES).map(|v| (v, 1.0)).collect(),
+        ];
+        let joints = [(upper_joint, UPPER_HEAD, Some(&weights[0])), (lower_joint, LOWER_HEAD, Some(&weights[1]))];
+        let extents = HumanExtents::skinned(&helpers, joints.into_iter());
+        let (bounds, aabb) = bounds(&mut world, extents, human_transform);
+
+        for (i, &vertex) in helpers.iter().enumerate() {
+            let (pose, head) = if i < HALF { (upper, UPPER_HEAD) } else { (lower, LOWER_HEAD) };
+            let posed = pose.translation + pose.rotation * (vertex - head);
+            assert!(contains(&aabb, posed), "vertex {} at {} left the mesh bounds", i, posed);
+            let world_posed = human_transform.transform_point(posed);
+            assert!(bounds.contains(world_posed), "vertex {} at {} left {:?}", i, world_posed, bounds);
+        }
+        // The raised arm is taller than the rest pose
+        assert!(aabb.max().y > 1.4);
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+#[cfg(feature = "rig")]
+use bevy::render::mesh::skinning::SkinnedMeshInverseBindposes;
+use std::{
+    collections::HashMap,
+    hash::{ DefaultHasher, Hash, Hasher },
+};
+use crate::{
+    skinning::HumanMaterial,
+    HumanConfig,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+#[derive(Clone)]
+pub(crate) struct CachedHuman {
+    pub(crate) helpers: Vec<Vec3>,
+    // None for RigType::None
+    #[cfg(feature = "rig")]
+    pub(crate) inverse_bindposes: Option<Handle<SkinnedMeshInverseBindposes>>,
+    // (mesh, material) per LOD level.  Just the full level without LodSettings.
+    pub(crate) levels: Vec<Vec<(Handle<Mesh>, HumanMaterial)>>,
+}
+
+/*-------------+
+ |  Resources  |
+ +-------------*/
+// Humans with identical configs share mesh, material and bindpose handles.
+// Entries are dropped once no human uses them.
+#[derive(Resource)]
+pub struct HumanMeshCache {
+    entries: HashMap<u64, CachedHuman>,
+    pub enabled: bool,
+}
+
+impl Default for HumanMeshCache {
+    fn default() -> Self {
+        HumanMeshCache {
+            entries: HashMap::new(),
+            enabled: true,
+        }
+    }
+}
+
+impl HumanMeshCache {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<&CachedHuman> {
+        if !self.enabled { return None; }
+        self.entries.get(&key)
+    }
+
+    pub(crate) fn insert(&mut self, key: u64, human: CachedHuman) {
+        if self.enabled { self.entries.insert(key, human); }
+    }
+
+    // Hash of everything in the config that changes the baked meshes or materials
+    pub(crate) fn key(config: &HumanConfig) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut targets: Vec<(&String, u32)> = config.morph_targets.iter()
+            .filter(|(_, &v)| v != 0.0)
+            .map(|(k, v)| (k, v.to_bits()))
+            .collect();
+        targets.sort();
+        targets.hash(&mut hasher);
+        config.rig.hash(&mut hasher);
+        config.skin_albedo.hash(&mut hasher);
+        for assets in [&config.body_parts, &config.equipment] {
+            let mut assets: Vec<&String> = assets.iter().collect();
+            assets.sort();
+            assets.hash(&mut hasher);
+        }
+        for color in [config.eye_color, config.eyebrow_color, config.hair_color] {
+            color.to_linear().to_f32_array().map(f32::to_bits).hash(&mut hasher);
+        }
+        config.skinning.influences().hash(&mut hasher);
+        config.skinning.dual_quaternion.hash(&mut hasher);
+        config.origin.hash(&mut hasher);
+        if let Some(lod) = config.lod.as_ref() {
+            lod.proxy.hash(&mut hasher);
+            [lod.proxy_ratio, lod.decimation].map(f32::to_bits).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+// Every human spawns the full level body mesh, so the cache holding the last strong handle means it is unused
+pub(crate) fn prune_human_mesh_cache(mut cache: ResMut<HumanMeshCache>) {
+    let unused = |human: &CachedHuman| match human.levels.first().and_then(|level| level.last()) {
+        Some((Handle::Strong(handle), _)) => std::sync::Arc::strong_count(handle) <= 1,
+        _ => true,
+    };
+    if !cache.entries.values().any(unused) { return; }
+    cache.entries.retain(|_, human| !unused(human));
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ HumanOrigin, RagdollSettings, RigType };
+
+    fn config() -> HumanConfig {
+        HumanConfig {
+            morph_targets: HashMap::from([
+                ("head-fat".to_string(), 0.4),
+                ("l-hand-scale-incr".to_string(), 0.25),
+                ("nose-flaring-incr".to_string(), 0.7),
+            ]),
+            body_parts: vec!["eyes".to_string(), "eyebrow001".to_string()],
+            equipment: vec!["shirt".to_string(), "jeans".to_string()],
+            ..default()
+        }
+    }
+
+    #[test]
+    fn key_ignores_order_and_zero_targets() {
+        let mut shuffled = config();
+        // A new map has its own hasher seed, so it iterates in another order
+        let mut targets: Vec<(String, f32)> = shuffled.morph_targets.into_iter().collect();
+        targets.sort_by(|a, b| b.0.cmp(&a.0));
+        shuffled.morph_targets = targets.into_iter().collect();
+        shuffled.morph_targets.insert("belly-pregnant-incr".to_string(), 0.0);
+        shuffled.body_parts.reverse();
+        shuffled.equipment.reverse();
+        assert_eq!(HumanMeshCache::key(&config()), HumanMeshCache::key(&shuffled));
+    }
+
+    #[test]
+    fn key_ignores_settings_outside_the_meshes() {
+        let mut ragdoll = config();
+        ragdoll.ragdoll = Some(RagdollSettings::default());
+        assert_eq!(HumanMeshCache::key(&config()), HumanMeshCache::key(&ragdoll));
+    }
+
+    #[test]
+    fn key_follows_mesh_settings() {
+        let key = HumanMeshCache::key(&config());
+        let edits: [fn(&mut HumanConfig); 6] = [
+            |c| { c.morph_targets.insert("head-fat".to_string(), 0.41); },
+            |c| c.rig = RigType::GameEngine,
+            |c| { c.equipment.pop(); },
+            |c| c.hair_color = Color::WHITE,
+            |c| c.skinning.dual_quaternion = !c.skinning.dual_quaternion,
+            |c| c.origin = HumanOrigin::BaseMesh,
+        ];
+        for (i, edit) in edits.iter().enumerate() {
+            let mut edited = config();
+            edit(&mut edited);
+            assert_ne!(key, HumanMeshCache::key(&edited), "edit {} kept the key", i);
+        }
+    }
+
+    #[test]
+    fn disabled_cache_stays_empty() {
+        let mut cache = HumanMeshCache { enabled: false, ..default() };
+        let human = CachedHuman {
+            helpers: vec![],
+            #[cfg(feature = "rig")]
+            inverse_bindposes: None,
+            levels: vec![],
+        };
+        cache.insert(1, human.clone());
+        assert!(cache.is_empty());
+        cache.enabled = true;
+        cache.insert(1, human);
+        assert!(cache.get(1).is_some());
+        cache.enabled = false;
+        assert!(cache.get(1).is_none());
+    }
+}
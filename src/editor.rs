@@ -0,0 +1,490 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+};
+use crate::{
+    morphs::{ macro_targets, MacroSliders, MacroValues, MorphSliders, SliderSide },
+    HumanAssetRegistry,
+    HumanCommandsExt,
+    HumanConfig,
+    HumanPreset,
+    HumentityState,
+    MorphTargets,
+};
+
+const ROWS_PER_PAGE: usize = 12;
+const STEP: f32 = 0.1;
+// Seconds without edits before the preview is rebaked
+const REBAKE_DELAY: f32 = 0.25;
+const PANEL_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.85);
+const BUTTON_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+const BUTTON_HOVER_COLOR: Color = Color::srgb(0.35, 0.35, 0.35);
+
+/*----------+
+ |  Plugin  |
+ +----------*/
+// Character creator UI editing a preview human.  Add alongside Humentity.
+pub struct HumentityEditorPlugin {
+    // Starting point.  Its morph targets are kept as details on top of the macro sliders.
+    pub base: HumanConfig,
+    pub preview_transform: Transform,
+    // The Export button writes a HumanPreset here
+    pub export_path: PathBuf,
+}
+
+impl Default for HumentityEditorPlugin {
+    fn default() -> Self {
+        HumentityEditorPlugin {
+            base: HumanConfig::default(),
+            preview_transform: Transform::IDENTITY,
+            export_path: PathBuf::from("human_preset.json"),
+        }
+    }
+}
+
+impl Plugin for HumentityEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<HumanConfigExported>();
+        app.insert_resource(EditorSettings {
+            base: self.base.clone(),
+            preview_transform: self.preview_transform,
+            export_path: self.export_path.clone(),
+        });
+        app.add_systems(OnEnter(HumentityState::Ready), setup_editor);
+        app.add_systems(Update, (
+            editor_buttons,
+            rebuild_editor_ui,
+            update_preview,
+        ).chain().run_if(in_state(HumentityState::Ready)));
+    }
+}
+
+/*----------+
+ |  Events  |
+ +----------*/
+// Sent by the Export button
+#[derive(Event)]
+pub struct HumanConfigExported(pub HumanConfig);
+
+/*---------+
+ |  Types  |
+ +---------*/
+#[derive(Clone, PartialEq)]
+enum EditorPage {
+    Macros,
+    Category { name: String, start: usize, count: usize },
+    Appearance,
+    Equipment { start: usize },
+}
+
+/*-------------+
+ |  Resources  |
+ +-------------*/
+#[derive(Resource)]
+struct EditorSettings {
+    base: HumanConfig,
+    preview_transform: Transform,
+    export_path: PathBuf,
+}
+
+#[derive(Resource)]
+struct EditorState {
+    pages: Vec<EditorPage>,
+    page: usize,
+    macros: MacroValues,
+    // Target values from the target.json sliders
+    details: HashMap<String, f32>,
+    skin: String,
+    // Slot -> body part
+    body_parts: HashMap<String, String>,
+    equipment: Vec<String>,
+    // (slot, name), sorted by slot.  Equipment without a slot is listed under Other.
+    equipment_list: Vec<(String, String)>,
+    preview: Option<Entity>,
+    // Time of the last edit not yet baked into the preview
+    edited: Option<f32>,
+    rebuild_ui: bool,
+}
+
+/*----------------+
+ |  System Params  |
+ +----------------*/
+// Everything EditorState needs to become a HumanConfig
+#[derive(SystemParam)]
+struct EditorConfigData<'w> {
+    settings: Res<'w, EditorSettings>,
+    macro_sliders: Res<'w, MacroSliders>,
+    targets: Res<'w, MorphTargets>,
+}
+
+impl EditorConfigData<'_> {
+    fn config(&self, state: &EditorState) -> HumanConfig {
+        let mut morph_targets = macro_targets(&state.macros, &self.macro_sliders, &self.targets);
+        morph_targets.extend(state.details.iter().map(|(k, v)| (k.clone(), *v)));
+        let mut body_parts: Vec<String> = state.body_parts.values().cloned().collect();
+        body_parts.sort();
+        HumanConfig {
+            morph_targets,
+            skin_albedo: state.skin.clone(),
+            body_parts,
+            equipment: state.equipment.clone(),
+            ..self.settings.base.clone()
+        }
+    }
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+#[derive(Component)]
+struct EditorRoot;
+
+#[derive(Component, Clone)]
+enum EditorAction {
+    Page(i32),
+    Symmetry,
+    Export,
+    Macro(String, f32),
+    Race(String, f32),
+    Slider(String, SliderSide, f32),
+    Skin(i32),
+    BodyPart(String, i32),
+    Equipment(String),
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+fn setup_editor(
+    mut commands: Commands,
+    settings: Res<EditorSettings>,
+    sliders: Res<MorphSliders>,
+    registry: Res<HumanAssetRegistry>,
+) {
+    let mut pages = vec![EditorPage::Macros];
+    for category in sliders.categories() {
+        let count = sliders.sliders(category).len();
+        for start in (0..count).step_by(ROWS_PER_PAGE) {
+            pages.push(EditorPage::Category { name: category.to_string(), start, count });
+        }
+    }
+    pages.push(EditorPage::Appearance);
+
+    let mut equipment_list = Vec::<(String, String)>::new();
+    for (slot, names) in registry.slot_equipment.iter() {
+        equipment_list.extend(names.iter().map(|n| (slot.clone(), n.clone())));
+    }
+    for (name, eq) in registry.equipment.iter() {
+        if eq.slots.is_empty() { equipment_list.push(("Other".to_string(), name.clone())); }
+    }
+    equipment_list.sort();
+    for start in (0..equipment_list.len()).step_by(ROWS_PER_PAGE) {
+        pages.push(EditorPage::Equipment { start });
+    }
+
+    let mut body_parts = HashMap::<String, String>::new();
+    for bp in settings.base.body_parts.iter() {
+        let Some(asset) = registry.body_parts.get(bp) else { continue };
+        if let Some(slot) = asset.slots.first() { body_parts.insert(slot.clone(), bp.clone()); }
+    }
+    let skin = if settings.base.skin_albedo.is_empty() {
+        registry.skins.first().cloned().unwrap_or_default()
+    } else {
+        settings.base.skin_albedo.clone()
+    };
+
+    commands.insert_resource(EditorState {
+        pages,
+        page: 0,
+        macros: MacroValues::default(),
+        details: settings.base.morph_targets.clone(),
+        skin,
+        body_parts,
+        equipment: settings.base.equipment.clone(),
+        equipment_list,
+        preview: None,
+        edited: Some(0.0),
+        rebuild_ui: true,
+    });
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Px(420.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        },
+        EditorRoot,
+    ));
+}
+
+fn editor_buttons(
+    mut interactions: Query<(&Interaction, &EditorAction, &mut BackgroundColor), Changed<Interaction>>,
+    mut state: ResMut<EditorState>,
+    mut sliders: ResMut<MorphSliders>,
+    mut exported: EventWriter<HumanConfigExported>,
+    data: EditorConfigData,
+    registry: Res<HumanAssetRegistry>,
+    time: Res<Time>,
+) {
+    for (interaction, action, mut color) in interactions.iter_mut() {
+        match interaction {
+            Interaction::Hovered => { *color = BUTTON_HOVER_COLOR.into(); continue; }
+            Interaction::None => { *color = BUTTON_COLOR.into(); continue; }
+            Interaction::Pressed => {}
+        }
+        let state = state.as_mut();
+        let mut edited = true;
+        match action {
+            EditorAction::Page(step) => {
+                let count = state.pages.len() as i32;
+                state.page = (state.page as i32 + step).rem_euclid(count) as usize;
+                edited = false;
+            }
+            EditorAction::Symmetry => {
+                sliders.symmetry = !sliders.symmetry;
+                if sliders.symmetry { sliders.mirror(&mut state.details, SliderSide::Left); }
+            }
+            EditorAction::Export => {
+                let config = data.config(state);
+                let export_path = &data.settings.export_path;
+                match HumanPreset::from(&config).save(export_path) {
+                    Ok(()) => info!("Exported human preset to {:?}", export_path),
+                    Err(e) => warn!("Failed to export human preset to {:?}: {}", export_path, e),
+                }
+                exported.send(HumanConfigExported(config));
+                edited = false;
+            }
+            EditorAction::Macro(name, step) => {
+                let value = state.macros.macros.entry(name.clone()).or_insert(0.5);
+                *value = (*value + step).clamp(0.0, 1.0);
+            }
+            EditorAction::Race(race, step) => {
+                let value = (state.macros.races.get(race).copied().unwrap_or(0.0) + step).clamp(0.0, 1.0);
+                set_race(&mut state.macros.races, race, value);
+            }
+            EditorAction::Slider(name, side, step) => {
+                let Some(slider) = sliders.slider(name) else { continue };
+                let min = if slider.is_signed() { -1.0 } else { 0.0 };
+                let value = (slider.get(&state.details, *side) + step).clamp(min, 1.0);
+                sliders.set(&mut state.details, name, value, *side);
+            }
+            EditorAction::Skin(step) => {
+                let skins = &registry.skins;
+                if skins.is_empty() { continue; }
+                let current = skins.iter().position(|s| *s == state.skin).unwrap_or(0) as i32;
+                let next = (current + step).rem_euclid(skins.len() as i32) as usize;
+                state.skin = skins[next].clone();
+            }
+            EditorAction::BodyPart(slot, step) => {
+                // None is the first option
+                let mut options: Vec<Option<String>> = vec![None];
+                let mut parts = registry.slot_body_parts.get(slot).cloned().unwrap_or_default();
+                parts.sort();
+                options.extend(parts.into_iter().map(Some));
+                let current = options.iter().position(|o| o.as_ref() == state.body_parts.get(slot)).unwrap_or(0) as i32;
+                let next = (current + step).rem_euclid(options.len() as i32) as usize;
+                match &options[next] {
+                    Some(part) => { state.body_parts.insert(slot.clone(), part.clone()); }
+                    None => { state.body_parts.remove(slot); }
+                }
+            }
+            EditorAction::Equipment(name) => {
+                if let Some(i) = state.equipment.iter().position(|e| e == name) {
+                    state.equipment.remove(i);
+                } else {
+                    state.equipment.push(name.clone());
+                }
+            }
+        }
+        if edited { state.edited = Some(time.elapsed_seconds()); }
+        state.rebuild_ui = true;
+    }
+}
+
+fn update_preview(
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    data: EditorConfigData,
+    time: Res<Time>,
+) {
+    let Some(edited) = state.edited else { return };
+    if time.elapsed_seconds() - edited < REBAKE_DELAY { return; }
+    if let Some(preview) = state.preview {
+        commands.entity(preview).despawn_recursive();
+    }
+    let config = data.config(&state);
+    state.preview = Some(commands.spawn_human(config).insert(data.settings.preview_transform).id());
+    state.edited = None;
+}
+
+fn rebuild_editor_ui(
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    roots: Query<Entity, With<EditorRoot>>,
+    sliders: Res<MorphSliders>,
+    registry: Res<HumanAssetRegistry>,
+    macro_sliders: Res<MacroSliders>,
+) {
+    if !state.rebuild_ui { return; }
+    state.rebuild_ui = false;
+    let Ok(root) = roots.get_single() else { return };
+    commands.entity(root).despawn_descendants();
+
+    let page = state.pages[state.page].clone();
+    let title = match &page {
+        EditorPage::Macros => "Macros".to_string(),
+        EditorPage::Category { name, start, count } if *count > ROWS_PER_PAGE => {
+            format!("{} ({}/{})", name, start / ROWS_PER_PAGE + 1, count.div_ceil(ROWS_PER_PAGE))
+        }
+        EditorPage::Category { name, .. } => name.clone(),
+        EditorPage::Appearance => "Appearance".to_string(),
+        EditorPage::Equipment { .. } => "Equipment".to_string(),
+    };
+
+    commands.entity(root).with_children(|root| {
+        row(root, |row| {
+            button(row, "<", EditorAction::Page(-1));
+            label(row, &title, 300.0);
+            button(row, ">", EditorAction::Page(1));
+        });
+
+        match &page {
+            EditorPage::Macros => {
+                for (name, text) in macro_sliders.sliders() {
+                    let value = state.macros.macros.get(name).copied().unwrap_or(0.5);
+                    let text = if text.is_empty() { name } else { text };
+                    value_row(root, text, value, EditorAction::Macro(name.to_string(), -STEP), EditorAction::Macro(name.to_string(), STEP));
+                }
+                let mut races: Vec<(&String, &f32)> = state.macros.races.iter().collect();
+                races.sort_by(|a, b| a.0.cmp(b.0));
+                for (race, &value) in races {
+                    value_row(root, race, value, EditorAction::Race(race.clone(), -STEP), EditorAction::Race(race.clone(), STEP));
+                }
+            }
+            EditorPage::Category { name, start, .. } => {
+                for slider in sliders.sliders(name).into_iter().skip(*start).take(ROWS_PER_PAGE) {
+                    let sides = if sliders.symmetry || !slider.has_left_and_right() {
+                        vec![(SliderSide::Both, "")]
+                    } else {
+                        vec![(SliderSide::Left, " L"), (SliderSide::Right, " R")]
+                    };
+                    for (side, suffix) in sides {
+                        let name = slider.name().to_string();
+                        value_row(
+                            root,
+                            &(name.clone() + suffix),
+                            slider.get(&state.details, side),
+                            EditorAction::Slider(name.clone(), side, -STEP),
+                            EditorAction::Slider(name, side, STEP),
+                        );
+                    }
+                }
+            }
+            EditorPage::Appearance => {
+                cycle_row(root, "Skin", &state.skin, EditorAction::Skin(-1), EditorAction::Skin(1));
+                let mut slots: Vec<&String> = registry.slot_body_parts.keys().collect();
+                slots.sort();
+                for slot in slots {
+                    let part = state.body_parts.get(slot).map(|p| p.as_str()).unwrap_or("none");
+                    cycle_row(root, slot, part, EditorAction::BodyPart(slot.clone(), -1), EditorAction::BodyPart(slot.clone(), 1));
+                }
+            }
+            EditorPage::Equipment { start } => {
+                for (slot, name) in state.equipment_list.iter().skip(*start).take(ROWS_PER_PAGE) {
+                    let worn = state.equipment.contains(name);
+                    row(root, |row| {
+                        label(row, &format!("{}: {}", slot, name), 300.0);
+                        button(row, if worn { "on" } else { "off" }, EditorAction::Equipment(name.clone()));
+                    });
+                }
+            }
+        }
+
+        row(root, |row| {
+            button(row, if sliders.symmetry { "Symmetry: on" } else { "Symmetry: off" }, EditorAction::Symmetry);
+            button(row, "Export", EditorAction::Export);
+        });
+    });
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+// Sets one race weight and scales the others so they still sum to 1
+fn set_race(races: &mut HashMap<String, f32>, race: &str, value: f32) {
+    let others: f32 = races.iter().filter(|(r, _)| *r != race).map(|(_, w)| w).sum();
+    let count = races.len().saturating_sub(1) as f32;
+    for (r, w) in races.iter_mut() {
+        if r == race {
+            *w = value;
+        } else if others > 0.0 {
+            *w *= (1.0 - value) / others;
+        } else if count > 0.0 {
+            *w = (1.0 - value) / count;
+        }
+    }
+}
+
+fn row(parent: &mut ChildBuilder, children: impl FnOnce(&mut ChildBuilder)) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            margin: UiRect::vertical(Val::Px(2.0)),
+            ..default()
+        },
+        ..default()
+    }).with_children(children);
+}
+
+fn label(parent: &mut ChildBuilder, text: &str, width: f32) {
+    parent.spawn(TextBundle::from_section(text, TextStyle { font_size: 16.0, ..default() })
+        .with_style(Style { width: Val::Px(width), ..default() }));
+}
+
+fn button(parent: &mut ChildBuilder, text: &str, action: EditorAction) {
+    parent.spawn((
+        ButtonBundle {
+            style: Style {
+                padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                margin: UiRect::horizontal(Val::Px(2.0)),
+                ..default()
+            },
+            background_color: BUTTON_COLOR.into(),
+            ..default()
+        },
+        action,
+    )).with_children(|button| {
+        button.spawn(TextBundle::from_section(text, TextStyle { font_size: 16.0, ..default() }));
+    });
+}
+
+fn value_row(parent: &mut ChildBuilder, text: &str, value: f32, minus: EditorAction, plus: EditorAction) {
+    row(parent, |row| {
+        label(row, text, 220.0);
+        button(row, "-", minus);
+        label(row, &format!("{:.2}", value), 60.0);
+        button(row, "+", plus);
+    });
+}
+
+fn cycle_row(parent: &mut ChildBuilder, text: &str, value: &str, prev: EditorAction, next: EditorAction) {
+    row(parent, |row| {
+        label(row, text, 120.0);
+        button(row, "<", prev);
+        label(row, value, 220.0);
+        button(row, ">", next);
+    });
+}
@@ -0,0 +1,345 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng,
+};
+use std::collections::HashMap;
+#[cfg(feature = "clothing")]
+use crate::HumanAssetRegistry;
+use crate::{
+    morphs::{ macro_targets, MacroSliders, MacroValues, MorphSliders, SliderSide },
+    HumanConfig,
+    MorphTargets,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Samples are clamped to [0, 1].  Uniform bounds can come in either order.
+#[derive(Clone, Copy, Debug)]
+pub enum Distribution {
+    Constant(f32),
+    Uniform { min: f32, max: f32 },
+    Normal { mean: f32, std_dev: f32 },
+}
+
+impl Distribution {
+    fn sample(&self, rng: &mut StdRng) -> f32 {
+        let value = match *self {
+            Distribution::Constant(v) => v,
+            Distribution::Uniform { min, max } => min + (max - min) * rng.gen::<f32>(),
+            Distribution::Normal { mean, std_dev } => {
+                // Box-Muller
+                let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+                let u2: f32 = rng.gen();
+                mean + std_dev * (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+            }
+        };
+        value.clamp(0.0, 1.0)
+    }
+}
+
+pub struct HumanGenerator {
+    rng: StdRng,
+    pub gender: Distribution,
+    pub age: Distribution,
+    pub muscle: Distribution,
+    pub weight: Distribution,
+    pub height: Distribution,
+    pub proportions: Distribution,
+    pub cupsize: Distribution,
+    pub firmness: Distribution,
+    // Chance of each race being the dominant one, negative chances count as none
+    pub races: Vec<(String, f32)>,
+    // Share of the race weights given to the non dominant races
+    pub race_mixing: f32,
+    // target.json categories, or target name prefixes, that get small random values.
+    // Prefixed targets only get positive values, so they should be one sided.
+    pub detail_categories: Vec<String>,
+    // Largest detail value, negative amounts count as none
+    pub detail_amount: f32,
+    pub hair_chance: f32,
+    pub hair_colors: Vec<Color>,
+    pub eye_colors: Vec<Color>,
+    // Copied into every generated config
+    pub equipment: Vec<String>,
+}
+
+impl Default for HumanGenerator {
+    fn default() -> Self {
+        HumanGenerator {
+            rng: StdRng::from_entropy(),
+            gender: Distribution::Uniform { min: 0.0, max: 1.0 },
+            age: Distribution::Normal { mean: 0.55, std_dev: 0.15 },
+            muscle: Distribution::Normal { mean: 0.5, std_dev: 0.15 },
+            weight: Distribution::Normal { mean: 0.5, std_dev: 0.15 },
+            height: Distribution::Normal { mean: 0.5, std_dev: 0.15 },
+            proportions: Distribution::Uniform { min: 0.5, max: 1.0 },
+            cupsize: Distribution::Normal { mean: 0.5, std_dev: 0.15 },
+            firmness: Distribution::Normal { mean: 0.5, std_dev: 0.15 },
+            races: vec![
+                ("african".to_string(), 1.0),
+                ("asian".to_string(), 1.0),
+                ("caucasian".to_string(), 1.0),
+            ],
+            race_mixing: 0.2,
+            detail_categories: ["nose", "chin", "ears"].iter().map(|c| c.to_string()).collect(),
+            detail_amount: 0.15,
+            hair_chance: 0.9,
+            hair_colors: vec![
+                Color::srgb(0.05, 0.04, 0.03),
+                Color::srgb(0.22, 0.13, 0.07),
+                Color::srgb(0.45, 0.3, 0.17),
+                Color::srgb(0.75, 0.6, 0.35),
+                Color::srgb(0.5, 0.2, 0.08),
+                Color::srgb(0.6, 0.6, 0.6),
+            ],
+            eye_colors: vec![
+                Color::srgb(0.2, 0.12, 0.05),
+                Color::srgb(0.35, 0.22, 0.1),
+                Color::srgb(0.25, 0.4, 0.2),
+                Color::srgb(0.25, 0.4, 0.6),
+                Color::srgb(0.4, 0.45, 0.5),
+            ],
+            equipment: vec![],
+        }
+    }
+}
+
+/*----------------+
+ |  System Params  |
+ +----------------*/
+#[derive(SystemParam)]
+pub struct HumanGeneratorData<'w> {
+    // Skins and body parts are left empty without the ClothingPlugin
+    #[cfg(feature = "clothing")]
+    registry: Option<Res<'w, HumanAssetRegistry>>,
+    targets: Res<'w, MorphTargets>,
+    sliders: Res<'w, MorphSliders>,
+    macros: Res<'w, MacroSliders>,
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+impl HumanGenerator {
+    // Same seed, settings and assets give the same sequence of humans
+    pub fn seeded(seed: u64) -> Self {
+        HumanGenerator {
+            rng: StdRng::seed_from_u64(seed),
+            ..default()
+        }
+    }
+
+    pub fn generate(&mut self, data: &HumanGeneratorData) -> HumanConfig {
+        let mut values = MacroValues::default();
+        let distributions = [
+            ("gender", self.gender),
+            ("age", self.age),
+            ("muscle", self.muscle),
+            ("weight", self.weight),
+            ("height", self.height),
+            ("proportions", self.proportions),
+            ("cupsize", self.cupsize),
+            ("firmness", self.firmness),
+        ];
+        for (name, distribution) in distributions.iter() {
+            values.macros.insert(name.to_string(), distribution.sample(&mut self.rng));
+        }
+        values.races = self.sample_races();
+        let mut morph_targets = macro_targets(&values, &data.macros, &data.targets);
+
+        // Subtle random details
+        let amount = self.detail_amount.max(0.0);
+        for category in self.detail_categories.iter() {
+            let sliders = data.sliders.sliders(category);
+            if !sliders.is_empty() {
+                for slider in sliders {
+                    let value = amount * (2.0 * self.rng.gen::<f32>() - 1.0);
+                    slider.set(&mut morph_targets, value, SliderSide::Both);
+                }
+            } else {
+                let prefix = category.to_string() + "-";
+                let mut names: Vec<&String> = data.targets.0.keys().filter(|n| n.starts_with(&prefix)).collect();
+                names.sort();
+                for name in names {
+                    morph_targets.insert(name.clone(), amount * self.rng.gen::<f32>());
+                }
+            }
+        }
+
+        #[cfg(feature = "clothing")]
+        let (skin_albedo, body_parts) = match data.registry.as_deref() {
+            Some(registry) => {
+                let gender = values.macros["gender"];
+                let age = values.macros["age"];
+                let race = values.races.iter()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .map(|(r, _)| r.clone())
+                    .unwrap_or_default();
+                (self.pick_skin(&registry.skins, gender, age, &race), self.pick_body_parts(registry))
+            }
+            None => (String::new(), vec![]),
+        };
+        #[cfg(not(feature = "clothing"))]
+        let (skin_albedo, body_parts) = (String::new(), Vec::<String>::new());
+        let hair_color = pick(&mut self.rng, &self.hair_colors).unwrap_or(Color::BLACK);
+
+        HumanConfig {
+            morph_targets,
+            skin_albedo,
+            body_parts,
+            equipment: self.equipment.clone(),
+            eye_color: pick(&mut self.rng, &self.eye_colors).unwrap_or(Color::BLACK),
+            eyebrow_color: hair_color,
+            hair_color,
+            ..default()
+        }
+    }
+
+    fn sample_races(&mut self) -> HashMap<String, f32> {
+        let total: f32 = self.races.iter().map(|(_, p)| p.max(0.0)).sum();
+        let mut roll = total * self.rng.gen::<f32>();
+        let mut dominant = 0;
+        for (i, (_, p)) in self.races.iter().enumerate() {
+            if roll < *p { dominant = i; break; }
+            roll -= p.max(0.0);
+        }
+        let mix: Vec<f32> = (0..self.races.len()).map(|_| self.rng.gen::<f32>()).collect();
+        let mix_total: f32 = mix.iter().enumerate().filter(|(i, _)| *i != dominant).map(|(_, m)| m).sum();
+
+        let mut races = HashMap::<String, f32>::new();
+        for (i, (race, _)) in self.races.iter().enumerate() {
+            let weight = if i == dominant {
+                if self.races.len() > 1 { 1.0 - self.race_mixing } else { 1.0 }
+            } else if mix_total > 0.0 {
+                self.race_mixing * mix[i] / mix_total
+            } else { 0.0 };
+            races.insert(race.clone(), weight);
+        }
+        races
+    }
+
+    // Skins are named like young_african_female_diffuse.png
+    #[cfg(feature = "clothing")]
+    fn pick_skin(&mut self, skins: &[String], gender: f32, age: f32, race: &str) -> String {
+        let gender = if gender < 0.5 { "_female" } else { "_male" };
+        let age = match macro_age_years(age) {
+            years if years < 35.0 => "young_",
+            years if years < 60.0 => "middleage_",
+            _ => "old_",
+        };
+        let matching: Vec<String> = skins.iter()
+            .filter(|s| !s.contains("special"))
+            .filter(|s| s.starts_with(age) && s.contains(race) && s.contains(gender))
+            .cloned()
+            .collect();
+        if let Some(skin) = pick(&mut self.rng, &matching) { return skin; }
+        pick(&mut self.rng, skins).unwrap_or_default()
+    }
+
+    // One part per slot.  Right side parts follow the left side when there is a matching name.
+    #[cfg(feature = "clothing")]
+    fn pick_body_parts(&mut self, registry: &HumanAssetRegistry) -> Vec<String> {
+        let mut slots: Vec<&String> = registry.slot_body_parts.keys().collect();
+        slots.sort();
+        let mut parts = Vec::<String>::new();
+        for slot in slots.iter() {
+            if slot.starts_with("Right") { continue; }
+            if slot.as_str() == "Hair" && self.rng.gen::<f32>() >= self.hair_chance { continue; }
+            let mut candidates = registry.slot_body_parts[*slot].clone();
+            candidates.sort();
+            let Some(part) = pick(&mut self.rng, &candidates) else { continue };
+
+            if let Some(side) = slot.strip_prefix("Left") {
+                let right_slot = "Right".to_string() + side;
+                let mirrored = part.replacen("Left", "Right", 1);
+                if registry.body_parts.contains_key(&mirrored) {
+                    parts.push(mirrored);
+                } else if let Some(candidates) = registry.slot_body_parts.get(&right_slot) {
+                    let mut candidates = candidates.clone();
+                    candidates.sort();
+                    if let Some(right) = pick(&mut self.rng, &candidates) { parts.push(right); }
+                }
+            }
+            parts.push(part);
+        }
+        parts
+    }
+}
+
+fn pick<T: Clone>(rng: &mut StdRng, items: &[T]) -> Option<T> {
+    if items.is_empty() { return None; }
+    Some(items[rng.gen_range(0..items.len())].clone())
+}
+
+// MakeHuman's age slider: 0 is 1 year, 0.1875 is 11, 0.5 is 25 and 1 is 90
+#[cfg(feature = "clothing")]
+fn macro_age_years(age: f32) -> f32 {
+    if age < 0.1875 {
+        1.0 + age / 0.1875 * 10.0
+    } else if age < 0.5 {
+        11.0 + (age - 0.1875) / 0.3125 * 14.0
+    } else {
+        25.0 + (age - 0.5) / 0.5 * 65.0
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let distributions = [
+            Distribution::Uniform { min: 0.8, max: 0.2 },
+            Distribution::Uniform { min: -3.0, max: 5.0 },
+            Distribution::Normal { mean: 0.5, std_dev: -2.0 },
+            Distribution::Constant(4.0),
+        ];
+        for distribution in distributions {
+            for _ in 0..100 {
+                let value = distribution.sample(&mut rng);
+                assert!((0.0..=1.0).contains(&value), "{:?} gave {}", distribution, value);
+            }
+        }
+        let swapped = Distribution::Uniform { min: 0.8, max: 0.2 };
+        assert!((0..100).all(|_| (0.2..=0.8).contains(&swapped.sample(&mut rng))));
+    }
+
+    #[test]
+    fn races_ignore_negative_chances() {
+        let mut generator = HumanGenerator::seeded(3);
+        generator.races = vec![("african".to_string(), -5.0), ("asian".to_string(), 1.0)];
+        generator.race_mixing = 0.0;
+        for _ in 0..50 {
+            assert_eq!(generator.sample_races()["asian"], 1.0);
+        }
+
+        generator.races = vec![("african".to_string(), 0.0), ("asian".to_string(), 0.0)];
+        let races = generator.sample_races();
+        assert_eq!(races.values().sum::<f32>(), 1.0);
+    }
+
+    #[test]
+    fn seeds_repeat() {
+        let races = |seed| {
+            let mut generator = HumanGenerator::seeded(seed);
+            (0..5).map(|_| {
+                let mut races: Vec<(String, f32)> = generator.sample_races().into_iter().collect();
+                races.sort_by(|a, b| a.0.cmp(&b.0));
+                races
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(races(11), races(11));
+        assert_ne!(races(11), races(12));
+    }
+}
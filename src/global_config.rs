@@ -1,28 +1,56 @@
 use bevy::prelude::Resource;
+#[cfg(feature = "animation")]
 use crate::AnimationLibrarySettings;
+#[cfg(feature = "rig")]
+use crate::{
+    sockets::default_sockets,
+    HumanSocketDef,
+    RigType,
+};
+use crate::{
+    FileHumanAssetSource,
+    HumanAssetSource,
+};
 use std::{
-    path::PathBuf,
-    env,
-    collections::HashSet
+    path::{ Path, PathBuf },
+    collections::{ HashMap, HashSet },
+    sync::Arc,
 };
 
+// Where the source was set from, so MorphPlugin can redo it with AssetPlugin::file_path
+#[derive(Clone)]
+pub(crate) enum AssetRoot {
+    AssetFolder,
+    Path(PathBuf),
+    Source,
+}
+
 #[derive(Resource, Clone)]
 pub struct HumentityGlobalConfig {
-    pub(crate) core_assets_path: PathBuf,
+    // Every path below is read through this
+    pub(crate) source: Arc<dyn HumanAssetSource>,
+    pub(crate) asset_root: AssetRoot,
     pub(crate) body_part_paths: HashSet<PathBuf>,
     pub(crate) equipment_paths: HashSet<PathBuf>,
     pub(crate) target_paths: HashSet<PathBuf>,
+    #[cfg(feature = "animation")]
     pub(crate) animation_libraries: AnimationLibrarySettings,
     pub(crate) body_part_slots: Vec<String>,
+    #[cfg(feature = "clothing")]
     pub(crate) transparent_slots: Vec<String>,
     pub(crate) equipment_slots: Vec<String>,
+    // Name -> (rig config, weights) for RigType::Custom
+    pub(crate) custom_rigs: HashMap<String, (PathBuf, PathBuf)>,
+    // Socket name -> definition, per rig
+    #[cfg(feature = "rig")]
+    pub(crate) sockets: HashMap<RigType, HashMap<String, HumanSocketDef>>,
+    // Read while loading on top of the rigs of humans spawned by then
+    #[cfg(feature = "rig")]
+    pub(crate) preloaded_rigs: Vec<RigType>,
 }
 
 impl Default for HumentityGlobalConfig {
     fn default() -> Self {
-        let mut path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
-        // It is assumed this is only one level deep in your source. 
-        if !path.to_str().unwrap().ends_with("humentity") { path = path.join("src/humentity") }
         let body_parts_slots = vec![
             "LeftEye",
             "LeftEyebrow",
@@ -34,7 +62,8 @@ impl Default for HumentityGlobalConfig {
             "Teeth",
             "Hair",
         ];
-        let transparent_slots = vec![
+        #[cfg(feature = "clothing")]
+        let transparent_slots = [
             "LeftEyebrow",
             "LeftEyelash",
             "RightEyebrow",
@@ -57,19 +86,77 @@ impl Default for HumentityGlobalConfig {
         ];
 
         HumentityGlobalConfig {
-            core_assets_path: path.join("assets"),
-            body_part_paths: vec![path.join("assets/body_parts")].into_iter().collect(),
-            equipment_paths: vec![path.join("assets/clothes")].into_iter().collect(),
-            target_paths: vec![path.join("assets/targets")].into_iter().collect(),
+            source: default_source(FileHumanAssetSource::from_asset_folder("assets")),
+            asset_root: AssetRoot::AssetFolder,
+            body_part_paths: vec![PathBuf::from("body_parts")].into_iter().collect(),
+            equipment_paths: vec![PathBuf::from("clothes")].into_iter().collect(),
+            target_paths: vec![PathBuf::from("targets")].into_iter().collect(),
+            #[cfg(feature = "animation")]
             animation_libraries: AnimationLibrarySettings::default(),
             body_part_slots: body_parts_slots.iter().map(|s| s.to_string()).collect(),
             equipment_slots: equipment_slots.iter().map(|s| s.to_string()).collect(),
+            #[cfg(feature = "clothing")]
             transparent_slots: transparent_slots.iter().map(|s| s.to_string()).collect(),
+            custom_rigs: HashMap::new(),
+            #[cfg(feature = "rig")]
+            sockets: default_sockets(),
+            #[cfg(feature = "rig")]
+            preloaded_rigs: vec![],
         }
     }
 }
 
+// Core data compiled in with the embedded_assets feature, disk files still override it
+#[cfg(feature = "embedded_assets")]
+fn default_source(disk: FileHumanAssetSource) -> Arc<dyn HumanAssetSource> {
+    Arc::new(crate::EmbeddedHumanAssetSource::new(disk))
+}
+
+#[cfg(not(feature = "embedded_assets"))]
+fn default_source(disk: FileHumanAssetSource) -> Arc<dyn HumanAssetSource> {
+    Arc::new(disk)
+}
+
 impl HumentityGlobalConfig {
+    // Folder holding base.obj, rigs, targets etc.  Defaults to the humentity folder of
+    // bevy's asset folder, or the asset folder itself when base.obj is in it.
+    pub fn with_asset_root(self, root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let mut new = self.with_asset_source(FileHumanAssetSource::new(root.clone()));
+        new.asset_root = AssetRoot::Path(root);
+        new
+    }
+
+    // For packaged games, custom asset sources or read only bundles
+    pub fn with_asset_source(self, source: impl HumanAssetSource) -> Self {
+        let mut new = self;
+        new.source = Arc::new(source);
+        new.asset_root = AssetRoot::Source;
+        new
+    }
+
+    // Called by MorphPlugin with AssetPlugin::file_path.  Disk roots without base.obj
+    // panic here rather than while reading every other file.
+    pub(crate) fn resolve_asset_folder(&mut self, file_path: &Path) {
+        let disk = match &self.asset_root {
+            AssetRoot::AssetFolder => FileHumanAssetSource::from_asset_folder(file_path),
+            AssetRoot::Path(root) => FileHumanAssetSource::new(root.clone()).with_asset_folder(file_path),
+            AssetRoot::Source => return,
+        };
+        // Embedded core data doesn't need anything on disk
+        let embedded = cfg!(feature = "embedded_assets") && matches!(self.asset_root, AssetRoot::AssetFolder);
+        if !embedded && !disk.root().join("base.obj").is_file() {
+            panic!(
+                "NO base.obj IN HUMENTITY ASSET ROOT {:?}, COPY THE ASSETS THERE OR SET HumentityGlobalConfig::with_asset_root",
+                disk.root()
+            );
+        }
+        self.source = match self.asset_root {
+            AssetRoot::AssetFolder => default_source(disk),
+            _ => Arc::new(disk),
+        };
+    }
+
     pub fn with_added_body_parts_paths<I>(self, paths: I) -> Self
     where I: IntoIterator<Item = PathBuf> {
         let mut new = self;
@@ -91,6 +178,7 @@ impl HumentityGlobalConfig {
         new
     }
 
+    #[cfg(feature = "animation")]
     pub fn with_animation_libraries(self, animation_library_settings: AnimationLibrarySettings) -> Self {
         let mut new = self;
         new.animation_libraries = animation_library_settings;
@@ -110,4 +198,30 @@ impl HumentityGlobalConfig {
         new.equipment_slots = slots.into_iter().collect();
         new
     }
+
+    // MPFB format rig and weights json, used by RigType::Custom(name)
+    pub fn with_custom_rig(self, name: impl Into<String>, rig: PathBuf, weights: PathBuf) -> Self {
+        let mut new = self;
+        new.custom_rigs.insert(name.into(), (rig, weights));
+        new
+    }
+
+    // Adds or replaces a socket.  Built-in rigs come with right_hand_grip, left_hand_grip,
+    // head_top, back and hips.
+    #[cfg(feature = "rig")]
+    pub fn with_socket(self, rig: RigType, name: impl Into<String>, socket: HumanSocketDef) -> Self {
+        let mut new = self;
+        new.sockets.entry(rig).or_default().insert(name.into(), socket);
+        new
+    }
+
+    // Rigs read while loading even if no human uses them yet.  Rigs of humans spawned
+    // while loading are always read, any other rig stalls the frame its first human spawns.
+    #[cfg(feature = "rig")]
+    pub fn with_preloaded_rigs<I>(self, rigs: I) -> Self
+    where I: IntoIterator<Item = RigType> {
+        let mut new = self;
+        new.preloaded_rigs = rigs.into_iter().collect();
+        new
+    }
 }
@@ -0,0 +1,254 @@
+use bevy::{
+    asset::UntypedAssetId,
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use std::{
+    collections::{ HashMap, HashSet },
+    path::PathBuf,
+    sync::Arc,
+};
+use crate::{
+    has_extension,
+    loaders::{ MhcloAsset, RigAsset, TargetAsset },
+    rigs::{ rig_files, RigFile },
+    AttachTo,
+    HumanAssetRegistry,
+    HumanBaking,
+    HumanConfig,
+    HumanLod,
+    HumanMeshCache,
+    HumanPart,
+    HumanSkeleton,
+    HumanSockets,
+    HumentityGlobalConfig,
+    MorphTargets,
+    PendingHuman,
+    RigData,
+    RigType,
+};
+
+/*-------------+
+ |  Resources  |
+ +-------------*/
+// Handles to every target, mhclo and rig file so the file watcher reports edits.  The
+// parsed files are taken out of their Assets as they arrive, the resources own the data.
+#[allow(dead_code)]
+#[derive(Resource)]
+pub(crate) struct WatchedHumanFiles {
+    targets: Vec<Handle<TargetAsset>>,
+    mhclos: Vec<Handle<MhcloAsset>>,
+    rigs: HashMap<AssetId<RigAsset>, (RigType, Handle<RigAsset>)>,
+    // Files that finished their first load, anything arriving after is an edit
+    loaded: HashSet<UntypedAssetId>,
+}
+
+/*-----------------+
+ |  System Params  |
+ +-----------------*/
+// File change events and the reloaded file contents
+#[derive(SystemParam)]
+pub(crate) struct ReloadedHumanFiles<'w, 's> {
+    target_events: EventReader<'w, 's, AssetEvent<TargetAsset>>,
+    mhclo_events: EventReader<'w, 's, AssetEvent<MhcloAsset>>,
+    rig_events: EventReader<'w, 's, AssetEvent<RigAsset>>,
+    target_assets: ResMut<'w, Assets<TargetAsset>>,
+    mhclo_assets: ResMut<'w, Assets<MhcloAsset>>,
+    rig_assets: ResMut<'w, Assets<RigAsset>>,
+    asset_server: Res<'w, AssetServer>,
+    watched: Option<ResMut<'w, WatchedHumanFiles>>,
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+pub(crate) fn watch_human_files(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    global_config: Res<HumentityGlobalConfig>,
+) {
+    let source = global_config.source.clone();
+    let files = |dirs: Vec<PathBuf>, extensions: &[&str]| -> Vec<PathBuf> {
+        dirs.into_iter()
+            .flat_map(|dir| source.files(&dir))
+            .filter(|path| has_extension(path, extensions))
+            .collect()
+    };
+
+    let targets = files(global_config.target_paths.iter().cloned().collect(), &["target"])
+        .into_iter()
+        .map(|path| asset_server.load(source.asset_path(&path)))
+        .collect();
+
+    let mut mhclo_dirs: Vec<PathBuf> = global_config.body_part_paths.iter()
+        .chain(global_config.equipment_paths.iter())
+        .cloned()
+        .collect();
+    mhclo_dirs.push(PathBuf::from("proxies"));
+    let mhclos = files(mhclo_dirs, &["mhclo", "proxy"])
+        .into_iter()
+        .map(|path| asset_server.load(source.asset_path(&path)))
+        .collect();
+
+    let mut rigs = HashMap::<AssetId<RigAsset>, (RigType, Handle<RigAsset>)>::new();
+    for (rig, rig_path, weights_path) in rig_files(&global_config) {
+        for path in [rig_path, weights_path] {
+            let handle: Handle<RigAsset> = asset_server.load(source.asset_path(&path));
+            rigs.insert(handle.id(), (rig.clone(), handle));
+        }
+    }
+
+    commands.insert_resource(WatchedHumanFiles { targets, mhclos, rigs, loaded: HashSet::new() });
+}
+
+// Initial loads are already in the resources, so only modifications are applied
+pub(crate) fn reload_human_files(
+    files: ReloadedHumanFiles,
+    mut targets: ResMut<MorphTargets>,
+    mut registry: ResMut<HumanAssetRegistry>,
+    mut rigs: ResMut<RigData>,
+    mut cache: ResMut<HumanMeshCache>,
+    humans: Query<(Entity, &HumanConfig), Without<PendingHuman>>,
+    mut commands: Commands,
+) {
+    let ReloadedHumanFiles {
+        mut target_events,
+        mut mhclo_events,
+        mut rig_events,
+        mut target_assets,
+        mut mhclo_assets,
+        mut rig_assets,
+        asset_server,
+        watched,
+    } = files;
+    let Some(mut watched) = watched else { return };
+    let mut changed_targets = Vec::<String>::new();
+    let mut changed_assets = Vec::<String>::new();
+    let mut changed_rigs = Vec::<RigType>::new();
+
+    for (id, target) in take_edits(&mut target_events, &mut target_assets, &mut watched.loaded) {
+        let Some(path) = asset_server.get_path(id) else { continue };
+        let Some(name) = path.path().file_stem().and_then(|s| s.to_str()) else { continue };
+        info!("Reloaded target {}", name);
+        targets.0.insert(name.to_string(), target.0);
+        changed_targets.push(name.to_string());
+    }
+
+    for (_, reloaded) in take_edits(&mut mhclo_events, &mut mhclo_assets, &mut watched.loaded) {
+        let name = &reloaded.0.name;
+        let registry = &mut *registry;
+        let existing = registry.body_parts.get_mut(name)
+            .or_else(|| registry.equipment.get_mut(name))
+            .or_else(|| registry.proxies.get_mut(name));
+        let Some(asset) = existing else {
+            warn!("Reloaded {} is not in the registry, restart to add new assets", name);
+            continue;
+        };
+        info!("Reloaded asset {}", name);
+        asset.reload_from(&reloaded.0);
+        changed_assets.push(name.clone());
+    }
+
+    for (id, reloaded) in take_edits(&mut rig_events, &mut rig_assets, &mut watched.loaded) {
+        let Some((rig, _)) = watched.rigs.get(&id) else { continue };
+        // Rigs not used yet are read from disk on first use anyway
+        if !rigs.is_loaded(rig) { continue; }
+        info!("Reloaded rig {:?}", rig);
+        match reloaded.0 {
+            RigFile::Bones(bones) => { rigs.set_config(rig, bones); }
+            RigFile::Weights(weights) => { rigs.weights.insert(rig.clone(), Arc::new(weights)); }
+        }
+        changed_rigs.push(rig.clone());
+    }
+
+    if changed_targets.is_empty() && changed_assets.is_empty() && changed_rigs.is_empty() { return; }
+    // Cache keys don't cover file contents
+    cache.clear();
+
+    // Rebuild the humans using a changed file
+    for (human, config) in humans.iter() {
+        let affected = changed_rigs.contains(&config.rig)
+            || changed_targets.iter().any(|t| config.morph_targets.contains_key(t))
+            || changed_assets.iter().any(|a| {
+                config.body_parts.contains(a)
+                || config.equipment.contains(a)
+                || config.lod.as_ref().is_some_and(|lod| &lod.proxy == a)
+            });
+        if !affected { continue; }
+        let config = config.clone();
+        commands.add(move |world: &mut World| rebuild_human(world, human, config));
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+// Takes arriving files out of their Assets, keeping the ones that aren't a first load.
+// A taken file comes back as Added when it's edited, the handle keeps it watched.
+fn take_edits<A: Asset>(
+    events: &mut EventReader<AssetEvent<A>>,
+    assets: &mut Assets<A>,
+    loaded: &mut HashSet<UntypedAssetId>,
+) -> Vec<(AssetId<A>, A)> {
+    let mut edits = Vec::new();
+    for event in events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = *event else { continue };
+        let Some(asset) = assets.remove(id) else { continue };
+        if !loaded.insert(id.untyped()) { edits.push((id, asset)); }
+    }
+    edits
+}
+
+// Re-adding the config makes on_human_added build the human again.  Attached props wait
+// for the new sockets, other children of the human are kept.
+fn rebuild_human(world: &mut World, human: Entity, config: HumanConfig) {
+    let parts: Vec<Entity> = world.query::<(Entity, &HumanPart)>().iter(world)
+        .filter(|(_, part)| part.0 == human)
+        .map(|(entity, _)| entity)
+        .collect();
+    let attached: Vec<Entity> = world.query::<(Entity, &AttachTo)>().iter(world)
+        .filter(|(_, attach)| attach.human == human)
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in attached {
+        world.entity_mut(entity).remove_parent();
+    }
+    for part in parts {
+        // Sockets go with their bones
+        if let Some(part) = world.get_entity_mut(part) { part.despawn_recursive(); }
+    }
+
+    let Some(mut human) = world.get_entity_mut(human) else { return };
+    // Dropping the task cancels a bake of the old files
+    human.remove::<(HumanBaking, HumanLod, HumanSkeleton, HumanSockets, HumanConfig)>();
+    human.insert(config);
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuild_keeps_props_and_children() {
+        let mut world = World::new();
+        let human = world.spawn((HumanConfig::default(), HumanSockets::default())).id();
+        let bone = world.spawn(HumanPart(human)).set_parent(human).id();
+        let socket = world.spawn(HumanPart(human)).set_parent(bone).id();
+        let prop = world.spawn(AttachTo { human, socket: "hips".to_string() }).set_parent(socket).id();
+        let child = world.spawn_empty().set_parent(human).id();
+        let other = world.spawn(HumanConfig::default()).id();
+        let other_bone = world.spawn(HumanPart(other)).set_parent(other).id();
+
+        rebuild_human(&mut world, human, HumanConfig::default());
+
+        assert!(world.get_entity(bone).is_none() && world.get_entity(socket).is_none());
+        assert!(world.get::<Parent>(prop).is_none());
+        assert_eq!(world.get::<Parent>(child).map(|p| p.get()), Some(human));
+        assert!(world.get_entity(other_bone).is_some());
+        assert!(world.get::<HumanSockets>(human).is_none());
+        assert!(world.get::<HumanConfig>(human).is_some());
+    }
+}
@@ -1,127 +1,309 @@
 mod basemesh;
 mod morphs;
+#[cfg(feature = "rig")]
 mod rigs;
+#[cfg(feature = "rig")]
+mod skeleton;
+#[cfg(feature = "rig")]
+mod sockets;
 mod global_config;
+#[cfg(feature = "render")]
 mod assets;
+#[cfg(feature = "animation")]
 mod animation;
+#[cfg(feature = "render")]
 mod mesh_ops;
+mod ragdoll;
+mod measurements;
+mod generator;
+mod preset;
+mod validation;
+#[cfg(feature = "editor")]
+mod editor;
+mod lod;
+#[cfg(feature = "render")]
+mod cache;
+#[cfg(feature = "render")]
+mod baking;
+#[cfg(feature = "render")]
+mod bounds;
+#[cfg(feature = "hot_reload")]
+mod loaders;
+mod skinning;
+mod asset_source;
+mod plugins;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
 
 use bevy::{
+    ecs::system::EntityCommands,
     prelude::*,
-    render::mesh::skinning::SkinnedMeshInverseBindposes
 };
-use std::collections::{ HashMap, HashSet };
-use bevy_obj::ObjPlugin;
-use basemesh::{
-    create_body_mesh,
-    create_body_vertex_map,
+#[cfg(feature = "render")]
+use bevy::{
+    ecs::system::SystemParam,
+    render::mesh::skinning::SkinnedMesh,
+};
+use serde::{ Deserialize, Serialize };
+#[cfg(feature = "rig")]
+use bevy::render::mesh::skinning::SkinnedMeshInverseBindposes;
+use std::collections::HashMap;
+#[cfg(feature = "render")]
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::Arc,
 };
+#[cfg(feature = "clothing")]
 use assets::{
-    HumanAssetRegistry,
+    asset_material,
     HumanAssetTextures,
-    generate_asset_vertex_maps,
-    delete_mesh_verts,
 };
-use rigs::{
-    RigData,
-    bone_debug_draw,
-    build_rig,
-    set_basemesh_rig_arrays,
-    set_asset_rig_arrays,
+#[cfg(feature = "rig")]
+use rigs::build_rig;
+use morphs::adjust_helpers_to_morphs;
+#[cfg(feature = "rig")]
+use ragdoll::build_ragdoll;
+#[cfg(feature = "rig")]
+use sockets::spawn_sockets;
+#[cfg(feature = "render")]
+use bounds::HumanExtents;
+#[cfg(feature = "render")]
+use baking::{
+    BakeInput,
+    spawn_human_meshes,
 };
-use morphs::{
-    adjust_helpers_to_morphs,
-    bake_asset_morphs,
-    bake_body_morphs,
-    MorphTargets,
+use measurements::{
+    MeasurementLoops,
+    measure_body,
+};
+#[cfg(feature = "render")]
+use skinning::{
+    HumanMaterial,
+    HumanMaterials,
 };
-use animation::load_animations;
 
+#[cfg(feature = "render")]
 pub(crate) use mesh_ops::{
     get_vertex_positions,
     get_vertex_normals,
     get_uv_coords,
     generate_vertex_map,
     generate_inverse_vertex_map,
-    parse_obj_vertices,
+    decimate_mesh,
 };
 pub(crate) use basemesh::{
+    align_to_origin,
     BaseMesh,
     VertexGroups,
     BODY_SCALE,
 };
-pub(crate) use assets::{
-    HelperMap,
-    HumanMeshAsset,
-};
+#[cfg(feature = "clothing")]
+pub(crate) use basemesh::parse_obj_vertices;
+#[cfg(feature = "rig")]
+pub(crate) use assets::HelperMap;
+pub(crate) use asset_source::has_extension;
 
-pub use rigs::RigType;
+#[cfg(feature = "render")]
+pub use assets::HumanMeshAsset;
+#[cfg(feature = "clothing")]
+pub use assets::HumanAssetRegistry;
+pub use morphs::{
+    MacroValues,
+    MorphSlider,
+    MorphSliders,
+    MorphTargets,
+    SliderSide,
+};
+pub use generator::{
+    Distribution,
+    HumanGenerator,
+    HumanGeneratorData,
+};
+pub use preset::HumanPreset;
+pub use validation::{
+    HumanConfigError,
+    HumanSpawnFailed,
+};
+#[cfg(feature = "render")]
+pub use cache::HumanMeshCache;
+#[cfg(feature = "hot_reload")]
+pub use loaders::{
+    MhcloAsset,
+    MhcloLoader,
+    RigAsset,
+    RigLoader,
+    TargetAsset,
+    TargetLoader,
+};
+#[cfg(feature = "rig")]
+pub use skeleton::{
+    HumanBone,
+    HumanSkeleton,
+    HumanSkeletonTemplate,
+};
+#[cfg(feature = "rig")]
+pub use rigs::RigData;
+#[cfg(feature = "rig")]
+pub use sockets::{
+    AttachTo,
+    HumanSocketDef,
+    HumanSockets,
+};
+#[cfg(feature = "render")]
+pub use baking::{
+    HumanBaking,
+    HumanReady,
+};
+#[cfg(feature = "render")]
+pub use bounds::HumanBounds;
+pub use skinning::SkinningSettings;
+#[cfg(feature = "render")]
+pub use skinning::{
+    HumanSkinnedMaterial,
+    HumanSkinning,
+    ATTRIBUTE_JOINT_INDEX_1,
+    ATTRIBUTE_JOINT_WEIGHT_1,
+};
+pub use lod::LodSettings;
+#[cfg(feature = "render")]
+pub use lod::{
+    HumanLod,
+    HumanLodLevel,
+};
+#[cfg(feature = "editor")]
+pub use editor::{
+    HumanConfigExported,
+    HumentityEditorPlugin,
+};
 pub use global_config::HumentityGlobalConfig;
+pub use asset_source::{
+    FileHumanAssetSource,
+    HumanAssetSource,
+};
+#[cfg(feature = "embedded_assets")]
+pub use asset_source::EmbeddedHumanAssetSource;
+#[cfg(feature = "animation")]
 pub use animation::{
     AnimationLibrarySet,
     AnimationLibrarySettings,
 };
+pub use ragdoll::{
+    BoneCollider,
+    ColliderKind,
+    ColliderShape,
+    HumanRagdoll,
+    JointLimits,
+    RagdollJoint,
+    RagdollJointKind,
+    RagdollSettings,
+};
+pub use measurements::{
+    BodyMeasurements,
+    FitSettings,
+    HumanMeasurer,
+    Measurement,
+    MeasurementFit,
+};
+pub use plugins::{
+    Humentity,
+    HumentityPlugins,
+    MorphPlugin,
+};
+#[cfg(feature = "clothing")]
+pub use plugins::ClothingPlugin;
+#[cfg(feature = "rig")]
+pub use plugins::RigPlugin;
+#[cfg(feature = "animation")]
+pub use plugins::AnimationPlugin;
+#[cfg(feature = "debug")]
+pub use plugins::DebugPlugin;
 
 pub mod prelude {
     pub use crate::{
         Humentity,
+        HumentityPlugins,
+        MorphPlugin,
         HumentityGlobalConfig,
         HumentityState,
         HumanConfig,
-        SpawnTransform,
+        HumanCommandsExt,
+        HumanPart,
         RigType,
+        HumanOrigin,
+        ColliderKind,
+        HumanRagdoll,
+        RagdollSettings,
+        BodyMeasurements,
+        FitSettings,
+        HumanMeasurer,
+        Measurement,
+        HumanGenerator,
+        HumanGeneratorData,
+        MorphSliders,
+        SliderSide,
+        HumanPreset,
+        HumanConfigError,
+        HumanSpawnFailed,
+        LodSettings,
+        SkinningSettings,
+    };
+    #[cfg(feature = "render")]
+    pub use crate::{
+        HumanLod,
+        HumanBaking,
+        HumanReady,
+        HumanBounds,
+    };
+    #[cfg(feature = "clothing")]
+    pub use crate::ClothingPlugin;
+    #[cfg(feature = "rig")]
+    pub use crate::{
+        RigPlugin,
+        HumanBone,
+        HumanSkeleton,
+        AttachTo,
+        HumanSocketDef,
+        HumanSockets,
+    };
+    #[cfg(feature = "animation")]
+    pub use crate::{
+        AnimationPlugin,
         AnimationLibrarySet,
         AnimationLibrarySettings,
     };
+    #[cfg(feature = "debug")]
+    pub use crate::DebugPlugin;
+    #[cfg(feature = "editor")]
+    pub use crate::{
+        HumentityEditorPlugin,
+        HumanConfigExported,
+    };
 }
 
-/*----------+
- |  Plugin  |
- +----------*/
-pub struct Humentity{
-    pub debug: bool,
-}
-
-impl Default for Humentity {
-    fn default() -> Self {
-        Humentity {
-            debug: true,
-        }
-    }
+/*---------+
+ |  Types  |
+ +---------*/
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub enum RigType {
+    None,
+    Default,
+    Mixamo,
+    GameEngine,
+    // Registered with HumentityGlobalConfig::with_custom_rig
+    Custom(String),
 }
 
-impl Plugin for Humentity {
-    fn build(&self, app: &mut App) {
-        let mut loading_state = HashMap::<LoadingPhase, bool>::new();
-        loading_state.insert(LoadingPhase::CreateBodyMesh, false);
-        loading_state.insert(LoadingPhase::GenerateBodyVertexMap, false);
-        loading_state.insert(LoadingPhase::GenerateAssetVertexMap, false);
-        loading_state.insert(LoadingPhase::SetUpAnimationLibraries, false);
-
-        if !app.is_plugin_added::<ObjPlugin>() {
-            app.add_plugins(ObjPlugin{ compute_smooth_normals: true });
-        }
-        app.insert_state(HumentityState::Loading);
-        app.insert_resource(LoadingState(loading_state));
-        app.init_resource::<MorphTargets>();
-        app.init_resource::<HumanAssetRegistry>();
-        app.init_resource::<BaseMesh>();
-        app.init_resource::<RigData>();
-        app.init_resource::<AnimationLibrarySet>();
-        app.add_systems(Update, ((
-            loading_state_checker,
-            create_body_mesh,
-            create_body_vertex_map,
-            generate_asset_vertex_maps,
-            load_animations,
-        )).run_if(in_state(HumentityState::Loading)));
-        app.add_systems(Update, (
-            on_human_added,
-        ).run_if(in_state(HumentityState::Ready)));
-        if self.debug {
-            app.add_systems(Update, bone_debug_draw);
-        }
-    }
+// Where the entity's origin sits on the morphed body.  Only the height is adjusted.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum HumanOrigin {
+    // Lowest point of the feet, so every body stands on the ground
+    #[default]
+    Feet,
+    // MakeHuman's joint-ground helper
+    JointGround,
+    // Base mesh origin, legs grow or shrink into the ground
+    BaseMesh,
 }
 
 /*----------+
@@ -140,6 +322,7 @@ pub enum LoadingPhase {
     GenerateBodyVertexMap,
     GenerateAssetVertexMap,
     SetUpAnimationLibraries,
+    LoadRigs,
 }
 
 /*-------------+
@@ -151,10 +334,16 @@ pub(crate) struct LoadingState(HashMap<LoadingPhase, bool>);
 /*--------------+
  |  Components  |
  +--------------*/
+// Waits here until HumentityState::Ready, spawns issued while loading are kept
 #[derive(Component)]
-pub struct SpawnTransform(pub Transform);
+pub(crate) struct PendingHuman;
 
-#[derive(Component)]
+// On every entity spawned for a human: bones, sockets, meshes and ragdoll joints.
+// Rebuilding a human replaces these and leaves anything else under it alone.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct HumanPart(pub Entity);
+
+#[derive(Component, Clone)]
 pub struct HumanConfig {
     // Could be f16 (unstable type warning)
     pub morph_targets: HashMap<String, f32>,
@@ -165,6 +354,13 @@ pub struct HumanConfig {
     pub eye_color: Color,
     pub eyebrow_color: Color,
     pub hair_color: Color,
+    // Generates a HumanRagdoll component when set
+    pub ragdoll: Option<RagdollSettings>,
+    // Generates lower detail meshes and a HumanLod component when set
+    pub lod: Option<LodSettings>,
+    // Ignored for RigType::None
+    pub skinning: SkinningSettings,
+    pub origin: HumanOrigin,
 }
 
 impl Default for HumanConfig {
@@ -178,194 +374,417 @@ impl Default for HumanConfig {
             eye_color: Color::BLACK,
             eyebrow_color: Color::BLACK,
             hair_color: Color::BLACK,
+            ragdoll: None,
+            lod: None,
+            skinning: SkinningSettings::default(),
+            origin: HumanOrigin::default(),
         }
     }
 }
 
+/*------------+
+ |  Commands  |
+ +------------*/
+pub trait HumanCommandsExt {
+    // Spawns the human at the origin, insert a Transform or SpatialBundle to place it.
+    // Can be called before HumentityState::Ready, the human is built once loading is done.
+    fn spawn_human(&mut self, config: HumanConfig) -> EntityCommands<'_>;
+}
+
+impl HumanCommandsExt for Commands<'_, '_> {
+    fn spawn_human(&mut self, config: HumanConfig) -> EntityCommands<'_> {
+        #[cfg(feature = "render")]
+        let spatial = SpatialBundle::default();
+        #[cfg(not(feature = "render"))]
+        let spatial = TransformBundle::default();
+        self.spawn((config, spatial))
+    }
+}
+
+/*-----------------+
+ |  System Params  |
+ +-----------------*/
+// Morphing and measuring the body
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+pub(crate) struct HumanMorphData<'w> {
+    global_config: Res<'w, HumentityGlobalConfig>,
+    base_mesh: Res<'w, BaseMesh>,
+    targets: Res<'w, MorphTargets>,
+    vg: Res<'w, VertexGroups>,
+    measurement_loops: Res<'w, MeasurementLoops>,
+}
+
+// Clothing resources are missing when the ClothingPlugin isn't added
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+pub(crate) struct HumanMeshData<'w> {
+    meshes: Res<'w, Assets<Mesh>>,
+    materials: HumanMaterials<'w>,
+    asset_server: Res<'w, AssetServer>,
+    cache: Res<'w, HumanMeshCache>,
+    #[cfg(feature = "clothing")]
+    registry: Option<Res<'w, HumanAssetRegistry>>,
+    #[cfg(feature = "clothing")]
+    asset_textures: Option<Res<'w, HumanAssetTextures>>,
+}
+
+// Rigs are missing when the RigPlugin isn't added
+#[cfg(feature = "rig")]
+#[derive(SystemParam)]
+pub(crate) struct HumanRigData<'w> {
+    rigs: Option<ResMut<'w, RigData>>,
+    inverse_bindposes: ResMut<'w, Assets<SkinnedMeshInverseBindposes>>,
+}
+
+// Validated humans waiting to be built
+#[cfg(feature = "render")]
+type NewHumans<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static HumanConfig, Option<&'static Transform>, Has<Visibility>),
+    With<PendingHuman>,
+>;
+
 /*-----------+
  |  Systems  |
  +-----------*/
-fn loading_state_checker(
+// Runs in every state so humans spawned while loading aren't missed
+pub(crate) fn queue_new_humans(
+    new_humans: Query<Entity, Added<HumanConfig>>,
+    mut commands: Commands,
+) {
+    for human in new_humans.iter() {
+        commands.entity(human).insert(PendingHuman);
+    }
+}
+
+pub(crate) fn loading_state_checker(
     loading_state: Res<LoadingState>,
     mut next: ResMut<NextState<HumentityState>>,
     mut commands: Commands,
 ) {
-    if !loading_state.0.get(&LoadingPhase::CreateBodyMesh).unwrap() { return; }
-    if !loading_state.0.get(&LoadingPhase::GenerateBodyVertexMap).unwrap() { return; }
-    if !loading_state.0.get(&LoadingPhase::GenerateAssetVertexMap).unwrap() { return; }
-    if !loading_state.0.get(&LoadingPhase::SetUpAnimationLibraries).unwrap() { return; }
+    // Each plugin adds the phases it needs
+    if !loading_state.0.values().all(|done| *done) { return; }
     commands.remove_resource::<LoadingState>();
     next.set(HumentityState::Ready);
 }
 
-fn on_human_added(
-    new_humans: Query<(Entity, &HumanConfig, &SpawnTransform), Added<HumanConfig>>,
+// Without rendering only the measurements of the morphed body are available
+#[cfg(not(feature = "render"))]
+pub(crate) fn on_human_added(
+    new_humans: Query<(Entity, &HumanConfig, Has<Transform>), With<PendingHuman>>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut inv_bindposes: ResMut<Assets<SkinnedMeshInverseBindposes>>,
-    global_config: Res<HumentityGlobalConfig>,
-    registry: Res<HumanAssetRegistry>,
     base_mesh: Res<BaseMesh>,
     targets: Res<MorphTargets>,
-    asset_server: Res<AssetServer>,
-    rigs: Res<RigData>,
     vg: Res<VertexGroups>,
-    asset_textures: Res<HumanAssetTextures>,
+    measurement_loops: Res<MeasurementLoops>,
+) {
+    new_humans.iter().for_each(|(human, config, has_transform)| {
+        let mut helpers = adjust_helpers_to_morphs(&config.morph_targets, &targets, &base_mesh);
+        align_to_origin(&mut helpers, &vg, config.origin);
+        commands.entity(human)
+            .insert(measure_body(&helpers, &measurement_loops, &vg))
+            .remove::<PendingHuman>();
+        if !has_transform {
+            commands.entity(human).insert(TransformBundle::default());
+        }
+    })
+}
+
+#[cfg(feature = "render")]
+pub(crate) fn on_human_added(
+    new_humans: NewHumans,
+    mut commands: Commands,
+    morph_data: HumanMorphData,
+    mesh_data: HumanMeshData,
+    mut ready: EventWriter<HumanReady>,
+    #[cfg(feature = "rig")] rig_data: HumanRigData,
 ) {
-    // TODO Can we wrap all these args up in a single struct to pass to every function?
-    // tried but couldn't figure out lifetimes
-    let path = global_config.core_assets_path.clone();
+    let HumanMorphData { global_config, base_mesh, targets, vg, measurement_loops } = morph_data;
+    let HumanMeshData {
+        meshes,
+        mut materials,
+        asset_server,
+        cache,
+        #[cfg(feature = "clothing")] registry,
+        #[cfg(feature = "clothing")] asset_textures,
+    } = mesh_data;
+    #[cfg(feature = "rig")]
+    let HumanRigData { mut rigs, inverse_bindposes: mut inv_bindposes } = rig_data;
+
+    let source = global_config.source.clone();
+    #[cfg(feature = "clothing")]
     let transparent_slots = global_config.transparent_slots.clone();
+    #[cfg(feature = "clothing")]
+    let clothing = registry.as_deref().zip(asset_textures.as_deref());
+    #[cfg(not(feature = "clothing"))]
+    let clothing: Option<()> = None;
 
-    new_humans.iter().for_each(|(human, config, spawn_transform)| {
-        // Body Material
-        let albedo = asset_server.load(path.join("skin_textures/albedo/".to_string() + &config.skin_albedo));
-        let material = materials.add(StandardMaterial {
-            base_color_texture: Some(albedo),
-            ..default()
-        });
+    new_humans.iter().for_each(|(human, config, transform, has_visibility)| {
+        // Humans spawned without one stay at the origin
+        let transform = transform.copied().unwrap_or_default();
 
-        let helpers = adjust_helpers_to_morphs(
-            &config.morph_targets,
-            &targets,
-            &base_mesh
-        );
-        let (skinned_mesh, sorted_bones) = build_rig(
-            &human,
-            config.rig,
-            &rigs,
-            &mut inv_bindposes,
-            &mut commands,
-            &vg,
-            &helpers,
-            spawn_transform.0,
-        );
+        // Identical configs share meshes, materials and bindposes
+        let key = HumanMeshCache::key(config);
+        let cached = cache.get(key).cloned();
 
-        let mut delete_verts = HashSet::<u16>::new();
-
-        // Body Parts
-        for bp in config.body_parts.iter() {
-            let err_msg = format!("FAILED TO FIND BODY PART {}", bp);
-            let asset = registry.body_parts.get(bp).expect(&err_msg);
-            delete_verts.extend(&asset.delete_verts);
-            let mesh = bake_asset_morphs(
-                &config.morph_targets, 
-                &targets,
-                &mut meshes,
-                &helpers,
-                &asset,
-            );
-            let mesh_handle = set_asset_rig_arrays(
-                config.rig,
-                mesh,
-                &rigs,
-                &asset.vertex_map,
-                &mut meshes,
-                &asset.helper_maps,
-                &sorted_bones,
-            );
-            let mut material = StandardMaterial::default();
-            if let Some(albedos) = asset_textures.albedo_maps.get(&asset.name) {
-                if albedos.len() > 0 { material.base_color_texture = Some(albedos[0].clone()); }
-            }
-            if let Some(normal) = asset_textures.normal_map.get(&asset.name) {
-                material.normal_map_texture = Some(normal.clone());
+        let helpers = match cached.as_ref() {
+            Some(cached) => cached.helpers.clone(),
+            None => {
+                let mut helpers = adjust_helpers_to_morphs(
+                    &config.morph_targets,
+                    &targets,
+                    &base_mesh
+                );
+                align_to_origin(&mut helpers, &vg, config.origin);
+                helpers
             }
-            if let Some(ao) = asset_textures.ao_map.get(&asset.name) {
-                material.occlusion_texture = Some(ao.clone());
+        };
+        commands.entity(human).insert(measure_body(&helpers, &measurement_loops, &vg));
+
+        // Static humans get no skeleton, skinning or animation player
+        #[cfg(feature = "rig")]
+        let rigged = match rigs.as_deref_mut() {
+            // Validation read the rig, or failed the human if it couldn't
+            Some(rigs) if rigs.is_loaded(&config.rig) => {
+                let (skeleton, inverse_bindposes) = build_rig(
+                    &human,
+                    &config.rig,
+                    rigs,
+                    &mut commands,
+                    &vg,
+                    &helpers,
+                    transform,
+                );
+                spawn_sockets(&mut commands, human, &skeleton, rigs, &global_config, &vg, &helpers);
+                let inverse_bindposes = cached.as_ref()
+                    .and_then(|c| c.inverse_bindposes.clone())
+                    .unwrap_or_else(|| inv_bindposes.add(inverse_bindposes));
+                let skinned_mesh = SkinnedMesh { inverse_bindposes, joints: skeleton.joints().to_vec() };
+                let sorted_bones = skeleton.names().to_vec();
+                // Name lookups for gameplay code
+                commands.entity(human).insert((skeleton, AnimationPlayer::default()));
+                Some((skinned_mesh, sorted_bones))
             }
-            for slot in asset.slots.iter() {
-                if transparent_slots.contains(slot) {
-                    material.alpha_mode = AlphaMode::Blend;
-                    material.reflectance = 0.25;
-                    if slot.contains("Eyebrow") { material.base_color = config.eyebrow_color; }
-                    else if slot.contains("Eye") && !slot.contains("Eyelash") { material.base_color = config.eye_color; }
-                    else if slot.contains("Hair") { material.base_color = config.hair_color; }
+            _ => None,
+        };
+        #[cfg(not(feature = "rig"))]
+        let rigged: Option<(SkinnedMesh, Vec<String>)> = None;
+
+        let (skinned_mesh, sorted_bones) = match rigged {
+            Some((skinned_mesh, sorted_bones)) => (Some(skinned_mesh), sorted_bones),
+            None => {
+                if config.rig != RigType::None {
+                    warn!("RigPlugin not added, spawning {:?} human without a rig", config.rig);
                 }
+                commands.entity(human).insert((
+                    TransformBundle::from_transform(transform),
+                    HumanExtents::rest(&helpers),
+                ));
+                (None, vec![])
             }
+        };
 
-            commands.spawn((
-                skinned_mesh.clone(),
-                PbrBundle {
-                    mesh: mesh_handle,
-                    material: materials.add(material),
-                    ..default()
-                },
-            ));
+        // Physics
+        if config.ragdoll.is_some() && skinned_mesh.is_none() {
+            warn!("Ragdolls need a rig, none generated for RigType::None");
+        }
+        #[cfg(feature = "rig")]
+        if let Some((settings, rigs)) = config.ragdoll.as_ref().zip(rigs.as_deref()).filter(|_| skinned_mesh.is_some()) {
+            let ragdoll = build_ragdoll(&config.rig, rigs, &vg, &helpers, settings);
+            #[cfg(any(feature = "rapier", feature = "avian"))]
+            {
+                let bone_entities: HashMap<String, Entity> = sorted_bones.iter().cloned()
+                    .zip(skinned_mesh.iter().flat_map(|s| s.joints.iter().copied()))
+                    .collect();
+                #[cfg(feature = "rapier")]
+                ragdoll::insert_rapier_components(&mut commands, human, &ragdoll, &bone_entities);
+                #[cfg(feature = "avian")]
+                ragdoll::insert_avian_components(&mut commands, human, &ragdoll, &bone_entities);
+            }
+            commands.entity(human).insert(ragdoll);
         }
 
-        // Equipment
-        for eq in config.equipment.iter() {
-            let err_msg = format!("FAILED TO FIND EQUIPMENT {}", eq);
-            let asset = registry.equipment.get(eq).expect(&err_msg);
-            delete_verts.extend(&asset.delete_verts);
-            let mesh = bake_asset_morphs(
-                &config.morph_targets, 
-                &targets,
-                &mut meshes,
-                &helpers,
-                &asset,
-            );
-            let mesh_handle = set_asset_rig_arrays(
-                config.rig,
-                mesh,
-                &rigs,
-                &asset.vertex_map,
-                &mut meshes,
-                &asset.helper_maps,
+        commands.entity(human).remove::<PendingHuman>();
+        if !has_visibility {
+            commands.entity(human).insert(VisibilityBundle::default());
+        }
+
+        if let Some(cached) = cached {
+            spawn_human_meshes(
+                &mut commands,
+                human,
+                cached.levels,
+                skinned_mesh.as_ref(),
                 &sorted_bones,
+                config.lod.as_ref(),
             );
-            let mut material = StandardMaterial::default();
-            if let Some(albedos) = asset_textures.albedo_maps.get(&asset.name) {
-                if albedos.len() > 0 { material.base_color_texture = Some(albedos[0].clone()); }
-            }
-            if let Some(normal) = asset_textures.normal_map.get(&asset.name) {
-                material.normal_map_texture = Some(normal.clone());
-            }
-            if let Some(ao) = asset_textures.ao_map.get(&asset.name) {
-                material.occlusion_texture = Some(ao.clone());
-            }
-            commands.spawn((
-                skinned_mesh.clone(),
-                PbrBundle {
-                    mesh: mesh_handle,
-                    material: materials.add(material),
-                    ..default()
-                },
-            ));
+            ready.send(HumanReady(human));
+            return;
         }
 
-        // Body Mesh
-        // Delete verts
-        let mesh = delete_mesh_verts(&mut meshes, &base_mesh, delete_verts);
-        let vertices = &get_vertex_positions(&mesh);
-        let new_vtx_map = generate_vertex_map(&base_mesh.vertices, vertices);
-
-        // Apply Morphs
-        let mesh = bake_body_morphs(&mesh,&new_vtx_map,&helpers);
-        // Apply Rig
-        let mesh_handle = set_basemesh_rig_arrays(
-            config.rig,
-            mesh,
-            &rigs,
-            &new_vtx_map,
-            &mut meshes,
-            &sorted_bones,
+        // Materials are cheap, meshes are baked in the background
+        let skinning = skinned_mesh.as_ref().map(|_| &config.skinning);
+        #[cfg(feature = "clothing")]
+        let (delete_verts, assets, mut asset_materials) = match clothing {
+            Some((registry, asset_textures)) => human_assets(
+                config,
+                registry,
+                asset_textures,
+                &meshes,
+                &mut materials,
+                &transparent_slots,
+                skinning,
+            ),
+            None => default(),
+        };
+        #[cfg(not(feature = "clothing"))]
+        let (delete_verts, assets, mut asset_materials) = (
+            HashSet::<u16>::new(),
+            Vec::<(Mesh, HumanMeshAsset)>::new(),
+            Vec::<HumanMaterial>::new(),
         );
+        if clothing.is_none() && !(config.body_parts.is_empty() && config.equipment.is_empty()) {
+            warn!("ClothingPlugin not added, skipping body parts and equipment");
+        }
+
+        // Body Material
+        let albedo = asset_server.load(source.asset_path(&Path::new("skin_textures/albedo").join(&config.skin_albedo)));
+        asset_materials.push(materials.add(StandardMaterial {
+            base_color_texture: Some(albedo),
+            ..default()
+        }, skinning));
 
-        // Spawn avatar as separate entity
-        commands.spawn((
-            skinned_mesh.clone(),
-            PbrBundle {
-                mesh: mesh_handle,
-                material: material.clone(),
-                ..default()
-            },
-        ));
-        commands.entity(human).remove::<SpawnTransform>();
-        commands.entity(human).insert(AnimationPlayer::default());
+        // Lower LODs share the skeleton
+        let proxy = config.lod.as_ref().and_then(|settings| {
+            #[cfg(feature = "clothing")]
+            let proxy = clothing.and_then(|(registry, _)| registry.proxies.get(&settings.proxy));
+            #[cfg(not(feature = "clothing"))]
+            let proxy: Option<&HumanMeshAsset> = None;
+            let Some(proxy) = proxy else {
+                warn!("No proxy {}, using the base body for LODs", settings.proxy);
+                return None;
+            };
+            Some((meshes.get(&proxy.mesh_handle).unwrap().clone(), proxy.clone()))
+        });
+
+        let input = BakeInput {
+            helpers: Arc::new(helpers),
+            #[cfg(feature = "rig")]
+            weights: rigs.as_ref().and_then(|rigs| rigs.weights.get(&config.rig).cloned()),
+            #[cfg(feature = "rig")]
+            influences: config.skinning.influences(),
+            sorted_bones: Arc::new(sorted_bones),
+            assets,
+            body: meshes.get(&base_mesh.mesh_handle).unwrap().clone(),
+            body_vertices: base_mesh.vertices.clone(),
+            body_vertex_map: base_mesh.vertex_map.clone(),
+            delete_verts,
+            proxy,
+            lod: config.lod.clone(),
+        };
+        commands.entity(human).insert(HumanBaking::new(input, key, skinned_mesh, asset_materials));
     })
 
 }
 
+// Meshes and materials of the body parts and equipment, and the body vertices they hide
+#[cfg(feature = "clothing")]
+fn human_assets(
+    config: &HumanConfig,
+    registry: &HumanAssetRegistry,
+    asset_textures: &HumanAssetTextures,
+    meshes: &Assets<Mesh>,
+    materials: &mut HumanMaterials,
+    transparent_slots: &[String],
+    skinning: Option<&SkinningSettings>,
+) -> (HashSet<u16>, Vec<(Mesh, HumanMeshAsset)>, Vec<HumanMaterial>) {
+    let mut delete_verts = HashSet::<u16>::new();
+    let mut assets = Vec::<(Mesh, HumanMeshAsset)>::new();
+    let mut asset_materials = Vec::<HumanMaterial>::new();
+
+    // Body Parts
+    for bp in config.body_parts.iter() {
+        let err_msg = format!("FAILED TO FIND BODY PART {}", bp);
+        let asset = registry.body_parts.get(bp).expect(&err_msg);
+        delete_verts.extend(&asset.delete_verts);
+        assets.push((meshes.get(&asset.mesh_handle).unwrap().clone(), asset.clone()));
+        let mut material = asset_material(asset, asset_textures);
+        for slot in asset.slots.iter() {
+            if transparent_slots.contains(slot) {
+                material.alpha_mode = AlphaMode::Blend;
+                material.reflectance = 0.25;
+                if slot.contains("Eyebrow") { material.base_color = config.eyebrow_color; }
+                else if slot.contains("Eye") && !slot.contains("Eyelash") { material.base_color = config.eye_color; }
+                else if slot.contains("Hair") { material.base_color = config.hair_color; }
+            }
+        }
+        asset_materials.push(materials.add(material, skinning));
+    }
+
+    // Equipment
+    for eq in config.equipment.iter() {
+        let err_msg = format!("FAILED TO FIND EQUIPMENT {}", eq);
+        let asset = registry.equipment.get(eq).expect(&err_msg);
+        delete_verts.extend(&asset.delete_verts);
+        assets.push((meshes.get(&asset.mesh_handle).unwrap().clone(), asset.clone()));
+        asset_materials.push(materials.add(asset_material(asset, asset_textures), skinning));
+    }
+    (delete_verts, assets, asset_materials)
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn spawn_human_adds_a_transform() {
+        let mut world = World::new();
+        let human = world.run_system_once(|mut commands: Commands| {
+            commands.spawn_human(HumanConfig::default()).id()
+        });
+        let human = world.entity(human);
+        assert!(human.contains::<HumanConfig>());
+        assert_eq!(human.get::<Transform>(), Some(&Transform::IDENTITY));
+        assert!(human.contains::<GlobalTransform>());
+        #[cfg(feature = "render")]
+        assert!(human.contains::<Visibility>());
+    }
+
+    #[test]
+    fn placed_humans_keep_their_transform() {
+        let mut world = World::new();
+        let transform = Transform::from_xyz(2.0, 0.0, -1.0);
+        let human = world.run_system_once(move |mut commands: Commands| {
+            commands.spawn_human(HumanConfig::default()).insert(transform).id()
+        });
+        assert_eq!(world.get::<Transform>(human), Some(&transform));
+    }
+
+    #[test]
+    fn new_humans_wait_until_built() {
+        let mut world = World::new();
+        let human = world.run_system_once(|mut commands: Commands| {
+            commands.spawn_human(HumanConfig::default()).id()
+        });
+        let bare = world.spawn(HumanConfig::default()).id();
+        let mut queue = IntoSystem::into_system(queue_new_humans);
+        queue.initialize(&mut world);
+        queue.run((), &mut world);
+        queue.apply_deferred(&mut world);
+        assert!(world.get::<PendingHuman>(human).is_some());
+        assert!(world.get::<PendingHuman>(bare).is_some());
+
+        // Only new configs are picked up, so built humans aren't queued again
+        world.entity_mut(human).remove::<PendingHuman>();
+        queue.run((), &mut world);
+        queue.apply_deferred(&mut world);
+        assert!(world.get::<PendingHuman>(human).is_none());
+        assert!(world.get::<PendingHuman>(bare).is_some());
+    }
+}
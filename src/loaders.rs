@@ -0,0 +1,104 @@
+use bevy::{
+    asset::{
+        io::Reader,
+        AssetLoader,
+        AsyncReadExt,
+        LoadContext,
+    },
+    prelude::*,
+};
+use std::collections::HashMap;
+use crate::{
+    assets::parse_mhclo,
+    morphs::parse_target,
+    rigs::{ parse_rig_file, RigFile },
+    HumanMeshAsset,
+};
+
+/*----------+
+ |  Assets  |
+ +----------*/
+// Vertex offsets of one .target file
+#[derive(Asset, TypePath)]
+pub struct TargetAsset(pub(crate) HashMap<u16, Vec3>);
+
+// Parsed .mhclo or .proxy file.  The obj mesh is not loaded.
+#[derive(Asset, TypePath)]
+pub struct MhcloAsset(pub(crate) HumanMeshAsset);
+
+// rig.*.json or weights.*.json
+#[derive(Asset, TypePath)]
+pub struct RigAsset(pub(crate) RigFile);
+
+/*-----------+
+ |  Loaders  |
+ +-----------*/
+#[derive(Default)]
+pub struct TargetLoader;
+
+impl AssetLoader for TargetLoader {
+    type Asset = TargetAsset;
+    type Settings = ();
+    type Error = std::io::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<TargetAsset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(TargetAsset(parse_target(bytes.as_slice())))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["target"]
+    }
+}
+
+#[derive(Default)]
+pub struct MhcloLoader;
+
+impl AssetLoader for MhcloLoader {
+    type Asset = MhcloAsset;
+    type Settings = ();
+    type Error = std::io::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<MhcloAsset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(MhcloAsset(parse_mhclo(bytes.as_slice(), load_context.path())))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mhclo", "proxy"]
+    }
+}
+
+// Rig configs with or without the bones wrapper, and weights files.  Found by asset type
+// only, so other .json assets of the app are left to their own loaders.
+#[derive(Default)]
+pub struct RigLoader;
+
+impl AssetLoader for RigLoader {
+    type Asset = RigAsset;
+    type Settings = ();
+    type Error = serde_json::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<RigAsset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(serde_json::Error::io)?;
+        Ok(RigAsset(parse_rig_file(&bytes)?))
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(feature = "render")]
+use bevy::{
+    animation::AnimationTarget,
+    prelude::*,
+};
+use serde::{ Deserialize, Serialize };
+
+// Bones that stop animating at low LODs when freeze_extremity_bones is set
+#[cfg(feature = "render")]
+const EXTREMITY_BONES: [&str; 7] = ["finger", "thumb", "index", "middle", "ring", "pinky", "toe"];
+
+/*---------+
+ |  Types  |
+ +---------*/
+#[cfg(feature = "render")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HumanLodLevel {
+    // Base body with all body parts and equipment
+    Full,
+    // Proxy body, body parts and equipment simplified by proxy_ratio
+    Proxy,
+    // Proxy body and everything else simplified by decimation
+    Decimated,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LodSettings {
+    // Proxy name in HumanAssetRegistry::proxies.  Falls back to the base body when missing.
+    pub proxy: String,
+    // Share of vertices kept for body parts and equipment at the proxy level
+    pub proxy_ratio: f32,
+    // Share of vertices kept for everything at the decimated level
+    pub decimation: f32,
+    // Camera distances where the proxy and decimated levels start
+    pub distances: [f32; 2],
+    // Finger and toe bones keep their last pose below the full level.  They stay in the
+    // skin, lower levels just stop animating them.
+    pub freeze_extremity_bones: bool,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            proxy: "proxy741".to_string(),
+            proxy_ratio: 0.5,
+            decimation: 0.25,
+            distances: [8.0, 25.0],
+            freeze_extremity_bones: true,
+        }
+    }
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+// All levels are spawned as children of the human, skinned to the same joints.
+// Only the current level is visible.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct HumanLod {
+    pub settings: LodSettings,
+    level: HumanLodLevel,
+    meshes: [Vec<Entity>; 3],
+    extremity_bones: Vec<Entity>,
+    frozen: Vec<(Entity, AnimationTarget)>,
+}
+
+#[cfg(feature = "render")]
+impl HumanLod {
+    pub(crate) fn new(settings: LodSettings, meshes: [Vec<Entity>; 3], bones: &[(String, Entity)]) -> Self {
+        let extremity_bones = bones.iter()
+            .filter(|(name, _)| is_extremity_bone(name))
+            .map(|(_, entity)| *entity)
+            .collect();
+        HumanLod {
+            settings,
+            level: HumanLodLevel::Full,
+            meshes,
+            extremity_bones,
+            frozen: vec![],
+        }
+    }
+
+    pub fn level(&self) -> HumanLodLevel {
+        self.level
+    }
+
+    fn level_for_distance(&self, distance: f32) -> HumanLodLevel {
+        if distance >= self.settings.distances[1] { HumanLodLevel::Decimated }
+        else if distance >= self.settings.distances[0] { HumanLodLevel::Proxy }
+        else { HumanLodLevel::Full }
+    }
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+#[cfg(feature = "render")]
+pub(crate) fn update_human_lods(
+    mut humans: Query<(&mut HumanLod, &GlobalTransform)>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut visibilities: Query<&mut Visibility>,
+    targets: Query<&AnimationTarget>,
+    mut commands: Commands,
+) {
+    let Some((_, camera)) = cameras.iter().find(|(c, _)| c.is_active) else { return };
+    for (mut lod, transform) in humans.iter_mut() {
+        let level = lod.level_for_distance(camera.translation().distance(transform.translation()));
+        if level == lod.level { continue; }
+
+        for (i, entities) in lod.meshes.iter().enumerate() {
+            let visibility = if i == level as usize { Visibility::Inherited } else { Visibility::Hidden };
+            for &entity in entities.iter() {
+                if let Ok(mut v) = visibilities.get_mut(entity) { *v = visibility; }
+            }
+        }
+
+        if lod.settings.freeze_extremity_bones {
+            if level == HumanLodLevel::Full {
+                for (bone, target) in lod.frozen.drain(..) {
+                    commands.entity(bone).insert(target);
+                }
+            } else if lod.frozen.is_empty() {
+                let frozen: Vec<(Entity, AnimationTarget)> = lod.extremity_bones.iter()
+                    .filter_map(|&bone| targets.get(bone).ok().map(|t| (bone, *t)))
+                    .collect();
+                for (bone, _) in frozen.iter() {
+                    commands.entity(*bone).remove::<AnimationTarget>();
+                }
+                lod.frozen = frozen;
+            }
+        }
+        lod.level = level;
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+#[cfg(feature = "render")]
+fn is_extremity_bone(name: &str) -> bool {
+    let name = name.to_lowercase();
+    EXTREMITY_BONES.iter().any(|b| name.contains(b))
+}
@@ -0,0 +1,696 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use std::{
+    collections::HashMap,
+    path::Path,
+};
+use crate::{
+    basemesh::BODY_VERTICES,
+    morphs::adjust_helpers_to_morphs,
+    BaseMesh,
+    HumanConfig,
+    HumentityGlobalConfig,
+    MorphTargets,
+    VertexGroups,
+};
+
+// MakeHuman's ruler, measure name without the measure- prefix -> vertices in tape order
+const RULER_FILE: &str = "measurements.json";
+
+/*---------+
+ |  Types  |
+ +---------*/
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Measurement {
+    Stature,
+    NeckCirc,
+    BustCirc,
+    UnderbustCirc,
+    WaistCirc,
+    HipsCirc,
+    UpperArmCirc,
+    WristCirc,
+    ThighCirc,
+    KneeCirc,
+    CalfCirc,
+    AnkleCirc,
+    ShoulderDist,
+    UpperArmLength,
+    LowerArmLength,
+    UpperLegLength,
+    LowerLegLength,
+}
+
+impl Measurement {
+    pub const ALL: [Measurement; 17] = [
+        Measurement::Stature,
+        Measurement::NeckCirc,
+        Measurement::BustCirc,
+        Measurement::UnderbustCirc,
+        Measurement::WaistCirc,
+        Measurement::HipsCirc,
+        Measurement::UpperArmCirc,
+        Measurement::WristCirc,
+        Measurement::ThighCirc,
+        Measurement::KneeCirc,
+        Measurement::CalfCirc,
+        Measurement::AnkleCirc,
+        Measurement::ShoulderDist,
+        Measurement::UpperArmLength,
+        Measurement::LowerArmLength,
+        Measurement::UpperLegLength,
+        Measurement::LowerLegLength,
+    ];
+
+    // Name of the measure-*-incr/decr target pair, without the suffix
+    pub fn target(&self) -> Option<&'static str> {
+        match self {
+            Measurement::Stature => None,
+            Measurement::NeckCirc => Some("measure-neck-circ"),
+            Measurement::BustCirc => Some("measure-bust-circ"),
+            Measurement::UnderbustCirc => Some("measure-underbust-circ"),
+            Measurement::WaistCirc => Some("measure-waist-circ"),
+            Measurement::HipsCirc => Some("measure-hips-circ"),
+            Measurement::UpperArmCirc => Some("measure-upperarm-circ"),
+            Measurement::WristCirc => Some("measure-wrist-circ"),
+            Measurement::ThighCirc => Some("measure-thigh-circ"),
+            Measurement::KneeCirc => Some("measure-knee-circ"),
+            Measurement::CalfCirc => Some("measure-calf-circ"),
+            Measurement::AnkleCirc => Some("measure-ankle-circ"),
+            Measurement::ShoulderDist => Some("measure-shoulder-dist"),
+            Measurement::UpperArmLength => Some("measure-upperarm-length"),
+            Measurement::LowerArmLength => Some("measure-lowerarm-length"),
+            Measurement::UpperLegLength => Some("measure-upperleg-height"),
+            Measurement::LowerLegLength => Some("measure-lowerleg-height"),
+        }
+    }
+
+    // Limb measurements are taken on the left side
+    fn joints(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Measurement::ShoulderDist => Some(("joint-l-shoulder", "joint-r-shoulder")),
+            Measurement::UpperArmLength => Some(("joint-l-shoulder", "joint-l-elbow")),
+            Measurement::LowerArmLength => Some(("joint-l-elbow", "joint-l-hand")),
+            Measurement::UpperLegLength => Some(("joint-l-upper-leg", "joint-l-knee")),
+            Measurement::LowerLegLength => Some(("joint-l-knee", "joint-l-ankle")),
+            _ => None,
+        }
+    }
+
+    fn is_limb(&self) -> bool {
+        matches!(self,
+            Measurement::UpperArmCirc |
+            Measurement::WristCirc |
+            Measurement::ThighCirc |
+            Measurement::KneeCirc |
+            Measurement::CalfCirc |
+            Measurement::AnkleCirc
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FitSettings {
+    // Max absolute error per measurement, in meters
+    pub tolerance: f32,
+    pub max_iterations: usize,
+}
+
+impl Default for FitSettings {
+    fn default() -> Self {
+        FitSettings {
+            tolerance: 0.005,
+            max_iterations: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MeasurementFit {
+    // Measured minus requested, in meters
+    pub residuals: HashMap<Measurement, f32>,
+    pub converged: bool,
+    pub iterations: usize,
+}
+
+// Vertices a circumference is measured around
+#[derive(Clone, Debug)]
+enum TapePath {
+    // MakeHuman's own list, measured vertex to vertex in order like MakeHuman does
+    Ruler(Vec<u16>),
+    // Band of vertices the measure-*-circ target moves the most, when the ruler has no list
+    Band(Vec<u16>),
+}
+
+impl TapePath {
+    fn length(&self, helpers: &[Vec3]) -> f32 {
+        match self {
+            // Vertex to vertex in list order, like MakeHuman's Ruler
+            TapePath::Ruler(vertices) => vertices.windows(2)
+                .map(|pair| helpers[pair[0] as usize].distance(helpers[pair[1] as usize]))
+                .sum(),
+            TapePath::Band(vertices) => {
+                let points: Vec<Vec3> = vertices.iter().map(|&v| helpers[v as usize]).collect();
+                circumference(&points)
+            }
+        }
+    }
+}
+
+// A signed slider over a pair of targets.  Positive values use the high target.
+struct Driver {
+    low: String,
+    high: String,
+}
+
+/*-------------+
+ |  Resources  |
+ +-------------*/
+// Vertices to run the tape measure around, per circumference
+#[derive(Resource)]
+pub(crate) struct MeasurementLoops(HashMap<Measurement, TapePath>);
+
+impl FromWorld for MeasurementLoops {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.get_resource::<HumentityGlobalConfig>().expect("NO CONFIG LOADED");
+        let targets = world.get_resource::<MorphTargets>().expect("Morph targets not loaded");
+        let base_mesh = world.get_resource::<BaseMesh>().expect("Base mesh not loaded");
+        let vg = world.get_resource::<VertexGroups>().expect("Vertex groups not loaded");
+        let left = joint_position(vg, &base_mesh.vertices, "joint-l-shoulder").x.signum();
+
+        let mut ruler = match config.source.read(Path::new(RULER_FILE)) {
+            Ok(json) => serde_json::from_slice::<HashMap<String, Vec<u16>>>(&json)
+                .expect("FAILED TO READ MEASUREMENTS JSON"),
+            Err(_) => {
+                warn!("No {}, circumferences are estimated from the measure targets", RULER_FILE);
+                HashMap::new()
+            }
+        };
+
+        let has_ruler = !ruler.is_empty();
+        let mut loops = HashMap::<Measurement, TapePath>::new();
+        for measurement in Measurement::ALL.iter() {
+            if measurement.joints().is_some() { continue; }
+            let Some(name) = measurement.target() else { continue };
+            let key = name.trim_start_matches("measure-");
+            match ruler.remove(key) {
+                Some(vertices) if vertices.iter().all(|&v| v < BODY_VERTICES) => {
+                    loops.insert(*measurement, TapePath::Ruler(vertices));
+                    continue;
+                }
+                Some(_) => warn!("Ruler list {} has vertices off the body, estimating it", key),
+                None if has_ruler => warn!("No ruler list for {}, estimating it", key),
+                None => {}
+            }
+
+            let Some(target) = targets.0.get(&(name.to_string() + "-incr")) else {
+                warn!("Missing target {}-incr, can't measure {:?}", name, measurement);
+                continue;
+            };
+            let max = target.values().map(|v| v.length()).fold(0.0, f32::max);
+            let mut vertices: Vec<u16> = target.iter()
+                .filter(|(&vtx, offset)| vtx < BODY_VERTICES && offset.length() >= 0.5 * max)
+                .filter(|(&vtx, _)| !measurement.is_limb() || base_mesh.vertices[vtx as usize].x.signum() == left)
+                .map(|(&vtx, _)| vtx)
+                .collect();
+            vertices.sort();
+            loops.insert(*measurement, TapePath::Band(vertices));
+        }
+        MeasurementLoops(loops)
+    }
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+// Dimensions of a human in meters.  Helper positions are already
+// in meters since target offsets are scaled by BODY_SCALE on load.
+#[derive(Component, Clone, Debug, Default)]
+pub struct BodyMeasurements(pub HashMap<Measurement, f32>);
+
+impl BodyMeasurements {
+    pub fn get(&self, measurement: Measurement) -> f32 {
+        *self.0.get(&measurement).unwrap_or(&0.0)
+    }
+
+    pub fn stature(&self) -> f32 { self.get(Measurement::Stature) }
+    pub fn bust(&self) -> f32 { self.get(Measurement::BustCirc) }
+    pub fn waist(&self) -> f32 { self.get(Measurement::WaistCirc) }
+    pub fn hips(&self) -> f32 { self.get(Measurement::HipsCirc) }
+}
+
+/*----------------+
+ |  System Params  |
+ +----------------*/
+#[derive(SystemParam)]
+pub struct HumanMeasurer<'w> {
+    targets: Res<'w, MorphTargets>,
+    base_mesh: Res<'w, BaseMesh>,
+    loops: Res<'w, MeasurementLoops>,
+    vg: Res<'w, VertexGroups>,
+}
+
+impl<'w> HumanMeasurer<'w> {
+    pub fn measure(&self, config: &HumanConfig) -> BodyMeasurements {
+        let helpers = adjust_helpers_to_morphs(&config.morph_targets, &self.targets, &self.base_mesh);
+        measure_body(&helpers, &self.loops, &self.vg)
+    }
+
+    // Adjusts the measure-* targets, plus the macro height and weight targets
+    // for the config's gender and age, until the body matches the goals.
+    pub fn fit(
+        &self,
+        config: &mut HumanConfig,
+        goals: &HashMap<Measurement, f32>,
+        settings: FitSettings,
+    ) -> MeasurementFit {
+        let drivers = self.drivers(config, goals);
+
+        // Start from whatever the config already has on the driven targets
+        let mut x: Vec<f32> = drivers.iter().map(|d| {
+            config.morph_targets.get(&d.high).unwrap_or(&0.0) - config.morph_targets.get(&d.low).unwrap_or(&0.0)
+        }).collect();
+        let mut fixed = config.morph_targets.clone();
+        for driver in drivers.iter() {
+            fixed.remove(&driver.low);
+            fixed.remove(&driver.high);
+        }
+        let base = adjust_helpers_to_morphs(&fixed, &self.targets, &self.base_mesh);
+
+        let goal_list: Vec<(Measurement, f32)> = goals.iter().map(|(&m, &v)| (m, v)).collect();
+        let residuals = |x: &[f32]| -> Vec<f32> {
+            let helpers = self.apply_drivers(&base, &drivers, x);
+            let measured = measure_body(&helpers, &self.loops, &self.vg);
+            goal_list.iter().map(|(m, goal)| measured.get(*m) - goal).collect()
+        };
+
+        // Levenberg-Marquardt with a forward difference jacobian
+        let step = 0.01;
+        let mut damping = 1e-3;
+        let mut r = residuals(&x);
+        let mut iterations = 0;
+        while iterations < settings.max_iterations {
+            if r.iter().all(|e| e.abs() <= settings.tolerance) { break; }
+            iterations += 1;
+
+            let mut jacobian = vec![vec![0.0; x.len()]; r.len()];
+            for j in 0..x.len() {
+                let h = if x[j] < 1.0 - step { step } else { -step };
+                let mut xh = x.clone();
+                xh[j] += h;
+                let rh = residuals(&xh);
+                for i in 0..r.len() { jacobian[i][j] = (rh[i] - r[i]) / h; }
+            }
+
+            let n = x.len();
+            let mut jtj = vec![vec![0.0; n]; n];
+            let mut jtr = vec![0.0; n];
+            for i in 0..r.len() {
+                for a in 0..n {
+                    jtr[a] += jacobian[i][a] * r[i];
+                    for b in 0..n { jtj[a][b] += jacobian[i][a] * jacobian[i][b]; }
+                }
+            }
+
+            let mut improved = false;
+            while damping < 1e6 {
+                let mut lhs = jtj.clone();
+                for a in 0..n { lhs[a][a] += damping * (jtj[a][a] + 1e-6); }
+                let rhs: Vec<f32> = jtr.iter().map(|v| -v).collect();
+                let Some(dx) = solve_linear(lhs, rhs) else { damping *= 10.0; continue };
+                let candidate: Vec<f32> = x.iter().zip(dx.iter()).map(|(a, d)| (a + d).clamp(-1.0, 1.0)).collect();
+                let rc = residuals(&candidate);
+                if sum_squares(&rc) < sum_squares(&r) {
+                    x = candidate;
+                    r = rc;
+                    damping = (damping * 0.3).max(1e-7);
+                    improved = true;
+                    break;
+                }
+                damping *= 10.0;
+            }
+            if !improved { break; }
+        }
+
+        for (driver, &value) in drivers.iter().zip(x.iter()) {
+            config.morph_targets.remove(&driver.low);
+            config.morph_targets.remove(&driver.high);
+            if value > 0.0 { config.morph_targets.insert(driver.high.clone(), value); }
+            if value < 0.0 { config.morph_targets.insert(driver.low.clone(), -value); }
+        }
+
+        MeasurementFit {
+            residuals: goal_list.iter().zip(r.iter()).map(|((m, _), &e)| (*m, e)).collect(),
+            converged: r.iter().all(|e| e.abs() <= settings.tolerance),
+            iterations,
+        }
+    }
+
+    fn drivers(&self, config: &HumanConfig, goals: &HashMap<Measurement, f32>) -> Vec<Driver> {
+        let mut drivers = Vec::<Driver>::new();
+        for measurement in Measurement::ALL.iter() {
+            if !goals.contains_key(measurement) { continue; }
+            let Some(name) = measurement.target() else { continue };
+            drivers.push(Driver {
+                low: name.to_string() + "-decr",
+                high: name.to_string() + "-incr",
+            });
+        }
+
+        let (gender, age) = macro_gender_age(&config.morph_targets);
+        if goals.contains_key(&Measurement::Stature) {
+            let prefix = format!("{}-{}-averagemuscle-averageweight", gender, age);
+            drivers.push(Driver {
+                low: prefix.clone() + "-minheight",
+                high: prefix + "-maxheight",
+            });
+        }
+        if goals.keys().any(|m| m.joints().is_none() && *m != Measurement::Stature) {
+            let prefix = format!("universal-{}-{}-averagemuscle", gender, age);
+            drivers.push(Driver {
+                low: prefix.clone() + "-minweight",
+                high: prefix + "-maxweight",
+            });
+        }
+
+        drivers.retain(|d| {
+            let found = self.targets.0.contains_key(&d.low) && self.targets.0.contains_key(&d.high);
+            if !found { warn!("Missing targets {} / {}, not fitting with them", d.low, d.high); }
+            found
+        });
+        drivers
+    }
+
+    fn apply_drivers(&self, base: &[Vec3], drivers: &[Driver], x: &[f32]) -> Vec<Vec3> {
+        let mut helpers = base.to_vec();
+        for (driver, &value) in drivers.iter().zip(x.iter()) {
+            let (name, weight) = if value >= 0.0 { (&driver.high, value) } else { (&driver.low, -value) };
+            if weight == 0.0 { continue; }
+            for (&vertex, &offset) in self.targets.0.get(name).unwrap().iter() {
+                helpers[vertex as usize] += offset * weight;
+            }
+        }
+        helpers
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+pub(crate) fn measure_body(
+    helpers: &[Vec3],
+    loops: &MeasurementLoops,
+    vg: &VertexGroups,
+) -> BodyMeasurements {
+    let mut measurements = HashMap::<Measurement, f32>::new();
+
+    let (min, max) = helpers[..BODY_VERTICES as usize].iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(v.y), max.max(v.y)));
+    measurements.insert(Measurement::Stature, max - min);
+
+    for measurement in Measurement::ALL.iter() {
+        if let Some((a, b)) = measurement.joints() {
+            let length = (joint_position(vg, helpers, a) - joint_position(vg, helpers, b)).length();
+            measurements.insert(*measurement, length);
+        } else if let Some(path) = loops.0.get(measurement) {
+            measurements.insert(*measurement, path.length(helpers));
+        }
+    }
+    BodyMeasurements(measurements)
+}
+
+// Gender and age of the heaviest race-gender-age target in the config
+fn macro_gender_age(morph_targets: &HashMap<String, f32>) -> (&'static str, &'static str) {
+    let mut best = (0.0, "female", "young");
+    for (name, &value) in morph_targets.iter() {
+        let parts: Vec<&str> = name.split('-').collect();
+        if parts.len() != 3 || value <= best.0 { continue; }
+        let Some(gender) = ["female", "male"].into_iter().find(|g| *g == parts[1]) else { continue };
+        let Some(age) = ["baby", "child", "young", "old"].into_iter().find(|a| *a == parts[2]) else { continue };
+        best = (value, gender, age);
+    }
+    (best.1, best.2)
+}
+
+fn sum_squares(values: &[f32]) -> f32 {
+    values.iter().map(|v| v * v).sum()
+}
+
+// Gaussian elimination with partial pivoting
+fn solve_linear(mut a: Vec<Vec<f32>>, mut b: Vec<f32>) -> Option<Vec<f32>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 { return None; }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let f = a[row][col] / pivot_row[col];
+            for (v, p) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) { *v -= f * p; }
+            b[row] -= f * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f32 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+// Same point the rig uses for CUBE bone strategies
+pub(crate) fn joint_position(vg: &VertexGroups, helpers: &[Vec3], joint: &str) -> Vec3 {
+    let err_msg = format!("No joint cube {}", joint);
+    let ranges = vg.0.get(joint).expect(&err_msg);
+    let vertices: Vec<usize> = ranges.iter().flat_map(|r| r[0]..=r[1]).collect();
+    vertices.iter().map(|&i| helpers[i]).sum::<Vec3>() / vertices.len() as f32
+}
+
+// A tape measure follows the convex hull of the loop, projected on its best fit plane
+fn circumference(points: &[Vec3]) -> f32 {
+    if points.len() < 3 { return 0.0; }
+    let center = points.iter().sum::<Vec3>() / points.len() as f32;
+
+    // Plane normal is the direction of least variance.  Inverse power iteration
+    // on the covariance matrix, slightly regularized since loops can be flat.
+    let mut cov = Mat3::ZERO;
+    for p in points.iter() {
+        let d = *p - center;
+        cov += Mat3::from_cols(d * d.x, d * d.y, d * d.z);
+    }
+    let inv = (cov + Mat3::IDENTITY * 1e-6).inverse();
+    let mut normal = Vec3::ONE.normalize();
+    for _ in 0..16 {
+        normal = (inv * normal).normalize_or_zero();
+    }
+
+    if normal == Vec3::ZERO { return 0.0; }
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    let mut projected: Vec<Vec2> = points.iter()
+        .map(|p| Vec2::new((*p - center).dot(u), (*p - center).dot(v)))
+        .collect();
+    let hull = convex_hull(&mut projected);
+    hull.iter()
+        .zip(hull.iter().cycle().skip(1))
+        .map(|(a, b)| a.distance(*b))
+        .sum()
+}
+
+// Monotone chain
+fn convex_hull(points: &mut [Vec2]) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    let mut lower = half_hull(points.iter());
+    let mut upper = half_hull(points.iter().rev());
+    // Each half ends where the other starts
+    lower.pop();
+    upper.pop();
+    lower.extend(upper);
+    lower
+}
+
+fn half_hull<'a>(points: impl Iterator<Item = &'a Vec2>) -> Vec<Vec2> {
+    let mut hull = Vec::<Vec2>::new();
+    for &p in points {
+        while hull.len() >= 2 && (hull[hull.len() - 1] - hull[hull.len() - 2]).perp_dot(p - hull[hull.len() - 2]) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(p);
+    }
+    hull
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::f32::consts::TAU;
+
+    const WAIST: std::ops::Range<u16> = 100..164;
+    const HIPS: std::ops::Range<u16> = 200..264;
+    const HEIGHT: &str = "female-young-averagemuscle-averageweight";
+
+    // Points around a circle on the XZ plane, at height y
+    fn ring(radius: f32, y: f32, count: u16) -> Vec<Vec3> {
+        (0..count).map(|i| {
+            let angle = TAU * i as f32 / count as f32;
+            Vec3::new(radius * angle.cos(), y, radius * angle.sin())
+        }).collect()
+    }
+
+    // A 1.8m body with waist and hips rings, measure-*-circ pairs that scale them,
+    // and macro height targets that move the top of the head
+    fn body_world() -> World {
+        let mut vertices = vec![Vec3::ZERO; BODY_VERTICES as usize];
+        vertices[0] = Vec3::Y * 1.8;
+        for (vtx, p) in WAIST.zip(ring(0.12, 1.0, WAIST.len() as u16)) {
+            vertices[vtx as usize] = p;
+        }
+        for (vtx, p) in HIPS.zip(ring(0.15, 0.9, HIPS.len() as u16)) {
+            vertices[vtx as usize] = p;
+        }
+        let scale = |range: std::ops::Range<u16>, factor: f32| -> HashMap<u16, Vec3> {
+            range.map(|vtx| (vtx, vertices[vtx as usize] * Vec3::new(factor, 0.0, factor))).collect()
+        };
+
+        let mut targets = HashMap::new();
+        targets.insert("measure-waist-circ-incr".to_string(), scale(WAIST, 0.5));
+        targets.insert("measure-waist-circ-decr".to_string(), scale(WAIST, -0.3));
+        targets.insert("measure-hips-circ-incr".to_string(), scale(HIPS, 0.4));
+        targets.insert("measure-hips-circ-decr".to_string(), scale(HIPS, -0.3));
+        targets.insert(format!("{}-maxheight", HEIGHT), HashMap::from([(0, Vec3::Y * 0.2)]));
+        targets.insert(format!("{}-minheight", HEIGHT), HashMap::from([(0, Vec3::Y * -0.2)]));
+        targets.insert("head-age-less".to_string(), HashMap::new());
+        let vg = Measurement::ALL.iter()
+            .filter_map(|m| m.joints())
+            .flat_map(|(a, b)| [a, b])
+            .map(|joint| (joint.to_string(), vec![[0, 0]]))
+            .collect();
+        let loops = HashMap::from([
+            (Measurement::WaistCirc, TapePath::Band(WAIST.collect())),
+            (Measurement::HipsCirc, TapePath::Band(HIPS.collect())),
+        ]);
+
+        let mut world = World::new();
+        world.insert_resource(MorphTargets(targets));
+        world.insert_resource(BaseMesh {
+            #[cfg(feature = "render")]
+            mesh_handle: Handle::default(),
+            vertices,
+            #[cfg(feature = "render")]
+            vertex_map: HashMap::new(),
+        });
+        world.insert_resource(VertexGroups(vg));
+        world.insert_resource(MeasurementLoops(loops));
+        world
+    }
+
+    fn fit_goals(
+        mut config: HumanConfig,
+        goals: HashMap<Measurement, f32>,
+    ) -> (HumanConfig, MeasurementFit, BodyMeasurements) {
+        body_world().run_system_once(move |measurer: HumanMeasurer| {
+            let fit = measurer.fit(&mut config, &goals, FitSettings::default());
+            let measured = measurer.measure(&config);
+            (config.clone(), fit, measured)
+        })
+    }
+
+    fn fit(goal: f32) -> (HumanConfig, MeasurementFit, BodyMeasurements) {
+        fit_goals(HumanConfig::default(), HashMap::from([(Measurement::WaistCirc, goal)]))
+    }
+
+    #[test]
+    fn ruler_follows_list_order() {
+        let helpers = [Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0), Vec3::Y];
+        // Around the square, then back and forth across its diagonal
+        assert!((TapePath::Ruler(vec![0, 1, 2, 3, 0]).length(&helpers) - 4.0).abs() < 1e-5);
+        assert!((TapePath::Ruler(vec![0, 2, 1, 3]).length(&helpers) - (1.0 + 2.0 * 2f32.sqrt())).abs() < 1e-5);
+    }
+
+    #[test]
+    fn band_wraps_outside_of_loop() {
+        // Tilted ring with points inside it, listed out of order
+        let tilt = Quat::from_rotation_x(0.4) * Quat::from_rotation_z(-0.7);
+        let mut helpers: Vec<Vec3> = ring(0.2, 0.0, 48).into_iter().rev().collect();
+        helpers.extend(ring(0.1, 0.01, 16));
+        helpers.push(Vec3::ZERO);
+        let helpers: Vec<Vec3> = helpers.into_iter().map(|p| tilt * p + Vec3::ONE).collect();
+
+        let length = TapePath::Band((0..helpers.len() as u16).collect()).length(&helpers);
+        assert!((length - TAU * 0.2).abs() < 0.01, "{}", length);
+    }
+
+    #[test]
+    fn solves_linear_systems() {
+        let a = vec![vec![0.0, 2.0, 1.0], vec![1.0, 1.0, 0.0], vec![3.0, 0.0, 1.0]];
+        let x = solve_linear(a, vec![7.0, 3.0, 6.0]).unwrap();
+        for (got, want) in x.iter().zip([1.0, 2.0, 3.0]) {
+            assert!((got - want).abs() < 1e-5, "{:?}", x);
+        }
+        assert!(solve_linear(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]).is_none());
+    }
+
+    #[test]
+    fn fit_converges_both_ways() {
+        for goal in [0.9, 0.65] {
+            let (config, fit, measured) = fit(goal);
+            assert!(fit.converged, "{:?}", fit);
+            let residual = fit.residuals[&Measurement::WaistCirc];
+            assert!(residual.abs() <= FitSettings::default().tolerance);
+            // Residuals match what the fitted config measures
+            assert!((measured.waist() - goal - residual).abs() < 1e-4);
+            // Only one side of the pair is set
+            let incr = config.morph_targets.contains_key("measure-waist-circ-incr");
+            let decr = config.morph_targets.contains_key("measure-waist-circ-decr");
+            assert_eq!((incr, decr), (goal > TAU * 0.12, goal < TAU * 0.12));
+        }
+    }
+
+    #[test]
+    fn fit_reports_unreachable_goals() {
+        // Past the incr target at full weight
+        let (config, fit, _) = fit(2.0);
+        assert!(!fit.converged);
+        assert!(fit.residuals[&Measurement::WaistCirc] < -0.5);
+        assert!((config.morph_targets["measure-waist-circ-incr"] - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fit_solves_goals_together() {
+        let mut config = HumanConfig::default();
+        config.morph_targets.insert("head-age-less".to_string(), 0.7);
+        // Starts from the config's own values
+        config.morph_targets.insert("measure-waist-circ-decr".to_string(), 0.5);
+        let goals = HashMap::from([
+            (Measurement::Stature, 1.7),
+            (Measurement::WaistCirc, 0.9),
+            (Measurement::HipsCirc, 0.85),
+        ]);
+
+        let (config, fit, measured) = fit_goals(config, goals.clone());
+        assert!(fit.converged, "{:?}", fit);
+        assert!(fit.iterations > 0);
+        for (measurement, goal) in goals {
+            let residual = fit.residuals[&measurement];
+            assert!(residual.abs() <= FitSettings::default().tolerance, "{:?} {}", measurement, residual);
+            assert!((measured.get(measurement) - goal - residual).abs() < 1e-4);
+        }
+        assert_eq!(config.morph_targets["head-age-less"], 0.7);
+        assert!(!config.morph_targets.contains_key("measure-waist-circ-decr"));
+        assert!((config.morph_targets[&format!("{}-minheight", HEIGHT)] - 0.5).abs() < 0.03);
+    }
+
+    #[test]
+    fn fit_stops_when_already_there() {
+        let (_, fit, _) = fit(TAU * 0.12);
+        assert!(fit.converged);
+        assert_eq!(fit.iterations, 0);
+    }
+}
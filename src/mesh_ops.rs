@@ -1,32 +1,17 @@
 use bevy::{
     prelude::*,
-    render::mesh::VertexAttributeValues,
+    render::mesh::{
+        Indices,
+        PrimitiveTopology,
+        VertexAttributeValues,
+    },
 };
-use std::{
-    collections::HashMap,
-    fs::File,
-    path::Path,
-    io::{ BufReader, BufRead },
+use std::collections::{ HashMap, HashSet };
+use crate::skinning::{
+    ATTRIBUTE_JOINT_INDEX_1,
+    ATTRIBUTE_JOINT_WEIGHT_1,
 };
 
-pub(crate) fn parse_obj_vertices<T: AsRef<Path>>(filename: T) -> Vec<Vec3> {
-    let path = filename.as_ref();
-    let err_msg = format!("Couldn't open file {:?}", path);
-    let file = File::open(path).expect(&err_msg);
-    let mut vertices = Vec::<Vec3>::new();
-    for line_result in BufReader::new(file).lines() {
-        let Ok(line) = line_result else { break };
-        if line.starts_with("v ") {
-            let coords: Vec<f32> = line.split_whitespace()
-                             .skip(1)
-                             .filter_map(|x| x.parse().ok())
-                             .collect();
-            vertices.push(Vec3::new(coords[0], coords[1], coords[2]));
-        }
-    }
-    vertices
-}
-
 pub(crate) fn get_vertex_positions(mesh: &Mesh) -> Vec<Vec3> {
     let Some(VertexAttributeValues::Float32x3(verts)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION)
             else { panic!("FAILED TO LOAD MESH VERTEX POSITIONS") };
@@ -71,8 +56,8 @@ pub(crate) fn get_joint_weights(mesh: &Mesh) -> Vec<Vec4> {
 
 // Maps mh vertex ids to vec of bevy ids
 pub(crate) fn generate_vertex_map(
-    mh_vertices: &Vec<Vec3>,
-    vertices: &Vec<Vec3>
+    mh_vertices: &[Vec3],
+    vertices: &[Vec3]
 ) -> HashMap<u16, Vec<u16>> {
     let mut vertex_map = HashMap::<u16, Vec<u16>>::new();
     let mut matched = std::collections::HashSet::<usize>::new();
@@ -102,4 +87,177 @@ pub(crate) fn generate_inverse_vertex_map(
         for vert in verts.iter() { inv_vertex_map.insert(*vert, *mhv); }
     }
     inv_vertex_map
-}
\ No newline at end of file
+}
+
+// Vertex clustering on a grid sized to keep roughly `ratio` of the vertices.
+// Each cluster keeps one of its original vertices, so every attribute (skinning included) carries over.
+// Vertices in a cell are only merged when connected inside it, so UV seams and separate
+// pieces passing through the same cell stay apart.
+pub(crate) fn decimate_mesh(mesh: &Mesh, ratio: f32) -> Mesh {
+    let Some(indices) = mesh.indices() else { return mesh.clone() };
+    let indices: Vec<usize> = indices.iter().collect();
+    let vertices = get_vertex_positions(mesh);
+    if ratio >= 1.0 || vertices.is_empty() { return mesh.clone(); }
+
+    // Vertices lie on a surface, so occupied cells scale with area / cell size squared
+    let area: f32 = indices.chunks_exact(3)
+        .map(|f| (vertices[f[1]] - vertices[f[0]]).cross(vertices[f[2]] - vertices[f[0]]).length() * 0.5)
+        .sum();
+    let target = (vertices.len() as f32 * ratio.max(0.0)).max(4.0);
+    let cell = (area / target).sqrt().max(f32::EPSILON);
+    let cells: Vec<IVec3> = vertices.iter().map(|v| (*v / cell).floor().as_ivec3()).collect();
+
+    // Islands within each cell.  Duplicates with the same position and UV are joined first,
+    // in case the loader split vertices per face.
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uvs)) => uvs.clone(),
+        _ => vec![[0.0; 2]; vertices.len()],
+    };
+    let mut islands: Vec<usize> = (0..vertices.len()).collect();
+    let mut welds = HashMap::<([u32; 3], [u32; 2]), usize>::new();
+    for (i, (v, uv)) in vertices.iter().zip(uvs.iter()).enumerate() {
+        let key = (v.to_array().map(f32::to_bits), uv.map(f32::to_bits));
+        let first = *welds.entry(key).or_insert(i);
+        join(&mut islands, first, i);
+    }
+    for face in indices.chunks_exact(3) {
+        for (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+            if cells[a] == cells[b] { join(&mut islands, a, b); }
+        }
+    }
+
+    let mut clusters = HashMap::<(IVec3, usize), u32>::new();
+    let mut keep = Vec::<usize>::new();
+    let remap: Vec<u32> = (0..vertices.len()).map(|i| {
+        let island = find(&mut islands, i);
+        *clusters.entry((cells[i], island)).or_insert_with(|| {
+            keep.push(i);
+            keep.len() as u32 - 1
+        })
+    }).collect();
+
+    let mut seen = HashSet::<[u32; 3]>::new();
+    let mut new_indices = Vec::<u32>::with_capacity(indices.len());
+    for face in indices.chunks_exact(3) {
+        let tri = [remap[face[0]], remap[face[1]], remap[face[2]]];
+        if tri[0] == tri[1] || tri[1] == tri[2] || tri[0] == tri[2] { continue; }
+        let mut key = tri;
+        key.sort();
+        if !seen.insert(key) { continue; }
+        new_indices.extend_from_slice(&tri);
+    }
+
+    let mut new_mesh = Mesh::new(PrimitiveTopology::TriangleList, mesh.asset_usage);
+    for attribute in [
+        Mesh::ATTRIBUTE_POSITION,
+        Mesh::ATTRIBUTE_NORMAL,
+        Mesh::ATTRIBUTE_UV_0,
+        Mesh::ATTRIBUTE_TANGENT,
+        Mesh::ATTRIBUTE_JOINT_INDEX,
+        Mesh::ATTRIBUTE_JOINT_WEIGHT,
+        ATTRIBUTE_JOINT_INDEX_1,
+        ATTRIBUTE_JOINT_WEIGHT_1,
+    ] {
+        let Some(values) = mesh.attribute(attribute.id) else { continue };
+        if let Some(values) = select_vertices(values, &keep) {
+            new_mesh.insert_attribute(attribute, values);
+        }
+    }
+    new_mesh.insert_indices(Indices::U32(new_indices));
+    new_mesh
+}
+
+// Union find over vertex ids
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root { root = parents[root]; }
+    let mut i = i;
+    while parents[i] != root { i = std::mem::replace(&mut parents[i], root); }
+    root
+}
+
+fn join(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+fn select_vertices(values: &VertexAttributeValues, keep: &[usize]) -> Option<VertexAttributeValues> {
+    Some(match values {
+        VertexAttributeValues::Float32x2(v) => VertexAttributeValues::Float32x2(keep.iter().map(|&i| v[i]).collect()),
+        VertexAttributeValues::Float32x3(v) => VertexAttributeValues::Float32x3(keep.iter().map(|&i| v[i]).collect()),
+        VertexAttributeValues::Float32x4(v) => VertexAttributeValues::Float32x4(keep.iter().map(|&i| v[i]).collect()),
+        VertexAttributeValues::Uint16x4(v) => VertexAttributeValues::Uint16x4(keep.iter().map(|&i| v[i]).collect()),
+        VertexAttributeValues::Uint32x4(v) => VertexAttributeValues::Uint32x4(keep.iter().map(|&i| v[i]).collect()),
+        _ => return None,
+    })
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_asset::RenderAssetUsages;
+
+    // Flat grid on XZ, 20 by 20 quads, with a UV seam down the middle.  The right half
+    // is its own island at u + 1.  With per_face, every face gets its own vertices.
+    fn seamed_grid(per_face: bool) -> Mesh {
+        let size = 20;
+        let mut positions = Vec::<[f32; 3]>::new();
+        let mut uvs = Vec::<[f32; 2]>::new();
+        let mut ids = HashMap::<(usize, usize, bool), u32>::new();
+        let mut indices = Vec::<u32>::new();
+        let mut vertex = |x: usize, z: usize, right: bool, positions: &mut Vec<[f32; 3]>, uvs: &mut Vec<[f32; 2]>| {
+            let new = positions.len() as u32;
+            let id = if per_face { new } else { *ids.entry((x, z, right)).or_insert(new) };
+            if id == new {
+                let u = x as f32 / size as f32 + if right { 1.0 } else { 0.0 };
+                positions.push([x as f32 * 0.1, 0.0, z as f32 * 0.1]);
+                uvs.push([u, z as f32 / size as f32]);
+            }
+            id
+        };
+        for x in 0..size {
+            for z in 0..size {
+                let right = x >= size / 2;
+                let quad: Vec<u32> = [(x, z), (x, z + 1), (x + 1, z + 1), (x + 1, z)].iter()
+                    .map(|&(x, z)| vertex(x, z, right, &mut positions, &mut uvs))
+                    .collect();
+                indices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+            }
+        }
+        Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+            .with_inserted_indices(Indices::U32(indices))
+    }
+
+    fn triangles(mesh: &Mesh) -> Vec<[Vec2; 3]> {
+        let uvs = get_uv_coords(mesh);
+        let indices: Vec<usize> = mesh.indices().unwrap().iter().collect();
+        indices.chunks_exact(3).map(|f| [uvs[f[0]], uvs[f[1]], uvs[f[2]]]).collect()
+    }
+
+    #[test]
+    fn keeps_uv_seams() {
+        for per_face in [false, true] {
+            let mesh = seamed_grid(per_face);
+            let decimated = decimate_mesh(&mesh, 0.2);
+            assert!(decimated.count_vertices() < mesh.count_vertices() / 2, "{}", decimated.count_vertices());
+            assert!(!triangles(&decimated).is_empty());
+            for tri in triangles(&decimated) {
+                let right = tri.iter().filter(|uv| uv.x >= 1.0).count();
+                assert!(right == 0 || right == 3, "{:?}", tri);
+            }
+        }
+    }
+
+    #[test]
+    fn full_ratio_keeps_mesh() {
+        let mesh = seamed_grid(false);
+        let kept = decimate_mesh(&mesh, 1.0);
+        assert_eq!(kept.count_vertices(), mesh.count_vertices());
+        assert_eq!(triangles(&kept).len(), triangles(&mesh).len());
+    }
+}
@@ -1,18 +1,19 @@
 use bevy::prelude::*;
 use std::{
-    collections::{ HashMap, HashSet },
-    fs::File,
-    io::{ BufReader, BufRead },
-    path::PathBuf,
+    collections::HashMap,
+    io::BufRead,
+    path::Path,
 };
 use serde::Deserialize;
-use serde_json;
-use walkdir::WalkDir;
 use crate::{ 
-    get_vertex_positions,
+    has_extension,
     BaseMesh,
     HumentityGlobalConfig,
     BODY_SCALE,
+};
+#[cfg(feature = "render")]
+use crate::{
+    get_vertex_positions,
     HumanMeshAsset,
 };
 
@@ -27,6 +28,10 @@ struct MacroData {
 
 #[derive(Deserialize, Debug)]
 struct MacroBounds {
+    // Only shown by the editor
+    #[cfg(feature = "editor")]
+    #[serde(default)]
+    label: String,
     parts: Vec<MacroBound>,
 }
 
@@ -44,12 +49,12 @@ struct MorphCategoriesJSON(HashMap<String, MorphCategoryJSON>);
 
 #[derive(Deserialize, Debug)]
 struct MorphCategoryJSON{
-    categories: Vec<CompositeMorph>,
+    categories: Vec<MorphSlider>,
 }
 
-#[allow(dead_code)]
+// One slider from target.json.  Signed values pick between the opposite targets.
 #[derive(Deserialize, Debug, Clone)]
-struct CompositeMorph {
+pub struct MorphSlider {
     has_left_and_right: bool,
     name: String,
     opposites: Option<Opposites>,
@@ -73,66 +78,183 @@ struct Opposites {
     positive_unsided: String,
 }
 
+/*---------+
+ |  Types  |
+ +---------*/
+// Macro slider values as in macro.json, all in [0, 1].  Races are weights that should sum to 1.
+#[derive(Clone, Debug)]
+pub struct MacroValues {
+    pub macros: HashMap<String, f32>,
+    pub races: HashMap<String, f32>,
+}
+
+impl Default for MacroValues {
+    fn default() -> Self {
+        let macros = ["gender", "age", "muscle", "weight", "proportions", "height", "cupsize", "firmness"];
+        let races = ["african", "asian", "caucasian"];
+        MacroValues {
+            macros: macros.iter().map(|m| (m.to_string(), 0.5)).collect(),
+            races: races.iter().map(|r| (r.to_string(), 1.0 / 3.0)).collect(),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum SliderSide {
+    Left,
+    Right,
+    Both,
+}
+
+impl MorphSlider {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn has_left_and_right(&self) -> bool {
+        self.has_left_and_right
+    }
+
+    // Single target sliders only go one way
+    pub fn is_signed(&self) -> bool {
+        self.opposites.is_some()
+    }
+
+    // (negative, positive) target names for one side.  Unsided sliders ignore the side.
+    pub fn target_pair(&self, side: SliderSide) -> (Option<&str>, Option<&str>) {
+        fn non_empty(s: &str) -> Option<&str> {
+            if s.is_empty() { None } else { Some(s) }
+        }
+        match &self.opposites {
+            Some(o) if !self.has_left_and_right => (non_empty(&o.negative_unsided), non_empty(&o.positive_unsided)),
+            Some(o) if side == SliderSide::Right => (non_empty(&o.negative_right), non_empty(&o.positive_right)),
+            Some(o) => (non_empty(&o.negative_left), non_empty(&o.positive_left)),
+            None => (None, self.targets.as_ref().and_then(|t| t.first()).and_then(|t| non_empty(t))),
+        }
+    }
+
+    // Signed slider value from the targets in a config.  Both reads the left side.
+    pub fn get(&self, morph_targets: &HashMap<String, f32>, side: SliderSide) -> f32 {
+        let (negative, positive) = self.target_pair(side);
+        let value = |name: Option<&str>| name.and_then(|n| morph_targets.get(n)).copied().unwrap_or(0.0);
+        value(positive) - value(negative)
+    }
+
+    // Sets the target matching the sign of the value and clears its opposite
+    pub fn set(&self, morph_targets: &mut HashMap<String, f32>, value: f32, side: SliderSide) {
+        let sides = match side {
+            SliderSide::Both if self.has_left_and_right => vec![SliderSide::Left, SliderSide::Right],
+            _ => vec![side],
+        };
+        for side in sides {
+            let (negative, positive) = self.target_pair(side);
+            for name in [negative, positive].into_iter().flatten() { morph_targets.remove(name); }
+            let target = if value >= 0.0 { positive } else { negative };
+            if let Some(name) = target {
+                if value != 0.0 { morph_targets.insert(name.to_string(), value.abs()); }
+            }
+        }
+    }
+}
+
 /*-------------+
  |  Resources  |
  +-------------*/
-#[allow(dead_code)]
 #[derive(Resource)]
-pub struct MorphSliders(HashMap<String, Vec<CompositeMorph>>);
+pub struct MorphSliders {
+    categories: HashMap<String, Vec<MorphSlider>>,
+    // Edits to sided sliders go to both sides
+    pub symmetry: bool,
+}
+
+impl MorphSliders {
+    // Sorted, for building UIs
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories: Vec<&str> = self.categories.keys().map(|c| c.as_str()).collect();
+        categories.sort();
+        categories
+    }
+
+    // Sorted by name.  Empty for unknown categories.
+    pub fn sliders(&self, category: &str) -> Vec<&MorphSlider> {
+        let mut sliders: Vec<&MorphSlider> = self.categories.get(category).into_iter().flatten().collect();
+        sliders.sort_by(|a, b| a.name.cmp(&b.name));
+        sliders
+    }
+
+    pub fn slider(&self, name: &str) -> Option<&MorphSlider> {
+        self.categories.values().flatten().find(|s| s.name == name)
+    }
+
+    pub fn get(&self, morph_targets: &HashMap<String, f32>, slider: &str, side: SliderSide) -> Option<f32> {
+        self.slider(slider).map(|s| s.get(morph_targets, side))
+    }
+
+    // Returns false for unknown sliders
+    pub fn set(&self, morph_targets: &mut HashMap<String, f32>, slider: &str, value: f32, side: SliderSide) -> bool {
+        let Some(slider) = self.slider(slider) else { return false };
+        let side = if self.symmetry { SliderSide::Both } else { side };
+        slider.set(morph_targets, value, side);
+        true
+    }
+
+    // Copies every sided slider from one side to the other
+    pub fn mirror(&self, morph_targets: &mut HashMap<String, f32>, from: SliderSide) {
+        let to = match from {
+            SliderSide::Left => SliderSide::Right,
+            SliderSide::Right => SliderSide::Left,
+            SliderSide::Both => return,
+        };
+        for slider in self.categories.values().flatten().filter(|s| s.has_left_and_right) {
+            let value = slider.get(morph_targets, from);
+            slider.set(morph_targets, value, to);
+        }
+    }
+}
 
 #[derive(Resource)]
-struct MacroSliders(MacroData);
+pub(crate) struct MacroSliders(MacroData);
+
+impl MacroSliders {
+    // (name, label) pairs, sorted by name
+    #[cfg(feature = "editor")]
+    pub(crate) fn sliders(&self) -> Vec<(&str, &str)> {
+        let mut sliders: Vec<(&str, &str)> = self.0.macrotargets.iter()
+            .map(|(name, bounds)| (name.as_str(), bounds.label.as_str()))
+            .collect();
+        sliders.sort();
+        sliders
+    }
+}
 
 #[derive(Resource)]
-pub struct MorphTargets(HashMap<String, HashMap<u16, Vec3>>);
+pub struct MorphTargets(pub(crate) HashMap<String, HashMap<u16, Vec3>>);
 
 
 impl FromWorld for MorphTargets {
     fn from_world(world: &mut World) -> Self {
         // Create Morph Target Entities from all the .target files
-        let core_path: PathBuf;
-        let target_paths: HashSet<PathBuf>;
-        if let Some(config) = world.get_resource::<HumentityGlobalConfig>() {
-            core_path = config.core_assets_path.clone();
-            target_paths = config.target_paths.clone();
-        } else {
-            panic!("No global Humentity config loaded");
+        let (source, target_paths) = match world.get_resource::<HumentityGlobalConfig>() {
+            Some(config) => (config.source.clone(), config.target_paths.clone()),
+            None => panic!("No global Humentity config loaded"),
         };
         let mut names = HashMap::<String, HashMap<u16, Vec3>>::new();
         for target_path in target_paths.iter() {
-            for entry in WalkDir::new(target_path).into_iter().filter_map(Result::ok) {
-                let path = entry.path();
-                let mut offsets = HashMap::<u16, Vec3>::new();
-                if path.is_file() && path.extension().and_then(|e| e.to_str()) == Some("target") {
-                    let Some(filename) = path.file_name().unwrap().to_str() else { continue };
-                    let Some(stem) = path.file_stem().unwrap().to_str() else { continue };
-                    let err_msg = "Couldn't open target file ".to_string() + filename;
-                    let file = File::open(path).expect(&err_msg);
-                    for line_result in BufReader::new(file).lines() {
-                        let Ok(line) = line_result else { break };
-                        let mut line_elements = line.split_whitespace();
-                        let Some(vert_str) = line_elements.next() else { continue };
-                        let Ok(vert) = vert_str.parse::<u16>() else { continue };
-                        let coords: Vec<f32> = line_elements
-                                              .filter_map(|x| x.parse().ok())
-                                              .collect();
-                        offsets.insert(vert, Vec3::from_slice(&coords[..]) * BODY_SCALE);
-                    }
-                    names.insert(stem.to_string(), offsets.clone());
-                }
-                else {
-                }
+            for path in source.files(target_path) {
+                if !has_extension(&path, &["target"]) { continue; }
+                let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+                let err_msg = format!("Couldn't open target file {:?}", path);
+                let bytes = source.read(&path).expect(&err_msg);
+                names.insert(stem.to_string(), parse_target(bytes.as_slice()));
             };
         };
-        let file = File::open(core_path.join("targets/macrodetails/macro.json")).expect("FAILED TO OPEN macro.json");
-        let reader = BufReader::new(file);
-        let macro_json: MacroData = serde_json::from_reader(reader).expect("FAILED TO PARSE macro.json");
+        let json = source.read(Path::new("targets/macrodetails/macro.json")).expect("FAILED TO OPEN macro.json");
+        let macro_json: MacroData = serde_json::from_slice(&json).expect("FAILED TO PARSE macro.json");
         world.insert_resource::<MacroSliders>(MacroSliders(macro_json));
 
-        let file = File::open(core_path.join("targets/target.json")).expect("FAILED TO OPEN target.json");
-        let reader = BufReader::new(file);
-        let categories_json: MorphCategoriesJSON = serde_json::from_reader(reader).expect("FAILED TO PARSE target.json");
-        let mut categories = HashMap::<String, Vec<CompositeMorph>>::new();
+        let json = source.read(Path::new("targets/target.json")).expect("FAILED TO OPEN target.json");
+        let categories_json: MorphCategoriesJSON = serde_json::from_slice(&json).expect("FAILED TO PARSE target.json");
+        let mut categories = HashMap::<String, Vec<MorphSlider>>::new();
         for (category, targets) in categories_json.0.iter() {
             let mut cat = targets.categories.clone();
             for target in cat.iter_mut() {
@@ -145,7 +267,10 @@ impl FromWorld for MorphTargets {
             }
             categories.insert(category.to_string(), cat);
         }
-        world.insert_resource::<MorphSliders>(MorphSliders(categories));
+        world.insert_resource::<MorphSliders>(MorphSliders {
+            categories,
+            symmetry: true,
+        });
 
         MorphTargets(names)
     }
@@ -154,6 +279,22 @@ impl FromWorld for MorphTargets {
 /*-------------+
  |  Functions  |
  +-------------*/
+// Vertex offsets from a .target file, scaled to bevy units
+pub(crate) fn parse_target<R: BufRead>(reader: R) -> HashMap<u16, Vec3> {
+    let mut offsets = HashMap::<u16, Vec3>::new();
+    for line_result in reader.lines() {
+        let Ok(line) = line_result else { break };
+        let mut line_elements = line.split_whitespace();
+        let Some(vert_str) = line_elements.next() else { continue };
+        let Ok(vert) = vert_str.parse::<u16>() else { continue };
+        let coords: Vec<f32> = line_elements
+                              .filter_map(|x| x.parse().ok())
+                              .collect();
+        offsets.insert(vert, Vec3::from_slice(&coords[..]) * BODY_SCALE);
+    }
+    offsets
+}
+
 pub(crate) fn adjust_helpers_to_morphs(
     shapekeys: &HashMap<String, f32>,
     targets: &Res<MorphTargets>,
@@ -170,12 +311,13 @@ pub(crate) fn adjust_helpers_to_morphs(
     helpers
 }
 
+#[cfg(feature = "render")]
 pub(crate) fn bake_body_morphs(
     mesh: &Mesh,
     vertex_map: &HashMap<u16, Vec<u16>>,
-    helpers: &Vec<Vec3>,
+    helpers: &[Vec3],
 ) -> Mesh {
-    let mut vertices = get_vertex_positions(&mesh);
+    let mut vertices = get_vertex_positions(mesh);
     for (mh_vert, vtx_list) in vertex_map.iter() {
         for vtx in vtx_list.iter() {
             vertices[*vtx as usize] = helpers[*mh_vert as usize];
@@ -188,38 +330,29 @@ pub(crate) fn bake_body_morphs(
 }
 
 
+// Fits the asset to the fully morphed helpers, like MakeHuman's proxy fitting.
+// Each vertex is placed exactly once, so any combination of targets works.
+#[cfg(feature = "render")]
 pub(crate) fn bake_asset_morphs(
-    shapekeys: &HashMap<String, f32>,
-    targets: &Res<MorphTargets>,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    helpers: &Vec<Vec3>,
+    mesh: &Mesh,
+    helpers: &[Vec3],
     asset: &HumanMeshAsset,
 ) -> Mesh {
-    let mesh = meshes.get(&asset.mesh_handle).unwrap().clone();
-    let mut vertices = get_vertex_positions(&mesh);
-    for (target_name, &value) in shapekeys.iter() {
-        let err_msg = format!("Failed to find morph {}", target_name);
-        let target = targets.0.get(target_name).expect(&err_msg);
-
-        for (asset_vert, vtx_list) in asset.vertex_map.iter() {
-            let helper_map = &asset.helper_maps[*asset_vert as usize];
-            for &vtx in vtx_list.iter() {
-                if let Some(mh_vtx) = helper_map.single_vertex {
-                    let offset = *target.get(&mh_vtx).unwrap();
-                    vertices[vtx as usize] += offset * value;
-                } else { // Triangulation
-                    let triangle = helper_map.triangle.as_ref().unwrap();
-                    let mut position = Vec3::ZERO;
-                    for i in 0..3 {
-                        let mh_vert = triangle.helper_verts[i];
-                        let wt = triangle.helper_weights[i];
-                        position += *helpers.get(mh_vert as usize).unwrap() * wt;
-                    }
-                    position += asset.get_offset_scale(helpers) * triangle.helper_offset;
-                    let offset = position - vertices[vtx as usize];
-                    vertices[vtx as usize] += offset * value;
-                }
+    let mut vertices = get_vertex_positions(mesh);
+    let offset_scale = asset.get_offset_scale(helpers);
+    for (asset_vert, vtx_list) in asset.vertex_map.iter() {
+        let helper_map = &asset.helper_maps[*asset_vert as usize];
+        let position = if let Some(mh_vtx) = helper_map.single_vertex {
+            helpers[mh_vtx as usize]
+        } else if let Some(triangle) = helper_map.triangle.as_ref() {
+            let mut position = offset_scale * triangle.helper_offset;
+            for i in 0..3 {
+                position += helpers[triangle.helper_verts[i] as usize] * triangle.helper_weights[i];
             }
+            position
+        } else { continue };
+        for &vtx in vtx_list.iter() {
+            vertices[vtx as usize] = position;
         }
     }
     mesh.clone()
@@ -228,3 +361,199 @@ pub(crate) fn bake_asset_morphs(
         .with_generated_tangents().unwrap()
 }
 
+
+// Weights for the macrodetails targets, following the combinations in macro.json.
+// Targets missing from the library are left out.
+pub(crate) fn macro_targets(
+    values: &MacroValues,
+    macros: &MacroSliders,
+    targets: &MorphTargets,
+) -> HashMap<String, f32> {
+    let mut result = HashMap::<String, f32>::new();
+    for (combination, variables) in macros.0.combinations.iter() {
+        let mut names = vec![(String::new(), 1.0)];
+        if combination == "genderagemuscleweight" { names[0].0 = "universal".to_string(); }
+        for variable in variables.iter() {
+            let levels = macro_levels(variable, values, macros);
+            let mut next = Vec::<(String, f32)>::with_capacity(names.len() * levels.len());
+            for (prefix, weight) in names.iter() {
+                for (level, level_weight) in levels.iter() {
+                    let name = if prefix.is_empty() { level.clone() } else { format!("{}-{}", prefix, level) };
+                    next.push((name, weight * level_weight));
+                }
+            }
+            names = next;
+        }
+        for (name, weight) in names.into_iter() {
+            if weight <= 0.0 || !targets.0.contains_key(&name) { continue; }
+            *result.entry(name).or_insert(0.0) += weight;
+        }
+    }
+    result
+}
+
+// Named levels with their weights for one macro variable, e.g. age 0.75 is half young, half old
+fn macro_levels(variable: &str, values: &MacroValues, macros: &MacroSliders) -> Vec<(String, f32)> {
+    if variable == "race" {
+        return values.races.iter().map(|(r, &w)| (r.clone(), w)).collect();
+    }
+    let Some(bounds) = macros.0.macrotargets.get(variable) else { return vec![] };
+    let value = *values.macros.get(variable).unwrap_or(&0.5);
+    // Bounds are padded slightly in the json so the parts don't overlap
+    let round = |x: f32| (x.clamp(0.0, 1.0) * 10000.0).round() / 10000.0;
+    let Some(part) = bounds.parts.iter().find(|p| value >= p.lowest && value <= p.highest) else { return vec![] };
+    let (low, high) = (round(part.lowest), round(part.highest));
+    let t = ((value - low) / (high - low)).clamp(0.0, 1.0);
+    let mut levels = Vec::<(String, f32)>::new();
+    if !part.low.is_empty() { levels.push((part.low.clone(), 1.0 - t)); }
+    if !part.high.is_empty() { levels.push((part.high.clone(), t)); }
+    levels
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    #[cfg(feature = "clothing")]
+    use bevy::render::{
+        mesh::{ Indices, PrimitiveTopology },
+        render_asset::RenderAssetUsages,
+    };
+    #[cfg(feature = "clothing")]
+    use crate::assets::parse_mhclo;
+
+    fn sided() -> MorphSlider {
+        serde_json::from_str(r#"{
+            "has_left_and_right": true,
+            "name": "armslegs/upperarm-scale-horiz",
+            "opposites": {
+                "negative-left": "l-upperarm-scale-horiz-decr",
+                "negative-right": "r-upperarm-scale-horiz-decr",
+                "negative-unsided": "",
+                "positive-left": "l-upperarm-scale-horiz-incr",
+                "positive-right": "r-upperarm-scale-horiz-incr",
+                "positive-unsided": ""
+            }
+        }"#).unwrap()
+    }
+
+    fn single() -> MorphSlider {
+        serde_json::from_str(r#"{
+            "has_left_and_right": false,
+            "name": "head/head-fat",
+            "targets": ["head-fat"]
+        }"#).unwrap()
+    }
+
+    fn sliders(symmetry: bool) -> MorphSliders {
+        MorphSliders {
+            categories: HashMap::from([("armslegs".to_string(), vec![sided()]), ("head".to_string(), vec![single()])]),
+            symmetry,
+        }
+    }
+
+    #[test]
+    fn signed_values_pick_one_target() {
+        let slider = sided();
+        let mut targets = HashMap::new();
+        slider.set(&mut targets, 0.4, SliderSide::Left);
+        assert_eq!(targets, HashMap::from([("l-upperarm-scale-horiz-incr".to_string(), 0.4)]));
+        slider.set(&mut targets, -0.25, SliderSide::Left);
+        assert_eq!(targets, HashMap::from([("l-upperarm-scale-horiz-decr".to_string(), 0.25)]));
+        assert_eq!(slider.get(&targets, SliderSide::Left), -0.25);
+        assert_eq!(slider.get(&targets, SliderSide::Right), 0.0);
+        slider.set(&mut targets, 0.0, SliderSide::Left);
+        assert!(targets.is_empty());
+    }
+
+    #[test]
+    fn single_targets_ignore_the_side() {
+        let slider = single();
+        assert!(!slider.is_signed());
+        assert_eq!(slider.target_pair(SliderSide::Right), (None, Some("head-fat")));
+        let mut targets = HashMap::new();
+        slider.set(&mut targets, 0.7, SliderSide::Both);
+        assert_eq!(slider.get(&targets, SliderSide::Left), 0.7);
+        assert_eq!(targets.len(), 1);
+    }
+
+    #[test]
+    fn symmetry_sets_both_sides() {
+        let mut targets = HashMap::new();
+        assert!(sliders(true).set(&mut targets, "armslegs/upperarm-scale-horiz", 0.5, SliderSide::Right));
+        assert_eq!(targets.get("l-upperarm-scale-horiz-incr"), Some(&0.5));
+        assert_eq!(targets.get("r-upperarm-scale-horiz-incr"), Some(&0.5));
+
+        let mut targets = HashMap::new();
+        assert!(sliders(false).set(&mut targets, "armslegs/upperarm-scale-horiz", 0.5, SliderSide::Right));
+        assert_eq!(targets, HashMap::from([("r-upperarm-scale-horiz-incr".to_string(), 0.5)]));
+        assert!(!sliders(false).set(&mut targets, "nope", 0.5, SliderSide::Right));
+    }
+
+    #[test]
+    fn mirror_copies_one_side() {
+        let sliders = sliders(false);
+        let mut targets = HashMap::from([
+            ("l-upperarm-scale-horiz-decr".to_string(), 0.3),
+            ("r-upperarm-scale-horiz-incr".to_string(), 0.9),
+            ("head-fat".to_string(), 0.2),
+        ]);
+        sliders.mirror(&mut targets, SliderSide::Left);
+        assert_eq!(sliders.get(&targets, "armslegs/upperarm-scale-horiz", SliderSide::Right), Some(-0.3));
+        assert!(!targets.contains_key("r-upperarm-scale-horiz-incr"));
+        assert_eq!(targets.get("head-fat"), Some(&0.2));
+
+        let before = targets.clone();
+        sliders.mirror(&mut targets, SliderSide::Both);
+        assert_eq!(targets, before);
+    }
+
+    #[test]
+    fn targets_only_move_listed_vertices() {
+        let offsets = parse_target("# comment\n0 1.0 0.0 0.0\n2 0.0 2.0 0.0\n".as_bytes());
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets[&2], Vec3::Y * 2.0 * BODY_SCALE);
+
+        let mut world = World::new();
+        world.insert_resource(MorphTargets(HashMap::from([("lift".to_string(), offsets)])));
+        world.insert_resource(BaseMesh {
+            #[cfg(feature = "render")]
+            mesh_handle: Handle::default(),
+            vertices: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            #[cfg(feature = "render")]
+            vertex_map: HashMap::new(),
+        });
+        let shapekeys = HashMap::from([("lift".to_string(), 0.5)]);
+        let helpers = world.run_system_once(move |targets: Res<MorphTargets>, base_mesh: Res<BaseMesh>| {
+            adjust_helpers_to_morphs(&shapekeys, &targets, &base_mesh)
+        });
+        assert_eq!(helpers[0], Vec3::X * 0.5 * BODY_SCALE);
+        assert_eq!(helpers[1], Vec3::X);
+        assert_eq!(helpers[2], Vec3::Y * (1.0 + BODY_SCALE));
+    }
+
+    // Vertex 0 is triangulated over all three helpers, 1 and 2 follow single helpers
+    // that no target touches.
+    #[cfg(feature = "clothing")]
+    #[test]
+    fn assets_follow_helpers_missing_from_targets() {
+        let mhclo = "name patch\nx_scale 0 1 1.0\ny_scale 0 2 1.0\nz_scale 0 1 1.0\nverts 0\n0 1 2 0.25 0.25 0.5 0.0 0.0 0.1\n1\n2\n";
+        let mut asset = parse_mhclo(mhclo.as_bytes(), Path::new("patch/patch.mhclo"));
+        asset.vertex_map = HashMap::from([(0, vec![0, 3]), (1, vec![1]), (2, vec![2])]);
+        let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32; 3]; 4])
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0f32, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]])
+            .with_inserted_indices(Indices::U32(vec![0, 1, 2, 3, 2, 1]));
+
+        let helpers = [Vec3::new(0.0, 0.0, 4.0), Vec3::X, Vec3::Y];
+        let vertices = get_vertex_positions(&bake_asset_morphs(&mesh, &helpers, &asset));
+        assert_eq!(vertices[1], Vec3::X);
+        assert_eq!(vertices[2], Vec3::Y);
+        let expected = (helpers[0] + helpers[1] + helpers[2] * 2.0) / 4.0 + Vec3::new(0.0, 0.0, -0.4);
+        assert!(vertices[0].distance(expected) < 1e-5, "{} != {}", vertices[0], expected);
+        assert_eq!(vertices[3], vertices[0]);
+    }
+}
@@ -0,0 +1,245 @@
+use bevy::{
+    app::PluginGroupBuilder,
+    prelude::*,
+};
+#[cfg(feature = "render")]
+use bevy::render::view::VisibilitySystems;
+#[cfg(feature = "render")]
+use bevy_obj::ObjPlugin;
+use std::{
+    collections::HashMap,
+    path::Path,
+};
+use crate::{
+    validation::{ validate_new_humans, HumanSpawnFailed },
+    loading_state_checker,
+    on_human_added,
+    queue_new_humans,
+    BaseMesh,
+    HumentityGlobalConfig,
+    HumentityState,
+    LoadingState,
+    MeasurementLoops,
+    MorphTargets,
+};
+#[cfg(feature = "render")]
+use crate::{
+    baking::{ finish_human_baking, HumanReady },
+    basemesh::{ create_body_mesh, create_body_vertex_map },
+    bounds::update_human_bounds,
+    cache::{ prune_human_mesh_cache, HumanMeshCache },
+    lod::update_human_lods,
+    skinning::add_skinning_material,
+    LoadingPhase,
+};
+#[cfg(feature = "clothing")]
+use crate::assets::{ generate_asset_vertex_maps, HumanAssetRegistry };
+#[cfg(feature = "rig")]
+use crate::{
+    rigs::{ load_rigs, RigData },
+    sockets::attach_to_sockets,
+};
+#[cfg(feature = "animation")]
+use crate::animation::{ load_animations, AnimationLibrarySet };
+#[cfg(feature = "debug")]
+use crate::rigs::bone_debug_draw;
+#[cfg(feature = "hot_reload")]
+use crate::{
+    hot_reload::{ reload_human_files, watch_human_files },
+    loaders::{ MhcloAsset, MhcloLoader, RigAsset, RigLoader, TargetAsset, TargetLoader },
+};
+
+/*-----------+
+ |  Plugins  |
+ +-----------*/
+// Every sub-plugin compiled in by the cargo features
+pub struct HumentityPlugins;
+
+impl PluginGroup for HumentityPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>()
+            .add(MorphPlugin);
+        #[cfg(feature = "clothing")]
+        let group = group.add(ClothingPlugin);
+        #[cfg(feature = "rig")]
+        let group = group.add(RigPlugin);
+        #[cfg(feature = "animation")]
+        let group = group.add(AnimationPlugin);
+        #[cfg(feature = "debug")]
+        let group = group.add(DebugPlugin);
+        group
+    }
+}
+
+// Kept for existing apps, same as HumentityPlugins without DebugPlugin when debug is off
+pub struct Humentity{
+    pub debug: bool,
+}
+
+impl Default for Humentity {
+    fn default() -> Self {
+        Humentity {
+            debug: true,
+        }
+    }
+}
+
+impl Plugin for Humentity {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "debug")]
+        if !self.debug {
+            app.add_plugins(HumentityPlugins.build().disable::<DebugPlugin>());
+            return;
+        }
+        app.add_plugins(HumentityPlugins);
+    }
+}
+
+// Targets, measurements and the base mesh.  Meshes are only baked with the render feature,
+// otherwise humans get their measurements and a transform.
+pub struct MorphPlugin;
+
+impl Plugin for MorphPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "embedded_assets")]
+        crate::asset_source::register_embedded_assets(app);
+        #[cfg(feature = "render")]
+        if !app.is_plugin_added::<ObjPlugin>() {
+            app.add_plugins(ObjPlugin{ compute_smooth_normals: true });
+        }
+        resolve_asset_folder(app);
+        app.insert_state(HumentityState::Loading);
+        app.init_resource::<MorphTargets>();
+        app.init_resource::<BaseMesh>();
+        app.init_resource::<MeasurementLoops>();
+        app.add_event::<HumanSpawnFailed>();
+        app.add_systems(Update, loading_state_checker.run_if(in_state(HumentityState::Loading)));
+        app.add_systems(Update, (
+            queue_new_humans,
+            (
+                validate_new_humans,
+                on_human_added,
+            ).chain().run_if(in_state(HumentityState::Ready)),
+        ).chain());
+
+        #[cfg(feature = "render")]
+        {
+            add_loading_phase(app, LoadingPhase::CreateBodyMesh);
+            add_loading_phase(app, LoadingPhase::GenerateBodyVertexMap);
+            app.init_resource::<HumanMeshCache>();
+            app.add_event::<HumanReady>();
+            add_skinning_material(app);
+            app.add_systems(Update, (
+                create_body_mesh,
+                create_body_vertex_map,
+            ).run_if(in_state(HumentityState::Loading)));
+            app.add_systems(Update, (
+                finish_human_baking,
+                update_human_lods,
+                prune_human_mesh_cache,
+            ).run_if(in_state(HumentityState::Ready)));
+            // Skinned meshes keep their rest pose Aabb otherwise, and get culled mid animation
+            app.add_systems(PostUpdate, update_human_bounds
+                .after(TransformSystem::TransformPropagate)
+                .after(VisibilitySystems::CalculateBounds)
+                .before(VisibilitySystems::CheckVisibility));
+        }
+        // Morphs alone have no loading phases
+        add_loading_phases_resource(app);
+    }
+
+    // Hot reload touches assets and rigs, so it needs both plugins.  The loaders only exist
+    // for the file watcher, startup still reads the files directly.
+    #[cfg(feature = "hot_reload")]
+    fn finish(&self, app: &mut App) {
+        if !app.is_plugin_added::<ClothingPlugin>() || !app.is_plugin_added::<RigPlugin>() {
+            warn!("Hot reload needs ClothingPlugin and RigPlugin, human files won't be watched");
+            return;
+        }
+        app.init_asset::<TargetAsset>()
+            .init_asset::<MhcloAsset>()
+            .init_asset::<RigAsset>()
+            .init_asset_loader::<TargetLoader>()
+            .init_asset_loader::<MhcloLoader>()
+            .init_asset_loader::<RigLoader>();
+        app.add_systems(OnEnter(HumentityState::Ready), watch_human_files);
+        app.add_systems(Update, reload_human_files.run_if(in_state(HumentityState::Ready)));
+    }
+}
+
+// Body parts, clothes, proxies and skin textures
+#[cfg(feature = "clothing")]
+pub struct ClothingPlugin;
+
+#[cfg(feature = "clothing")]
+impl Plugin for ClothingPlugin {
+    fn build(&self, app: &mut App) {
+        add_loading_phase(app, LoadingPhase::GenerateAssetVertexMap);
+        app.init_resource::<HumanAssetRegistry>();
+        app.add_systems(Update, generate_asset_vertex_maps.run_if(in_state(HumentityState::Loading)));
+    }
+}
+
+// Skeletons, skinning weights and ragdolls
+#[cfg(feature = "rig")]
+pub struct RigPlugin;
+
+#[cfg(feature = "rig")]
+impl Plugin for RigPlugin {
+    fn build(&self, app: &mut App) {
+        add_loading_phase(app, LoadingPhase::LoadRigs);
+        app.init_resource::<RigData>();
+        app.add_systems(Update, load_rigs.run_if(in_state(HumentityState::Loading)));
+        app.add_systems(Update, attach_to_sockets.run_if(in_state(HumentityState::Ready)));
+    }
+}
+
+// glTF animation libraries retargeted to the rigs
+#[cfg(feature = "animation")]
+pub struct AnimationPlugin;
+
+#[cfg(feature = "animation")]
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        add_loading_phase(app, LoadingPhase::SetUpAnimationLibraries);
+        app.init_resource::<AnimationLibrarySet>();
+        app.add_systems(Update, load_animations.run_if(in_state(HumentityState::Loading)));
+    }
+}
+
+// Bone gizmos
+#[cfg(feature = "debug")]
+pub struct DebugPlugin;
+
+#[cfg(feature = "debug")]
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, bone_debug_draw);
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+// The config's asset root follows AssetPlugin::file_path.  Apps without an AssetPlugin
+// get bevy's default folder.
+fn resolve_asset_folder(app: &mut App) {
+    let file_path = app.get_added_plugins::<AssetPlugin>().first()
+        .map(|plugin| plugin.file_path.clone())
+        .unwrap_or_else(|| AssetPlugin::default().file_path);
+    let Some(mut config) = app.world_mut().get_resource_mut::<HumentityGlobalConfig>() else { return };
+    config.resolve_asset_folder(Path::new(&file_path));
+}
+
+// Sub-plugins can be added in any order, the first one creates the resource
+fn add_loading_phases_resource(app: &mut App) {
+    if !app.world().contains_resource::<LoadingState>() {
+        app.insert_resource(LoadingState(HashMap::new()));
+    }
+}
+
+#[cfg(feature = "render")]
+fn add_loading_phase(app: &mut App, phase: LoadingPhase) {
+    add_loading_phases_resource(app);
+    app.world_mut().resource_mut::<LoadingState>().0.insert(phase, false);
+}
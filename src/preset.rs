@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use serde::{ Deserialize, Serialize };
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+};
+use crate::{
+    HumanConfig,
+    HumanOrigin,
+    LodSettings,
+    RagdollSettings,
+    RigType,
+    SkinningSettings,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Serializable HumanConfig.  Colors are sRGBA.  Misspelled fields fail to load
+// rather than silently falling back to defaults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HumanPreset {
+    pub morph_targets: HashMap<String, f32>,
+    pub rig: RigType,
+    pub skin_albedo: String,
+    pub body_parts: Vec<String>,
+    pub equipment: Vec<String>,
+    pub eye_color: [f32; 4],
+    pub eyebrow_color: [f32; 4],
+    pub hair_color: [f32; 4],
+    #[serde(default)]
+    pub origin: HumanOrigin,
+    #[serde(default)]
+    pub ragdoll: Option<RagdollSettings>,
+    #[serde(default)]
+    pub lod: Option<LodSettings>,
+    #[serde(default)]
+    pub skinning: SkinningSettings,
+}
+
+impl HumanPreset {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl From<&HumanConfig> for HumanPreset {
+    fn from(config: &HumanConfig) -> Self {
+        HumanPreset {
+            morph_targets: config.morph_targets.clone(),
+            rig: config.rig.clone(),
+            skin_albedo: config.skin_albedo.clone(),
+            body_parts: config.body_parts.clone(),
+            equipment: config.equipment.clone(),
+            eye_color: config.eye_color.to_srgba().to_f32_array(),
+            eyebrow_color: config.eyebrow_color.to_srgba().to_f32_array(),
+            hair_color: config.hair_color.to_srgba().to_f32_array(),
+            origin: config.origin,
+            ragdoll: config.ragdoll,
+            lod: config.lod.clone(),
+            skinning: config.skinning,
+        }
+    }
+}
+
+impl From<HumanPreset> for HumanConfig {
+    fn from(preset: HumanPreset) -> Self {
+        let color = |c: [f32; 4]| Color::srgba(c[0], c[1], c[2], c[3]);
+        HumanConfig {
+            morph_targets: preset.morph_targets,
+            rig: preset.rig,
+            skin_albedo: preset.skin_albedo,
+            body_parts: preset.body_parts,
+            equipment: preset.equipment,
+            eye_color: color(preset.eye_color),
+            eyebrow_color: color(preset.eyebrow_color),
+            hair_color: color(preset.hair_color),
+            origin: preset.origin,
+            ragdoll: preset.ragdoll,
+            lod: preset.lod,
+            skinning: preset.skinning,
+        }
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColliderKind;
+
+    #[test]
+    fn keeps_every_setting() {
+        let config = HumanConfig {
+            rig: RigType::GameEngine,
+            ragdoll: Some(RagdollSettings { kind: ColliderKind::ConvexHull, ..default() }),
+            lod: Some(LodSettings { proxy: "female_generic".to_string(), distances: [5.0, 12.0], ..default() }),
+            skinning: SkinningSettings { influences: 8, dual_quaternion: true },
+            origin: HumanOrigin::JointGround,
+            hair_color: Color::srgb(0.5, 0.25, 0.0),
+            ..default()
+        };
+        let json = serde_json::to_string(&HumanPreset::from(&config)).unwrap();
+        let loaded = HumanConfig::from(serde_json::from_str::<HumanPreset>(&json).unwrap());
+
+        assert_eq!(loaded.rig, RigType::GameEngine);
+        assert_eq!(loaded.ragdoll.map(|r| r.kind), Some(ColliderKind::ConvexHull));
+        let lod = loaded.lod.unwrap();
+        assert_eq!((lod.proxy.as_str(), lod.distances), ("female_generic", [5.0, 12.0]));
+        assert_eq!(loaded.skinning, config.skinning);
+        assert_eq!(loaded.origin, HumanOrigin::JointGround);
+        assert_eq!(loaded.hair_color.to_srgba(), config.hair_color.to_srgba());
+    }
+
+    #[test]
+    fn settings_are_optional() {
+        let json = r#"{
+            "morph_targets": { "head-age-less": 0.5 },
+            "rig": "Mixamo",
+            "skin_albedo": "",
+            "body_parts": [],
+            "equipment": [],
+            "eye_color": [0, 0, 0, 1],
+            "eyebrow_color": [0, 0, 0, 1],
+            "hair_color": [0, 0, 0, 1],
+            "lod": { "proxy": "proxy741" }
+        }"#;
+        let preset: HumanPreset = serde_json::from_str(json).unwrap();
+        assert!(preset.ragdoll.is_none());
+        assert_eq!(preset.skinning, SkinningSettings::default());
+        assert_eq!(preset.lod.unwrap().decimation, LodSettings::default().decimation);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let mut value = serde_json::to_value(HumanPreset::from(&HumanConfig::default())).unwrap();
+        value["skinning"]["dual_quaternions"] = true.into();
+        assert!(serde_json::from_value::<HumanPreset>(value.clone()).is_err());
+
+        value["skinning"] = serde_json::to_value(SkinningSettings::default()).unwrap();
+        value["hairs_color"] = value["hair_color"].clone();
+        let err = serde_json::from_value::<HumanPreset>(value).unwrap_err();
+        assert!(err.to_string().contains("hairs_color"), "{}", err);
+    }
+}
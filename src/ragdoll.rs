@@ -0,0 +1,394 @@
+use bevy::prelude::*;
+use serde::{ Deserialize, Serialize };
+#[cfg(feature = "rig")]
+use std::collections::HashMap;
+#[cfg(feature = "rig")]
+use crate::{
+    rigs::{ get_bone_head_tail, get_bone_transform },
+    RigData,
+    RigType,
+    VertexGroups,
+};
+#[cfg(any(feature = "rapier", feature = "avian"))]
+use crate::HumanPart;
+
+/*---------+
+ |  Types  |
+ +---------*/
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ColliderKind {
+    Capsule,
+    ConvexHull,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RagdollSettings {
+    pub kind: ColliderKind,
+    // Vertices with a smaller weight for a bone are not used to size its collider
+    pub min_weight: f32,
+    // Bones shorter than this get no collider (e.g. finger tips on low poly rigs)
+    pub min_bone_length: f32,
+}
+
+impl Default for RagdollSettings {
+    fn default() -> Self {
+        RagdollSettings {
+            kind: ColliderKind::Capsule,
+            min_weight: 0.5,
+            min_bone_length: 0.01,
+        }
+    }
+}
+
+// Engine agnostic collider shapes, in the local space of the collider
+#[derive(Clone, Debug)]
+pub enum ColliderShape {
+    // Aligned with the local Y axis, like the bone itself
+    Capsule { radius: f32, half_length: f32 },
+    ConvexHull(Vec<Vec3>),
+}
+
+#[derive(Clone, Debug)]
+pub struct BoneCollider {
+    pub bone: String,
+    pub shape: ColliderShape,
+    // Relative to the bone
+    pub offset: Transform,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RagdollJointKind {
+    // Bone branches off its parent (shoulders, hips, neck)
+    Branch,
+    // Bone continues a chain (elbows, knees, spine)
+    Chain,
+    // Bone has no children (head, finger tips, toes)
+    Leaf,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct JointLimits {
+    // Max angle between the bone and its rest direction
+    pub swing: f32,
+    // Min and max rotation around the bone axis
+    pub twist: [f32; 2],
+}
+
+impl RagdollJointKind {
+    pub fn limits(&self) -> JointLimits {
+        match self {
+            RagdollJointKind::Branch => JointLimits {
+                swing: 80f32.to_radians(),
+                twist: [-45f32.to_radians(), 45f32.to_radians()],
+            },
+            RagdollJointKind::Chain => JointLimits {
+                swing: 60f32.to_radians(),
+                twist: [-15f32.to_radians(), 15f32.to_radians()],
+            },
+            RagdollJointKind::Leaf => JointLimits {
+                swing: 30f32.to_radians(),
+                twist: [-10f32.to_radians(), 10f32.to_radians()],
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RagdollJoint {
+    pub bone: String,
+    pub parent: String,
+    pub kind: RagdollJointKind,
+    pub limits: JointLimits,
+    // Head of the bone in the parent's space.  The child anchor is its own origin.
+    pub parent_anchor: Vec3,
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+#[derive(Component, Clone, Debug)]
+pub struct HumanRagdoll {
+    pub colliders: Vec<BoneCollider>,
+    pub joints: Vec<RagdollJoint>,
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+#[cfg(feature = "rig")]
+pub(crate) fn build_ragdoll(
+    rig: &RigType,
+    rigs: &RigData,
+    vg: &VertexGroups,
+    helpers: &[Vec3],
+    settings: &RagdollSettings,
+) -> HumanRagdoll {
+    let bones = rigs.configs.get(rig).expect("No config for rig?");
+    let weights = rigs.weights.get(rig).expect("No weights for rig?");
+
+    let mut children = HashMap::<&str, usize>::new();
+    for bone in bones.values() {
+        *children.entry(bone.parent.as_str()).or_insert(0) += 1;
+    }
+
+    // Sort so output doesn't depend on hashmap order
+    let mut names: Vec<&String> = bones.keys().collect();
+    names.sort();
+
+    let mut colliders = Vec::<BoneCollider>::new();
+    for &name in names.iter() {
+        let bone = bones.get(name).unwrap();
+        let Some(bone_weights) = weights.get(name) else { continue };
+        let (head, tail) = get_bone_head_tail(bone, vg, helpers);
+        let length = (tail - head).length();
+        if length < settings.min_bone_length { continue; }
+
+        // Weighted vertices in bone space.  Bone points along +Y.
+        let inv = get_bone_transform(bone, vg, helpers).compute_matrix().inverse();
+        let points: Vec<(Vec3, f32)> = bone_weights.iter()
+            .map(|(&vtx, &wt)| (inv.transform_point3(helpers[vtx as usize]), wt))
+            .collect();
+        let Some((shape, offset)) = bone_shape(&points, length, settings) else { continue };
+        colliders.push(BoneCollider {
+            bone: name.clone(),
+            shape,
+            offset,
+        });
+    }
+
+    // Only bones that got a collider can be jointed.  Walk up past any parents without one.
+    let has_collider: Vec<&str> = colliders.iter().map(|c| c.bone.as_str()).collect();
+    let mut joints = Vec::<RagdollJoint>::new();
+    for collider in colliders.iter() {
+        let bone = bones.get(&collider.bone).unwrap();
+        let mut parent = bone.parent.as_str();
+        while !parent.is_empty() && !has_collider.contains(&parent) {
+            parent = bones.get(parent).unwrap().parent.as_str();
+        }
+        if parent.is_empty() { continue; }
+
+        let parent_bone = bones.get(parent).unwrap();
+        let kind = if !children.contains_key(collider.bone.as_str()) {
+            RagdollJointKind::Leaf
+        } else if *children.get(bone.parent.as_str()).unwrap_or(&0) > 1 {
+            RagdollJointKind::Branch
+        } else {
+            RagdollJointKind::Chain
+        };
+        let head = get_bone_transform(bone, vg, helpers).translation;
+        let parent_anchor = get_bone_transform(parent_bone, vg, helpers)
+            .compute_matrix()
+            .inverse()
+            .transform_point3(head);
+        joints.push(RagdollJoint {
+            bone: collider.bone.clone(),
+            parent: parent.to_string(),
+            kind,
+            limits: kind.limits(),
+            parent_anchor,
+        });
+    }
+
+    HumanRagdoll {
+        colliders,
+        joints,
+    }
+}
+
+// Shape and offset for a bone from its weighted vertices in bone space.  Hulls that would
+// be flat fall back to a capsule, so every bone given a collider gets a body.
+#[cfg(feature = "rig")]
+fn bone_shape(
+    points: &[(Vec3, f32)],
+    length: f32,
+    settings: &RagdollSettings,
+) -> Option<(ColliderShape, Transform)> {
+    let points: Vec<(Vec3, f32)> = points.iter().copied()
+        .filter(|(_, wt)| *wt >= settings.min_weight)
+        .collect();
+    if points.is_empty() { return None; }
+
+    if settings.kind == ColliderKind::ConvexHull {
+        let hull: Vec<Vec3> = points.iter().map(|(p, _)| *p).collect();
+        if is_solid(&hull, length * 1e-3) {
+            return Some((ColliderShape::ConvexHull(hull), Transform::IDENTITY));
+        }
+    }
+
+    // Weighted mean distance of the vertices from the bone axis.  They sit on the skin, so
+    // round limbs get their radius, and flat parts like the torso a radius between their
+    // half width and half depth.
+    let wt_sum: f32 = points.iter().map(|(_, wt)| wt).sum();
+    let radius = points.iter().map(|(p, wt)| Vec2::new(p.x, p.z).length() * wt).sum::<f32>() / wt_sum;
+    let shape = ColliderShape::Capsule {
+        radius,
+        half_length: (length * 0.5 - radius).max(0.0),
+    };
+    Some((shape, Transform::from_xyz(0.0, length * 0.5, 0.0)))
+}
+
+// Points span a volume thicker than min_size in every direction
+#[cfg(feature = "rig")]
+fn is_solid(points: &[Vec3], min_size: f32) -> bool {
+    let Some(&a) = points.first() else { return false };
+    let Some(&b) = points.iter().max_by(|p, q| p.distance_squared(a).total_cmp(&q.distance_squared(a))) else { return false };
+    if a.distance(b) <= min_size { return false; }
+    let axis = (b - a).normalize();
+
+    // Farthest from the line, then farthest from the plane
+    let off_line = |p: &Vec3| (*p - a).reject_from_normalized(axis).length();
+    let Some(&c) = points.iter().max_by(|p, q| off_line(p).total_cmp(&off_line(q))) else { return false };
+    if off_line(&c) <= min_size { return false; }
+    let normal = axis.cross(c - a).normalize();
+    points.iter().any(|p| (*p - a).dot(normal).abs() > min_size)
+}
+
+// Bodies are kinematic so they follow animation.  Switch them to dynamic to ragdoll.
+#[cfg(feature = "rapier")]
+pub(crate) fn insert_rapier_components(
+    commands: &mut Commands,
+    human: Entity,
+    ragdoll: &HumanRagdoll,
+    bone_entities: &HashMap<String, Entity>,
+) {
+    use bevy_rapier3d::prelude::*;
+
+    for collider in ragdoll.colliders.iter() {
+        let &bone = bone_entities.get(&collider.bone).unwrap();
+        // Jointed bones need a body even without a shape
+        commands.entity(bone).insert(RigidBody::KinematicPositionBased);
+        let shape = match &collider.shape {
+            ColliderShape::Capsule { radius, half_length } => Collider::capsule_y(*half_length, *radius),
+            ColliderShape::ConvexHull(points) => {
+                let Some(hull) = Collider::convex_hull(points) else {
+                    warn!("Convex hull for {} rejected, the bone gets no collider", collider.bone);
+                    continue;
+                };
+                hull
+            }
+        };
+        commands.entity(bone).with_children(|parent| {
+            parent.spawn((shape, HumanPart(human), TransformBundle::from_transform(collider.offset)));
+        });
+    }
+    for joint in ragdoll.joints.iter() {
+        let &bone = bone_entities.get(&joint.bone).unwrap();
+        let &parent = bone_entities.get(&joint.parent).unwrap();
+        let spherical = SphericalJointBuilder::new()
+            .local_anchor1(joint.parent_anchor)
+            .local_anchor2(Vec3::ZERO)
+            .limits(JointAxis::AngX, [-joint.limits.swing, joint.limits.swing])
+            .limits(JointAxis::AngZ, [-joint.limits.swing, joint.limits.swing])
+            .limits(JointAxis::AngY, joint.limits.twist);
+        commands.entity(bone).insert(ImpulseJoint::new(parent, spherical));
+    }
+}
+
+// Bodies are kinematic so they follow animation.  Switch them to dynamic to ragdoll.
+#[cfg(feature = "avian")]
+pub(crate) fn insert_avian_components(
+    commands: &mut Commands,
+    human: Entity,
+    ragdoll: &HumanRagdoll,
+    bone_entities: &HashMap<String, Entity>,
+) {
+    use avian3d::prelude::*;
+
+    for collider in ragdoll.colliders.iter() {
+        let &bone = bone_entities.get(&collider.bone).unwrap();
+        // Jointed bones need a body even without a shape
+        commands.entity(bone).insert(RigidBody::Kinematic);
+        let shape = match &collider.shape {
+            ColliderShape::Capsule { radius, half_length } => Collider::capsule(*radius, *half_length * 2.0),
+            ColliderShape::ConvexHull(points) => {
+                let Some(hull) = Collider::convex_hull(points.clone()) else {
+                    warn!("Convex hull for {} rejected, the bone gets no collider", collider.bone);
+                    continue;
+                };
+                hull
+            }
+        };
+        commands.entity(bone).with_children(|parent| {
+            parent.spawn((shape, HumanPart(human), TransformBundle::from_transform(collider.offset)));
+        });
+    }
+    for joint in ragdoll.joints.iter() {
+        let &bone = bone_entities.get(&joint.bone).unwrap();
+        let &parent = bone_entities.get(&joint.parent).unwrap();
+        commands.spawn((
+            SphericalJoint::new(parent, bone)
+                .with_local_anchor_1(joint.parent_anchor)
+                .with_local_anchor_2(Vec3::ZERO)
+                .with_swing_limits(-joint.limits.swing, joint.limits.swing)
+                .with_twist_limits(joint.limits.twist[0], joint.limits.twist[1]),
+            HumanPart(human),
+        ));
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(all(test, feature = "rig"))]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    // Rings of vertices around the bone axis, from head to tail
+    fn limb(radius: f32, length: f32, weight: f32) -> Vec<(Vec3, f32)> {
+        (0..5).flat_map(|ring| (0..12).map(move |i| {
+            let angle = TAU * i as f32 / 12.0;
+            let y = length * ring as f32 / 4.0;
+            (Vec3::new(radius * angle.cos(), y, radius * angle.sin()), weight)
+        })).collect()
+    }
+
+    fn settings(kind: ColliderKind) -> RagdollSettings {
+        RagdollSettings { kind, ..default() }
+    }
+
+    #[test]
+    fn capsule_fits_round_limbs() {
+        let (shape, offset) = bone_shape(&limb(0.05, 0.3, 1.0), 0.3, &settings(ColliderKind::Capsule)).unwrap();
+        let ColliderShape::Capsule { radius, half_length } = shape else { panic!("{:?}", shape) };
+        assert!((radius - 0.05).abs() < 1e-5);
+        assert!((half_length - 0.1).abs() < 1e-5);
+        assert_eq!(offset.translation, Vec3::new(0.0, 0.15, 0.0));
+    }
+
+    #[test]
+    fn capsule_ignores_light_weights() {
+        let mut points = limb(0.05, 0.3, 0.9);
+        points.extend(limb(0.5, 0.3, 0.1));
+        let (shape, _) = bone_shape(&points, 0.3, &settings(ColliderKind::Capsule)).unwrap();
+        assert!(matches!(shape, ColliderShape::Capsule { radius, .. } if (radius - 0.05).abs() < 1e-5));
+        assert!(bone_shape(&limb(0.5, 0.3, 0.1), 0.3, &settings(ColliderKind::Capsule)).is_none());
+    }
+
+    #[test]
+    fn flat_hulls_become_capsules() {
+        let hull = settings(ColliderKind::ConvexHull);
+        let (shape, offset) = bone_shape(&limb(0.05, 0.3, 1.0), 0.3, &hull).unwrap();
+        assert!(matches!(shape, ColliderShape::ConvexHull(ref points) if points.len() == 60));
+        assert_eq!(offset, Transform::IDENTITY);
+
+        // A single ring, and a single line
+        let ring: Vec<(Vec3, f32)> = limb(0.05, 0.0, 1.0).into_iter().take(12).collect();
+        let line: Vec<(Vec3, f32)> = (0..5).map(|i| (Vec3::Y * 0.05 * i as f32, 1.0)).collect();
+        for points in [ring, line] {
+            let (shape, _) = bone_shape(&points, 0.3, &hull).unwrap();
+            assert!(matches!(shape, ColliderShape::Capsule { .. }), "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn solid_needs_every_direction() {
+        let corners = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        assert!(is_solid(&corners, 0.01));
+        assert!(!is_solid(&corners[..3], 0.01));
+        assert!(!is_solid(&[Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::new(1.0, 1.0, 0.005)], 0.01));
+        assert!(!is_solid(&[Vec3::ONE; 4], 0.01));
+        assert!(!is_solid(&[], 0.01));
+    }
+}
@@ -1,48 +1,72 @@
 use bevy::{
-    animation::{AnimationTarget, AnimationTargetId}, color::palettes::css::RED, prelude::*, render::mesh::{
-        skinning::{ SkinnedMesh, SkinnedMeshInverseBindposes},
-        VertexAttributeValues,
-    }
+    animation::AnimationTarget,
+    prelude::*,
+    render::mesh::VertexAttributeValues,
 };
+#[cfg(feature = "debug")]
+use bevy::color::palettes::css::RED;
 use serde::Deserialize;
-use serde_json;
 use std::{
     collections::HashMap,
-    fs::File,
-    io::BufReader,
+    path::{ Path, PathBuf },
+    sync::Arc,
 };
 use crate::{
+    skinning::{
+        ATTRIBUTE_JOINT_INDEX_1,
+        ATTRIBUTE_JOINT_WEIGHT_1,
+        MAX_INFLUENCES,
+    },
+    bounds::HumanExtents,
     get_vertex_positions,
     HelperMap,
+    HumanPart,
+    HumanSkeleton,
+    HumanSkeletonTemplate,
+    HumanAssetSource,
     VertexGroups,
+    HumanConfig,
     HumentityGlobalConfig,
+    LoadingPhase,
+    LoadingState,
+    PendingHuman,
+    RigType,
 };
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone)]
-pub enum RigType {
-    None,
-    Default,
-    Mixamo,
-    GameEngine,
-}
-
 /*---------+
  |  JSON   |
  +---------*/
-#[derive(Deserialize, Debug)]
+// Position of a bone head or tail.  default_position is the point on the
+// unmorphed MPFB basemesh, in blender coordinates.
+#[derive(Deserialize, Debug, Clone)]
 struct BoneTransform {
     cube_name: Option<String>,
-    //default_position: Vec3,
-    strategy: String,
+    default_position: Option<[f32; 3]>,
+    strategy: Option<String>,
     vertex_indices: Option<Vec<u16>>,
     vertex_index: Option<u16>,
 }
 
-#[derive(Deserialize, Debug)]
-struct BoneData {
+// Blender's bone inherit_scale modes
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum InheritScale {
+    #[default]
+    Full,
+    FixShear,
+    Average,
+    None,
+    NoneLegacy,
+    Aligned,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct BoneData {
     head: BoneTransform,
-    //inherit_scale: String,
-    parent: String,
+    #[serde(default)]
+    pub(crate) inherit_scale: InheritScale,
+    pub(crate) parent: String,
+    #[serde(default)]
     roll: f32,
     tail: BoneTransform,
 }
@@ -52,65 +76,110 @@ struct WeightsFile {
     weights: HashMap<String, Vec<(u16, f32)>>
 }
 
-// Contains an extra layer for some reason.  Usual config is in the bones key
+// Mixamo contains an extra layer for some reason.  Usual config is in the bones key
 #[derive(Deserialize, Debug)]
-struct MixamoRigConfig {
+struct WrappedRigConfig {
     bones: HashMap<String, BoneData>
 }
 
+// Bone name -> mh vertex -> weight
+pub(crate) type BoneWeights = HashMap<String, HashMap<u16, f32>>;
+
+// Either of the two json files that make up a rig
+pub(crate) enum RigFile {
+    Bones(HashMap<String, BoneData>),
+    Weights(BoneWeights),
+}
+
 /*-----------+
  | Resources |
  +-----------*/
+// Rigs are read while loading when preloaded or used by a human spawned by then,
+// any other rig the first time a human uses it
 #[derive(Resource)]
-pub(crate) struct RigData {
-    weights: HashMap<RigType, HashMap<String, HashMap<u16, f32>>>,
-    configs: HashMap<RigType, HashMap<String, BoneData>>,
+pub struct RigData {
+    // Shared with baking tasks
+    pub(crate) weights: HashMap<RigType, Arc<BoneWeights>>,
+    pub(crate) configs: HashMap<RigType, HashMap<String, BoneData>>,
+    // Rebuilt whenever a config is set
+    pub(crate) templates: HashMap<RigType, Arc<HumanSkeletonTemplate>>,
+    files: Vec<(RigType, PathBuf, PathBuf)>,
+    // Rigs whose files couldn't be read, so they aren't read again every frame
+    failed: HashMap<RigType, String>,
+    source: Arc<dyn HumanAssetSource>,
 }
 
 impl FromWorld for RigData {
     fn from_world(world: &mut World) -> Self {
         let config = world.get_resource::<HumentityGlobalConfig>().unwrap();
-        let path = config.core_assets_path.clone();
-        let mut type_strings = HashMap::<RigType, &str>::new();
-        type_strings.insert(RigType::Default, "default");
-        type_strings.insert(RigType::Mixamo, "mixamo");
-        type_strings.insert(RigType::GameEngine, "game_engine");
-
-        let mut rig_weights = HashMap::<RigType, HashMap<String, HashMap<u16, f32>>>::new();
-        let mut rig_configs = HashMap::<RigType, HashMap<String, BoneData>>::new();
-
-        for (rig_type, name) in type_strings.iter() {
-            let err_msg = "FAILED TO OPEN WEIGHTS FILE : ".to_string() + name;
-            let weights_file = File::open(path.join("rigs/weights.".to_string() + type_strings.get(rig_type).unwrap() + ".json")).expect(&err_msg);
-            let weights_reader = BufReader::new(weights_file);
-            let err_msg = "FAILED TO READ WEIGHTS JSON : ".to_string() + name;
-            let weights: WeightsFile = serde_json::from_reader(weights_reader).expect(&err_msg);
-            let mut weights_hashmap = HashMap::<String, HashMap<u16, f32>>::new();
-            for (bone, wts) in weights.weights.iter() {
-                let hashmap: HashMap<u16, f32> = wts.iter().cloned().collect();
-                weights_hashmap.insert(bone.to_string(), hashmap);
-            }
-            rig_weights.insert(*rig_type, weights_hashmap);
-
-            let err_msg = "FAILED TO OPEN CONFIG FILE : ".to_string() + name;
-            let config_file = File::open(path.join("rigs/rig.".to_string() + type_strings.get(rig_type).unwrap() + ".json")).expect(&err_msg);
-            let config_reader = BufReader::new(config_file);
-            let err_msg = "FAILED TO READ CONFIG JSON : ".to_string() + name;
-            if *rig_type == RigType::Mixamo {
-                let config: MixamoRigConfig = serde_json::from_reader(config_reader).expect(&err_msg);
-                rig_configs.insert(*rig_type, config.bones);
-            } else {
-                let config: HashMap<String, BoneData> = serde_json::from_reader(config_reader).expect(&err_msg);
-                rig_configs.insert(*rig_type, config);
-            }
-        }
         RigData {
-            weights: rig_weights,
-            configs: rig_configs,
+            weights: HashMap::new(),
+            configs: HashMap::new(),
+            templates: HashMap::new(),
+            files: rig_files(config),
+            failed: HashMap::new(),
+            source: config.source.clone(),
         }
     }
 }
 
+impl RigData {
+    pub(crate) fn is_loaded(&self, rig: &RigType) -> bool {
+        self.configs.contains_key(rig) && self.weights.contains_key(rig)
+    }
+
+    // Errors are remembered, the files are only read once
+    pub(crate) fn load(&mut self, rig: &RigType) -> Result<(), String> {
+        if *rig == RigType::None || self.is_loaded(rig) { return Ok(()); }
+        if let Some(reason) = self.failed.get(rig) { return Err(reason.clone()); }
+        let (bones, weights) = self.read(rig).inspect_err(|reason| {
+            self.failed.insert(rig.clone(), reason.clone());
+        })?;
+
+        // Rest poses are unscaled, only animations that scale bones are affected
+        let mut partial_scale: Vec<&String> = bones.iter()
+            .filter(|(_, bone)| bone.inherit_scale != InheritScale::Full)
+            .map(|(name, _)| name)
+            .collect();
+        if !partial_scale.is_empty() {
+            partial_scale.sort();
+            warn!("Rig {:?} bones {:?} don't fully inherit scale, bevy transforms always do", rig, partial_scale);
+        }
+
+        self.weights.insert(rig.clone(), Arc::new(weights));
+        self.set_config(rig, bones);
+        Ok(())
+    }
+
+    // Why the rig's files couldn't be read, if that was tried
+    pub(crate) fn failure(&self, rig: &RigType) -> Option<&String> {
+        self.failed.get(rig)
+    }
+
+    fn read(&self, rig: &RigType) -> Result<(HashMap<String, BoneData>, BoneWeights), String> {
+        let Some((_, rig_path, weights_path)) = self.files.iter().find(|(r, _, _)| r == rig) else {
+            return Err("no rig files".to_string());
+        };
+        let read = |path: &PathBuf| -> Result<RigFile, String> {
+            let bytes = self.source.read(path).map_err(|e| format!("can't open {:?}: {}", path, e))?;
+            parse_rig_file(&bytes).map_err(|e| format!("can't read {:?}: {}", path, e))
+        };
+        let RigFile::Weights(weights) = read(weights_path)? else {
+            return Err(format!("{:?} is not a weights file", weights_path));
+        };
+        let RigFile::Bones(bones) = read(rig_path)? else {
+            return Err(format!("{:?} is not a rig config file", rig_path));
+        };
+        Ok((bones, weights))
+    }
+
+    pub(crate) fn set_config(&mut self, rig: &RigType, bones: HashMap<String, BoneData>) {
+        let template = HumanSkeletonTemplate::new(rig.clone(), &bones);
+        self.templates.insert(rig.clone(), Arc::new(template));
+        self.configs.insert(rig.clone(), bones);
+    }
+}
+
 /*------------+
  | Components |
  +------------*/
@@ -121,6 +190,7 @@ impl FromWorld for RigData {
 /*---------+
  | Systems |
  +---------*/
+ #[cfg(feature = "debug")]
  pub(crate) fn bone_debug_draw(
     query: Query<(&GlobalTransform, &Parent), With<Bone>>,
     transforms: Query<&GlobalTransform, With<Bone>>,
@@ -134,221 +204,184 @@ impl FromWorld for RigData {
     })
  }
     
-/*-----------+
- | Functions |
- +-----------*/
-pub(crate) fn build_rig(
-    human: &Entity,
-    rig: RigType,
-    rigs: &Res<RigData>,
-    inv_bindpose_assets: &mut ResMut<Assets<SkinnedMeshInverseBindposes>>,
-    commands: &mut Commands,
-    vg: &Res<VertexGroups>,
-    helpers: &Vec<Vec3>,
-    spawn_transform: Transform,
-) -> (SkinnedMesh, Vec<String>) {
-    let config_res = rigs.configs.get(&rig).unwrap();
-
-    // Spawn bone entities
-    // Use human as root of skeleton
-    let mut bone_names = HashMap::<String, Name>::new();
-    commands.entity(*human).insert(Bone);
-    let mut bone_entities = HashMap::<String, Entity>::with_capacity(config_res.len());
-    for (name, bone) in config_res.iter() {
-        let bone_name = Name::new(name.clone());
-        bone_names.insert(name.clone(), bone_name.clone());
-        if bone.parent == "" && name.eq_ignore_ascii_case("root"){
-            bone_entities.insert(name.to_string(), *human);
-            commands.entity(*human).insert(bone_name);
-        } else {
-            bone_entities.insert(name.to_string(), commands.spawn((Bone, bone_name)).id());
+// Runs every loading frame so humans spawned while loading get their rigs read up front too.
+// Apps that only spawn RigType::None humans read no rig at all.
+pub(crate) fn load_rigs(
+    mut rigs: ResMut<RigData>,
+    global_config: Res<HumentityGlobalConfig>,
+    pending: Query<&HumanConfig, With<PendingHuman>>,
+    mut loading_state: ResMut<LoadingState>,
+) {
+    for rig in global_config.preloaded_rigs.iter() {
+        if let Err(reason) = rigs.load(rig) {
+            error!("Failed to preload rig {:?}: {}", rig, reason);
         }
     }
-
-    // For finding in-degree of each bone in the tree
-    let mut in_degree = HashMap::<String, usize>::with_capacity(config_res.len());
-
-    // Set up parent child relationships
-    for (name, bone) in config_res.iter() {
-        in_degree.insert(name.to_string(), 0);
-        let &child = bone_entities.get(name).unwrap();
-        if let Some(parent) = bone_entities.get(&bone.parent) {
-            commands.entity(*parent).push_children(&[child]);
-        }
+    // Humans whose rig fails are reported by validation
+    for config in pending.iter() {
+        let _ = rigs.load(&config.rig);
     }
+    loading_state.0.insert(LoadingPhase::LoadRigs, true);
+}
 
-    // Find in-degree of the bones
-    for (name, bone) in config_res.iter() {
-        let mut parent = bone.parent.clone();
-        while parent != "" {
-            *in_degree.entry(name.to_string()).or_insert(0) += 1;
-            parent = config_res.get(&parent).unwrap().parent.clone();
-        }
+/*-----------+
+ | Functions |
+ +-----------*/
+// Name used in the rigs/rig.{name}.json and rigs/weights.{name}.json files
+fn rig_file_name(rig: &RigType) -> Option<&'static str> {
+    match rig {
+        RigType::Default => Some("default"),
+        RigType::Mixamo => Some("mixamo"),
+        RigType::GameEngine => Some("game_engine"),
+        RigType::None | RigType::Custom(_) => None,
     }
+}
 
-    // Get bone vecs sorted by degree
-    let mut in_degree_vec: Vec<(String, usize)> = in_degree.into_iter().collect();
-    in_degree_vec.sort_by(|a, b| a.1.cmp(&b.1));
-    let mut sorted_bones: Vec<String> = in_degree_vec.into_iter().map(|(k, _)| k.clone()).collect();
-    let mut joints: Vec<Entity> = sorted_bones.iter().map(|name| {
-        *bone_entities.get(name).unwrap()
-    }).collect();
-
-    // Get all global transforms
-    let mut transforms = HashMap::<String, Transform>::new();
-    for (name, bone) in config_res.iter() {
-        transforms.insert(name.to_string(), get_bone_transform(&bone, &vg, &helpers));
+// (rig, rig config path, weights path) for the built in rigs whose files exist and every custom rig
+pub(crate) fn rig_files(config: &HumentityGlobalConfig) -> Vec<(RigType, PathBuf, PathBuf)> {
+    let present = config.source.files(Path::new("rigs"));
+    let mut files: Vec<(RigType, PathBuf, PathBuf)> = [RigType::Default, RigType::Mixamo, RigType::GameEngine]
+        .into_iter()
+        .filter_map(|rig| {
+            let name = rig_file_name(&rig)?;
+            let rig_path = PathBuf::from(format!("rigs/rig.{}.json", name));
+            let weights_path = PathBuf::from(format!("rigs/weights.{}.json", name));
+            let found = [&rig_path, &weights_path].iter().all(|path| present.iter().any(|p| p.ends_with(path)));
+            found.then_some((rig, rig_path, weights_path))
+        })
+        .collect();
+    for (name, (rig_path, weights_path)) in config.custom_rigs.iter() {
+        files.push((RigType::Custom(name.clone()), rig_path.clone(), weights_path.clone()));
     }
+    files
+}
 
-    let mut local_transforms = HashMap::<String, Transform>::new();
-    // Convert to local space
-    for name in sorted_bones.iter() {
-        let mut bone = config_res.get(name).unwrap();
-        let mut parents = Vec::<String>::new();
-        while bone.parent != "" {
-            parents.push(bone.parent.clone());
-            bone = config_res.get(&bone.parent).unwrap();
-        }
-        let mut mat = transforms.get(name).unwrap().compute_matrix();
-        for parent in parents.iter().rev() {
-            mat = local_transforms.get(parent).unwrap().compute_matrix().inverse() * mat;
-        }
-        local_transforms.insert(name.clone(), Transform::from_matrix(mat));
+// Weights files have a weights key, Mixamo wraps its bones in a bones key
+pub(crate) fn parse_rig_file(bytes: &[u8]) -> serde_json::Result<RigFile> {
+    let value: serde_json::Value = serde_json::from_slice(bytes)?;
+    if value.get("weights").is_some() {
+        let file: WeightsFile = serde_json::from_value(value)?;
+        let weights = file.weights.into_iter()
+            .map(|(bone, wts)| (bone, wts.into_iter().collect()))
+            .collect();
+        Ok(RigFile::Weights(weights))
+    } else if value.get("bones").is_some() {
+        let config: WrappedRigConfig = serde_json::from_value(value)?;
+        Ok(RigFile::Bones(config.bones))
+    } else {
+        Ok(RigFile::Bones(serde_json::from_value(value)?))
     }
+}
 
-    // Set transforms and inverse bind poses
+// Spawns the bones and returns them with their inverse bindposes, which the caller
+// can swap for cached ones
+pub(crate) fn build_rig(
+    human: &Entity,
+    rig: &RigType,
+    rigs: &RigData,
+    commands: &mut Commands,
+    vg: &VertexGroups,
+    helpers: &[Vec3],
+    spawn_transform: Transform,
+) -> (HumanSkeleton, Vec<Mat4>) {
+    let config_res = rigs.configs.get(rig).expect("No config for rig?");
+    let template = rigs.templates.get(rig).expect("No skeleton template for rig?").clone();
+
+    // Spawn bone entities in joint order.  The root bone is a child of the human, so
+    // its rest pose keeps the origin alignment of the helpers.
+    let joints: Vec<Entity> = template.names().iter()
+        .map(|name| commands.spawn((Bone, HumanPart(*human), Name::new(name.clone()))).id())
+        .collect();
+
+    // Global rest poses, the inserted root sits at the origin
+    let transforms: Vec<Transform> = template.names().iter()
+        .map(|name| match config_res.get(name) {
+            Some(bone) => get_bone_transform(bone, vg, helpers),
+            None => Transform::IDENTITY,
+        })
+        .collect();
+
+    // Parents come first, so every bone can be made local to its parent
+    let mut local_transforms = Vec::<Transform>::with_capacity(joints.len());
     let mut inv_bindposes = Vec::<Mat4>::with_capacity(joints.len());
-    for name in sorted_bones.iter() {
-        let &entity = bone_entities.get(name).unwrap();
-        inv_bindposes.push(transforms.get(name).unwrap().compute_matrix().inverse());
-        commands.entity(entity).insert(TransformBundle{
-            local: *local_transforms.get(name).unwrap(),
-            ..default()
-        });
+    for (i, &entity) in joints.iter().enumerate() {
+        let global = transforms[i].compute_matrix();
+        let local = match template.parent(i) {
+            Some(parent) => Transform::from_matrix(transforms[parent].compute_matrix().inverse() * global),
+            None => transforms[i],
+        };
+        local_transforms.push(local);
+        inv_bindposes.push(global.inverse());
+
+        commands.entity(entity).insert(TransformBundle{ local, ..default() });
+        // Props attached to sockets inherit visibility through the bones
+        commands.entity(entity).insert(VisibilityBundle::default());
+        match template.parent(i) {
+            Some(parent) => { commands.entity(joints[parent]).add_child(entity); }
+            None => { commands.entity(*human).add_child(entity); }
+        }
+        if let Some(id) = template.target_id(i) {
+            commands.entity(entity).insert(AnimationTarget{ player: *human, id });
+        }
     }
 
-    // Mixamo rig has hips as root. Insert human as root bone.
-    let root_str = &sorted_bones[0].clone();
-    if root_str.ends_with("Hips") {
-        sorted_bones.insert(0, "Root".to_string());
-        bone_entities.insert("Root".to_string(), *human);
-        let &old_root = bone_entities.get(root_str).unwrap();
-        commands.entity(*human).push_children(&[old_root]);
-        commands.entity(*human).insert(TransformBundle{local:spawn_transform, ..default()});
-        joints.insert(0, *human);
-        inv_bindposes.insert(0, Mat4::IDENTITY)
-    } else {  // Set positions on already existant root bone
-        let &root = bone_entities.get(root_str).unwrap();
-        commands.entity(root).insert(TransformBundle{local:spawn_transform, ..default()});
-    }
+    commands.entity(*human).insert(TransformBundle{local:spawn_transform, ..default()});
 
-    // Set AnimationTarget Components
-    for (name, bone) in config_res.iter() {
-        if name == "Root" { continue; }
-        let &bone_entity = bone_entities.get(name).unwrap();
-        let mut bone_path: Vec<Name> = vec![bone_names.get(name).unwrap().clone()];
-        let mut parent = bone.parent.clone();
-        while parent != "" {
-            if parent.eq_ignore_ascii_case("root") { break; }
-            bone_path.push(bone_names.get(&parent).unwrap().clone());
-            parent = config_res.get(&parent).unwrap().parent.clone();
-        }
-        bone_path.push(Name::new("Human.rig"));
-        commands.entity(bone_entity).insert(AnimationTarget{
-            player: *human,
-            id: AnimationTargetId::from_names(bone_path.iter().rev())
-        });
-    }
+    // Culling bounds follow the joints, each grown by its farthest weighted vertex
+    let weights = rigs.weights.get(rig).expect("No weights for rig?");
+    commands.entity(*human).insert(HumanExtents::skinned(
+        helpers,
+        template.names().iter().enumerate().map(|(i, name)| (joints[i], transforms[i].translation, weights.get(name))),
+    ));
 
-    let inverse_bindposes = inv_bindpose_assets.add(inv_bindposes);
-    (SkinnedMesh {
-        inverse_bindposes: inverse_bindposes.clone(),
-        joints: joints,
-    }, sorted_bones)
+    (HumanSkeleton::new(template, joints, local_transforms), inv_bindposes)
 }
 
 pub(crate) fn set_basemesh_rig_arrays(
-    rig: RigType,
     mesh: Mesh,
-    rigs: &Res<RigData>,
+    weights_res: &BoneWeights,
     vertex_map: &HashMap<u16, Vec<u16>>,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    sorted_bones: &Vec<String>,
-) -> Handle<Mesh> {
-    // Build bone index and weight arrays
-    let weights_res = rigs.weights.get(&rig).expect("No weights for rig?");
+    sorted_bones: &[String],
+    influences: usize,
+) -> Mesh {
     let mut new_mesh = mesh.clone();
     let vertices = get_vertex_positions(&mesh);
-    let mut indices = vec![[0; 4]; vertices.len()];
-    let mut weights = vec![[0.0; 4]; vertices.len()];
+    let mut vertex_influences = vec![Vec::<(u16, f32)>::new(); vertices.len()];
 
     for (bone_index, bone_name) in sorted_bones.iter().enumerate() {
         let Some(bone_weights) = weights_res.get(bone_name) else { continue };
         // loop over vertex, bone weight pairs from config
         for (&mh_id, &wt) in bone_weights.iter() {
+            if wt <= 0.0 { continue; }
             // loop over bevy vertex ids mapping to this mh vertex
             for vertex in vertex_map.get(&mh_id).unwrap().iter() {
-                // Get the vertex(u16) -> weights(f32) map for this bone
-                // get the array at the vertex index to get the [u16;4] array we need to insert into
-                let mut indices_vec = indices[*vertex as usize];
-                // find smallest weight which is also < wt
-                let Some(vec_index) = indices_vec.iter()
-                    .enumerate()
-                    .filter_map(|(index, &value)| if (value as f32) < wt { Some(index) } else { None })
-                    .min() else { continue };
-                // Set the bone index in this vector
-                indices_vec[vec_index] = bone_index as u16;
-                // insert into indices array 
-                indices[*vertex as usize] = indices_vec;
-                // use the same vertex vec index to set the weights also
-                let mut weights_vec = weights[*vertex as usize];
-                weights_vec[vec_index] = *bone_weights.get(&mh_id).expect("Failed to get vertex bone weight");
-                weights[*vertex as usize] = weights_vec;
+                vertex_influences[*vertex as usize].push((bone_index as u16, wt));
             }
         }
     }
-    // Make sure weights sum to 1 for each vertex
-    for i in 0..weights.iter().len() {
-        let wvec = weights[i];
-        let norm = wvec[0] + wvec[1] + wvec[2] + wvec[3];
-        if norm == 0.0 { panic!("div by 0 ");}
-        weights[i] = [
-            wvec[0] / norm,
-            wvec[1] / norm,
-            wvec[2] / norm,
-            wvec[3] / norm,
-        ];
-    }
 
-    new_mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_INDEX, VertexAttributeValues::Uint16x4(indices));
-    new_mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, VertexAttributeValues::Float32x4(weights));
-    meshes.add(new_mesh)
+    insert_joint_arrays(&mut new_mesh, vertex_influences, influences);
+    new_mesh
 }
 
 pub(crate) fn set_asset_rig_arrays(
-    rig: RigType,
     mesh: Mesh,
-    rigs: &Res<RigData>,
+    weights_res: &BoneWeights,
     vertex_map: &HashMap<u16, Vec<u16>>,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    helper_maps: &Vec<HelperMap>,
-    sorted_bones: &Vec<String>,
-) -> Handle<Mesh> {
-    let weights_res = rigs.weights.get(&rig).expect("No weights for rig?");
+    helper_maps: &[HelperMap],
+    sorted_bones: &[String],
+    influences: usize,
+) -> Mesh {
     let mut new_mesh = mesh.clone();
     let vertices = get_vertex_positions(&mesh);
-
-    // Build hashmaps to store bone info for each obj vertex id
-    let mut indices_map = HashMap::<u16, Vec<usize>>::with_capacity(vertices.len());
-    let mut weights_map = HashMap::<u16, Vec<f32>>::with_capacity(vertices.len());
+    let mut vertex_influences = vec![Vec::<(u16, f32)>::new(); vertices.len()];
 
     // loop over obj vertices
-    for obj_id in vertex_map.keys() {
-        // Create vec in the map for bone indices and weights
-        let mut indices_vec = Vec::<usize>::new();
-        let mut weights_vec = Vec::<f32>::new();
+    for (obj_id, verts) in vertex_map.iter() {
         // Get helper map for this obj_id
         let helper_map = &helper_maps[*obj_id as usize];
+        // Same bone can have weights on all 3 verts of a triangle, so sum them
+        let mut aggregate = HashMap::<u16, f32>::new();
         // loop over bones and find any matching helper indices
         for (bone_index, bone_name) in sorted_bones.iter().enumerate() {
             let Some(bone_weights) = weights_res.get(bone_name) else { continue };
@@ -356,108 +389,219 @@ pub(crate) fn set_asset_rig_arrays(
             if let Some(v) = helper_map.single_vertex {
                 let Some(helper_wt) = bone_weights.get(&v) else { continue; };
                 if *helper_wt <= 0.0 { continue };
-                indices_vec.push(bone_index);
-                weights_vec.push(*helper_wt);
-            } else { 
+                *aggregate.entry(bone_index as u16).or_insert(0.0) += *helper_wt;
+            } else {
                 // Triangle.  Have to weight the base vertices
                 let triangle = helper_map.triangle.as_ref().unwrap();
                 for (i, mh_id) in triangle.helper_verts.iter().enumerate() {
-                    let Some(helper_wt) = bone_weights.get(&mh_id) else { continue; };
+                    let Some(helper_wt) = bone_weights.get(mh_id) else { continue; };
                     if *helper_wt <= 0.0 { continue };
-                    // Will aggregate below.  For now just allow duplicate entries
-                    // e.g. same bone can have weights on all 3 verts of triangle
-                    indices_vec.push(bone_index);
-                    weights_vec.push(*helper_wt * triangle.helper_weights[i]);
+                    *aggregate.entry(bone_index as u16).or_insert(0.0) += *helper_wt * triangle.helper_weights[i];
                 }
             }
         }
-        indices_map.insert(*obj_id, indices_vec);
-        weights_map.insert(*obj_id, weights_vec);
+        let aggregate: Vec<(u16, f32)> = aggregate.into_iter().collect();
+        for vtx in verts.iter() {
+            vertex_influences[*vtx as usize] = aggregate.clone();
+        }
     }
 
-    // The acutal arrays that go into the mesh data
-    let mut indices = vec![[0; 4]; vertices.len()];
-    let mut weights = vec![[0.0; 4]; vertices.len()];
+    insert_joint_arrays(&mut new_mesh, vertex_influences, influences);
+    new_mesh
+}
 
-    // Take top 4 weights
-    for (obj_id, verts) in vertex_map.iter() {
-        for vtx in verts.iter() {
-            let vtx_indices = indices_map.get(obj_id).expect("Error getting vertex bone indices");
-            let vtx_weights = weights_map.get(obj_id).expect("Error getting vertex bone weights");
-
-            // Deduplicate vertices by summing weights 
-            let mut aggregate = HashMap::<usize, f32>::new();
-            for (&ind, &wt) in vtx_indices.iter().zip(vtx_weights.iter()) {
-                let wtsum = aggregate.entry(ind).or_insert(0.0);
-                *wtsum += wt;
-            }
-            let (mut vtx_indices, mut vtx_weights): (Vec<usize>, Vec<f32>) = aggregate.into_iter().unzip();
-
-            // 4 bone limit for bevy animation
-            if vtx_indices.len() > 4 {
-                // Sort vec indices based on the weights
-                let mut ordering: Vec<usize> = (0..vtx_weights.len()).collect();
-                ordering.sort_by(|&i, &j| vtx_weights[j].partial_cmp(&vtx_weights[i]).unwrap());
-                // Get vec indices of top 4 weights
-                let top_weights: Vec<usize> = ordering.iter().take(4).copied().collect();
-                // set back into the original vecs
-                let new_vtx_weights: Vec<f32> = top_weights.iter().map(|&i| vtx_weights[i]).collect();
-                let new_vtx_indices: Vec<usize> = top_weights.iter().map(|&i| vtx_indices[i]).collect();
-                vtx_indices = new_vtx_indices;
-                vtx_weights = new_vtx_weights;
-            }
-            // Insert bone indices into final array
-            let mut indices_array = [0 as u16; 4];
-            for (i, &val) in vtx_indices.iter().enumerate() {
-                indices_array[i] = val as u16
-            };
-            indices[*vtx as usize] = indices_array;
-            // Normalize weights and insert
-            let mut weights_array = [0.0; 4];
-            let sum: f32 = vtx_weights.iter().sum();
-            for (i, &val) in vtx_weights.iter().enumerate() {
-                weights_array[i] = val / sum;
-            };
-            weights[*vtx as usize] = weights_array;
+// Keeps the highest weights of each vertex, normalized to sum to 1.
+// The first 4 go in bevy's joint attributes, any more in ATTRIBUTE_JOINT_INDEX_1/WEIGHT_1.
+fn insert_joint_arrays(mesh: &mut Mesh, mut vertex_influences: Vec<Vec<(u16, f32)>>, influences: usize) {
+    let influences = influences.clamp(1, MAX_INFLUENCES);
+    let vertex_count = vertex_influences.len();
+    let mut indices = [vec![[0u16; 4]; vertex_count], vec![[0u16; 4]; vertex_count]];
+    let mut weights = [vec![[0.0f32; 4]; vertex_count], vec![[0.0f32; 4]; vertex_count]];
+
+    for (vtx, vtx_influences) in vertex_influences.iter_mut().enumerate() {
+        // Ties broken by bone index so results don't depend on hashmap order
+        vtx_influences.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        vtx_influences.truncate(influences);
+        let sum: f32 = vtx_influences.iter().map(|(_, wt)| wt).sum();
+        // Unweighted vertices keep all zero weights
+        if sum <= 0.0 { continue; }
+        for (i, &(bone, wt)) in vtx_influences.iter().enumerate() {
+            indices[i / 4][vtx][i % 4] = bone;
+            weights[i / 4][vtx][i % 4] = wt / sum;
         }
     }
 
-    new_mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_INDEX, VertexAttributeValues::Uint16x4(indices));
-    new_mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, VertexAttributeValues::Float32x4(weights));
-    meshes.add(new_mesh)
+    let [indices, extra_indices] = indices;
+    let [weights, extra_weights] = weights;
+    mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_INDEX, VertexAttributeValues::Uint16x4(indices));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, VertexAttributeValues::Float32x4(weights));
+    if influences > 4 {
+        mesh.insert_attribute(ATTRIBUTE_JOINT_INDEX_1, VertexAttributeValues::Uint16x4(extra_indices));
+        mesh.insert_attribute(ATTRIBUTE_JOINT_WEIGHT_1, VertexAttributeValues::Float32x4(extra_weights));
+    }
 }
 
-fn get_bone_transform(
+// Same rest orientation blender gives the bone: the shortest rotation from its
+// Y axis to the bone, then the roll around it.  This is done in blender's Z up
+// space and the resulting axes converted, so bone local axes match blender's
+// and animations authored there line up.
+pub(crate) fn get_bone_transform(
     bone: &BoneData,
-    vg: &Res<VertexGroups>,
-    mh_vertices: &Vec<Vec3>,
+    vg: &VertexGroups,
+    mh_vertices: &[Vec3],
 ) -> Transform {
-    let (v1, v2) = get_bone_vertices(&bone.head, vg);
-    let (v3, v4) = get_bone_vertices(&bone.tail, vg);
-    let start = (mh_vertices[v1 as usize] + mh_vertices[v2 as usize]) * 0.5;
-    let end = (mh_vertices[v3 as usize] + mh_vertices[v4 as usize]) * 0.5;
-    let mut transform = Transform::from_translation(start)
-        .with_rotation(Quat::from_rotation_arc(Vec3::Y, (end - start).normalize()));
-    transform.rotate_local_y(bone.roll);
-    transform
+    let (start, end) = get_bone_head_tail(bone, vg, mh_vertices);
+    let direction = (end - start).normalize_or_zero();
+    let rotation = if direction == Vec3::ZERO {
+        warn!("Bone with zero length, using identity rotation");
+        Mat3::IDENTITY
+    } else {
+        let blender_to_bevy = Mat3::from_cols(Vec3::X, -Vec3::Z, Vec3::Y);
+        let blender_direction = blender_to_bevy.transpose() * direction;
+        blender_to_bevy * vec_roll_to_mat3(blender_direction, bone.roll)
+    };
+    Transform::from_translation(start).with_rotation(Quat::from_mat3(&rotation))
+}
+
+// Port of blender's vec_roll_to_mat3_normalized.  Direction must be normalized.
+fn vec_roll_to_mat3(direction: Vec3, roll: f32) -> Mat3 {
+    const SAFE_THRESHOLD: f32 = 6.1e-3;
+    const CRITICAL_THRESHOLD: f32 = 2.5e-4;
+    let Vec3 { x, y, z } = direction;
+    let mut theta = 1.0 + y;
+    let theta_alt = x * x + z * z;
+
+    let bone_matrix = if theta > SAFE_THRESHOLD || theta_alt > CRITICAL_THRESHOLD * CRITICAL_THRESHOLD {
+        // Close to -Y the precision of theta is bad, so use the series expansion of sqrt
+        if theta <= SAFE_THRESHOLD {
+            theta = theta_alt * 0.5 + theta_alt * theta_alt * 0.125;
+        }
+        Mat3::from_cols(
+            Vec3::new(1.0 - x * x / theta, -x, -x * z / theta),
+            Vec3::new(x, y, z),
+            Vec3::new(-x * z / theta, -z, 1.0 - z * z / theta),
+        )
+    } else {
+        // Pointing straight down -Y, mirror around Z
+        Mat3::from_diagonal(Vec3::new(-1.0, -1.0, 1.0))
+    };
+    Mat3::from_axis_angle(direction, roll) * bone_matrix
 }
 
-fn get_bone_vertices(
-    bone: &BoneTransform,
-    vg: &Res<VertexGroups>,
-) -> (u16, u16) {
-    let v1: u16;
-    let v2: u16;
-    if bone.strategy == "MEAN" {
-        v1 = bone.vertex_indices.as_ref().unwrap()[0];
-        v2 = bone.vertex_indices.as_ref().unwrap()[1];
-    } else if bone.strategy == "CUBE" {
-        let joint = bone.cube_name.as_ref().unwrap();
-        v1 = vg.0.get(joint).unwrap()[0][0] as u16;
-        v2 = vg.0.get(joint).unwrap()[0][1] as u16;
-    } else if bone.strategy == "VERTEX" {
-        v1 = bone.vertex_index.unwrap();
-        v2 = bone.vertex_index.unwrap();
-    } else { panic!("Unrecognized bone strategy {}", bone.strategy) }
-    (v1, v2)
-}
\ No newline at end of file
+pub(crate) fn get_bone_head_tail(
+    bone: &BoneData,
+    vg: &VertexGroups,
+    mh_vertices: &[Vec3],
+) -> (Vec3, Vec3) {
+    let start = get_joint_position(&bone.head, vg, mh_vertices);
+    let end = get_joint_position(&bone.tail, vg, mh_vertices);
+    (start, end)
+}
+
+// MPFB strategies: CUBE is the mean of a joint cube vertex group, MEAN the mean
+// of the listed vertices and VERTEX a single vertex.  Anything else, or a
+// strategy whose data is missing, falls back to the default position which
+// doesn't follow morphs.
+fn get_joint_position(
+    joint: &BoneTransform,
+    vg: &VertexGroups,
+    mh_vertices: &[Vec3],
+) -> Vec3 {
+    let mean = |indices: &mut dyn Iterator<Item = usize>| -> Option<Vec3> {
+        let mut sum = Vec3::ZERO;
+        let mut count = 0;
+        for i in indices {
+            sum += *mh_vertices.get(i)?;
+            count += 1;
+        }
+        (count > 0).then(|| sum / count as f32)
+    };
+
+    let position = match joint.strategy.as_deref() {
+        Some("CUBE") => joint.cube_name.as_ref()
+            .and_then(|cube| vg.0.get(cube))
+            .and_then(|ranges| mean(&mut ranges.iter().flat_map(|r| r[0]..=r[1]))),
+        Some("MEAN") => joint.vertex_indices.as_ref()
+            .and_then(|indices| mean(&mut indices.iter().map(|&i| i as usize))),
+        Some("VERTEX") => joint.vertex_index
+            .and_then(|i| mh_vertices.get(i as usize).copied()),
+        _ => None,
+    };
+
+    position
+        .or_else(|| joint.default_position.map(|[x, y, z]| Vec3::new(x, z, -y)))
+        .unwrap_or_else(|| {
+            warn!("No position for bone joint {:?}, using origin", joint);
+            Vec3::ZERO
+        })
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::{
+        mesh::{ MeshVertexAttribute, PrimitiveTopology },
+        render_asset::RenderAssetUsages,
+    };
+
+    fn joint_arrays(influences: Vec<Vec<(u16, f32)>>, count: usize) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+        insert_joint_arrays(&mut mesh, influences, count);
+        mesh
+    }
+
+    fn indices(mesh: &Mesh, attribute: MeshVertexAttribute) -> Vec<[u16; 4]> {
+        match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Uint16x4(values)) => values.clone(),
+            _ => panic!("MISSING JOINT INDICES"),
+        }
+    }
+
+    fn weights(mesh: &Mesh, attribute: MeshVertexAttribute) -> Vec<[f32; 4]> {
+        match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Float32x4(values)) => values.clone(),
+            _ => panic!("MISSING JOINT WEIGHTS"),
+        }
+    }
+
+    #[test]
+    fn keeps_highest_weights() {
+        let mesh = joint_arrays(vec![vec![(0, 0.05), (1, 0.3), (2, 0.1), (3, 0.2), (4, 0.25), (5, 0.1)]], 4);
+        assert_eq!(indices(&mesh, Mesh::ATTRIBUTE_JOINT_INDEX), vec![[1, 4, 3, 2]]);
+        assert!(mesh.attribute(ATTRIBUTE_JOINT_INDEX_1).is_none());
+        assert!(mesh.attribute(ATTRIBUTE_JOINT_WEIGHT_1).is_none());
+    }
+
+    #[test]
+    fn ties_go_to_lower_bone_index() {
+        let mesh = joint_arrays(vec![vec![(7, 0.2), (3, 0.2), (5, 0.2), (1, 0.2), (9, 0.2)]], 4);
+        assert_eq!(indices(&mesh, Mesh::ATTRIBUTE_JOINT_INDEX), vec![[1, 3, 5, 7]]);
+    }
+
+    #[test]
+    fn weights_are_normalized() {
+        let mesh = joint_arrays(vec![vec![(0, 2.0), (1, 1.0), (2, 1.0)], vec![(4, 0.1)], vec![]], 4);
+        let weights = weights(&mesh, Mesh::ATTRIBUTE_JOINT_WEIGHT);
+        assert_eq!(weights[0], [0.5, 0.25, 0.25, 0.0]);
+        assert_eq!(weights[1], [1.0, 0.0, 0.0, 0.0]);
+        // Unweighted vertices keep all zero weights
+        assert_eq!(weights[2], [0.0; 4]);
+    }
+
+    #[test]
+    fn extra_influences_split_across_attributes() {
+        let influences: Vec<(u16, f32)> = (0..10).map(|bone| (bone, 10.0 - bone as f32)).collect();
+        let mesh = joint_arrays(vec![influences], 8);
+        assert_eq!(indices(&mesh, Mesh::ATTRIBUTE_JOINT_INDEX), vec![[0, 1, 2, 3]]);
+        assert_eq!(indices(&mesh, ATTRIBUTE_JOINT_INDEX_1), vec![[4, 5, 6, 7]]);
+
+        let (first, second) = (weights(&mesh, Mesh::ATTRIBUTE_JOINT_WEIGHT), weights(&mesh, ATTRIBUTE_JOINT_WEIGHT_1));
+        let sum: f32 = first[0].iter().chain(second[0].iter()).sum();
+        assert!((sum - 1.0).abs() < 1e-6);
+        assert!((first[0][0] - 10.0 / 52.0).abs() < 1e-6);
+        assert!((second[0][3] - 3.0 / 52.0).abs() < 1e-6);
+    }
+}
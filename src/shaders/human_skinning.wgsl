@@ -0,0 +1,159 @@
+// Bevy's mesh vertex shader with up to 8 bone influences and optional dual quaternion blending.
+// Joint matrices already include the inverse bindposes.
+#import bevy_pbr::{
+    mesh_functions,
+    skinning,
+    view_transformations::position_world_to_clip,
+    forward_io::VertexOutput,
+}
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) tangent: vec4<f32>,
+    @location(6) joint_indices: vec4<u32>,
+    @location(7) joint_weights: vec4<f32>,
+#ifdef EXTRA_INFLUENCES
+    @location(8) joint_indices_1: vec4<u32>,
+    @location(9) joint_weights_1: vec4<f32>,
+#endif
+};
+
+fn quat_from_mat3(m: mat3x3<f32>) -> vec4<f32> {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    if trace > 0.0 {
+        let s = sqrt(trace + 1.0) * 2.0;
+        return vec4<f32>((m[1][2] - m[2][1]) / s, (m[2][0] - m[0][2]) / s, (m[0][1] - m[1][0]) / s, 0.25 * s);
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = sqrt(1.0 + m[0][0] - m[1][1] - m[2][2]) * 2.0;
+        return vec4<f32>(0.25 * s, (m[1][0] + m[0][1]) / s, (m[2][0] + m[0][2]) / s, (m[1][2] - m[2][1]) / s);
+    } else if m[1][1] > m[2][2] {
+        let s = sqrt(1.0 + m[1][1] - m[0][0] - m[2][2]) * 2.0;
+        return vec4<f32>((m[1][0] + m[0][1]) / s, 0.25 * s, (m[2][1] + m[1][2]) / s, (m[2][0] - m[0][2]) / s);
+    }
+    let s = sqrt(1.0 + m[2][2] - m[0][0] - m[1][1]) * 2.0;
+    return vec4<f32>((m[2][0] + m[0][2]) / s, (m[2][1] + m[1][2]) / s, 0.25 * s, (m[0][1] - m[1][0]) / s);
+}
+
+// Scaled humans scale every joint matrix, which a dual quaternion can't hold
+fn joint_scale(m: mat4x4<f32>) -> vec3<f32> {
+    return vec3<f32>(length(m[0].xyz), length(m[1].xyz), length(m[2].xyz));
+}
+
+// Real part in the first column, dual part in the second.  The scale is left out.
+fn dual_quat_from_joint(index: u32) -> mat2x4<f32> {
+    let m = skinning::joint_matrices.data[index];
+    let s = joint_scale(m);
+    let q = quat_from_mat3(mat3x3<f32>(m[0].xyz / s.x, m[1].xyz / s.y, m[2].xyz / s.z));
+    let t = m[3].xyz;
+    let d = 0.5 * vec4<f32>(
+        q.w * t.x + t.y * q.z - t.z * q.y,
+        q.w * t.y + t.z * q.x - t.x * q.z,
+        q.w * t.z + t.x * q.y - t.y * q.x,
+        -dot(t, q.xyz),
+    );
+    return mat2x4<f32>(q, d);
+}
+
+// Joints on the far side of the first one's hemisphere are flipped to take the short way
+fn blend_dual_quats(indices: vec4<u32>, weights: vec4<f32>, pivot: vec4<f32>) -> mat2x4<f32> {
+    var blended = mat2x4<f32>(vec4<f32>(0.0), vec4<f32>(0.0));
+    for (var i = 0u; i < 4u; i++) {
+        if weights[i] == 0.0 { continue; }
+        let dq = dual_quat_from_joint(indices[i]);
+        let sign = select(1.0, -1.0, dot(dq[0], pivot) < 0.0);
+        blended[0] += dq[0] * weights[i] * sign;
+        blended[1] += dq[1] * weights[i] * sign;
+    }
+    return blended;
+}
+
+// Blended linearly and applied before the rotation, exact for uniformly scaled humans
+fn blend_scales(indices: vec4<u32>, weights: vec4<f32>) -> vec3<f32> {
+    var blended = vec3<f32>(0.0);
+    for (var i = 0u; i < 4u; i++) {
+        if weights[i] == 0.0 { continue; }
+        blended += joint_scale(skinning::joint_matrices.data[indices[i]]) * weights[i];
+    }
+    return blended;
+}
+
+fn mat4_from_dual_quat(dq: mat2x4<f32>) -> mat4x4<f32> {
+    let len = length(dq[0]);
+    let q = dq[0] / len;
+    let d = dq[1] / len;
+    let t = 2.0 * (q.w * d.xyz - d.w * q.xyz + cross(q.xyz, d.xyz));
+    let x2 = q.x + q.x;
+    let y2 = q.y + q.y;
+    let z2 = q.z + q.z;
+    let xx = q.x * x2;
+    let xy = q.x * y2;
+    let xz = q.x * z2;
+    let yy = q.y * y2;
+    let yz = q.y * z2;
+    let zz = q.z * z2;
+    let wx = q.w * x2;
+    let wy = q.w * y2;
+    let wz = q.w * z2;
+    return mat4x4<f32>(
+        vec4<f32>(1.0 - (yy + zz), xy + wz, xz - wy, 0.0),
+        vec4<f32>(xy - wz, 1.0 - (xx + zz), yz + wx, 0.0),
+        vec4<f32>(xz + wy, yz - wx, 1.0 - (xx + yy), 0.0),
+        vec4<f32>(t, 1.0),
+    );
+}
+
+fn skin_model(vertex: Vertex) -> mat4x4<f32> {
+#ifdef DUAL_QUATERNION
+    let pivot = dual_quat_from_joint(vertex.joint_indices.x)[0];
+    var blended = blend_dual_quats(vertex.joint_indices, vertex.joint_weights, pivot);
+    var scale = blend_scales(vertex.joint_indices, vertex.joint_weights);
+#ifdef EXTRA_INFLUENCES
+    let extra = blend_dual_quats(vertex.joint_indices_1, vertex.joint_weights_1, pivot);
+    blended[0] += extra[0];
+    blended[1] += extra[1];
+    scale += blend_scales(vertex.joint_indices_1, vertex.joint_weights_1);
+#endif
+    let scale_matrix = mat4x4<f32>(
+        vec4<f32>(scale.x, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, scale.y, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, scale.z, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+    return mat4_from_dual_quat(blended) * scale_matrix;
+#else
+    var world_from_local = skinning::skin_model(vertex.joint_indices, vertex.joint_weights);
+#ifdef EXTRA_INFLUENCES
+    world_from_local += skinning::skin_model(vertex.joint_indices_1, vertex.joint_weights_1);
+#endif
+    return world_from_local;
+#endif
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    let world_from_local = skin_model(vertex);
+    out.world_normal = skinning::skin_normals(world_from_local, vertex.normal);
+    out.world_position = mesh_functions::mesh_position_local_to_world(world_from_local, vec4<f32>(vertex.position, 1.0));
+    out.position = position_world_to_clip(out.world_position.xyz);
+#ifdef VERTEX_UVS_A
+    out.uv = vertex.uv;
+#else ifdef VERTEX_UVS
+    out.uv = vertex.uv;
+#endif
+#ifdef VERTEX_TANGENTS
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent,
+        vertex.instance_index
+    );
+#endif
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    out.instance_index = vertex.instance_index;
+#endif
+    return out;
+}
@@ -0,0 +1,382 @@
+use bevy::{
+    animation::AnimationTargetId,
+    prelude::*,
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+use crate::{
+    rigs::BoneData,
+    RigType,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Rig independent names for the main bones
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum HumanBone {
+    Root,
+    Hips,
+    Spine,
+    Chest,
+    UpperChest,
+    Neck,
+    Head,
+    LeftShoulder,
+    LeftUpperArm,
+    LeftLowerArm,
+    LeftHand,
+    RightShoulder,
+    RightUpperArm,
+    RightLowerArm,
+    RightHand,
+    LeftUpperLeg,
+    LeftLowerLeg,
+    LeftFoot,
+    LeftToes,
+    RightUpperLeg,
+    RightLowerLeg,
+    RightFoot,
+    RightToes,
+}
+
+impl HumanBone {
+    pub const ALL: [HumanBone; 23] = [
+        HumanBone::Root,
+        HumanBone::Hips,
+        HumanBone::Spine,
+        HumanBone::Chest,
+        HumanBone::UpperChest,
+        HumanBone::Neck,
+        HumanBone::Head,
+        HumanBone::LeftShoulder,
+        HumanBone::LeftUpperArm,
+        HumanBone::LeftLowerArm,
+        HumanBone::LeftHand,
+        HumanBone::RightShoulder,
+        HumanBone::RightUpperArm,
+        HumanBone::RightLowerArm,
+        HumanBone::RightHand,
+        HumanBone::LeftUpperLeg,
+        HumanBone::LeftLowerLeg,
+        HumanBone::LeftFoot,
+        HumanBone::LeftToes,
+        HumanBone::RightUpperLeg,
+        HumanBone::RightLowerLeg,
+        HumanBone::RightFoot,
+        HumanBone::RightToes,
+    ];
+
+    // "RightHand" -> HumanBone::RightHand
+    pub fn from_name(name: &str) -> Option<HumanBone> {
+        HumanBone::ALL.iter().copied().find(|bone| format!("{:?}", bone) == name)
+    }
+
+    // Bone of the built-in rigs, None for custom rigs or bones the rig doesn't have
+    pub fn rig_name(&self, rig: &RigType) -> Option<String> {
+        let (default, game_engine, mixamo) = match self {
+            HumanBone::Root => ("root", "Root", "Root"),
+            HumanBone::Hips => ("root", "pelvis", "mixamorig:Hips"),
+            HumanBone::Spine => ("spine05", "spine_01", "mixamorig:Spine"),
+            HumanBone::Chest => ("spine03", "spine_02", "mixamorig:Spine1"),
+            HumanBone::UpperChest => ("spine01", "spine_03", "mixamorig:Spine2"),
+            HumanBone::Neck => ("neck01", "neck_01", "mixamorig:Neck"),
+            HumanBone::Head => ("head", "head", "mixamorig:Head"),
+            HumanBone::LeftShoulder => ("clavicle.L", "clavicle_l", "mixamorig:LeftShoulder"),
+            HumanBone::LeftUpperArm => ("upperarm01.L", "upperarm_l", "mixamorig:LeftArm"),
+            HumanBone::LeftLowerArm => ("lowerarm01.L", "lowerarm_l", "mixamorig:LeftForeArm"),
+            HumanBone::LeftHand => ("wrist.L", "hand_l", "mixamorig:LeftHand"),
+            HumanBone::RightShoulder => ("clavicle.R", "clavicle_r", "mixamorig:RightShoulder"),
+            HumanBone::RightUpperArm => ("upperarm01.R", "upperarm_r", "mixamorig:RightArm"),
+            HumanBone::RightLowerArm => ("lowerarm01.R", "lowerarm_r", "mixamorig:RightForeArm"),
+            HumanBone::RightHand => ("wrist.R", "hand_r", "mixamorig:RightHand"),
+            HumanBone::LeftUpperLeg => ("upperleg01.L", "thigh_l", "mixamorig:LeftUpLeg"),
+            HumanBone::LeftLowerLeg => ("lowerleg01.L", "calf_l", "mixamorig:LeftLeg"),
+            HumanBone::LeftFoot => ("foot.L", "foot_l", "mixamorig:LeftFoot"),
+            HumanBone::LeftToes => ("", "ball_l", "mixamorig:LeftToeBase"),
+            HumanBone::RightUpperLeg => ("upperleg01.R", "thigh_r", "mixamorig:RightUpLeg"),
+            HumanBone::RightLowerLeg => ("lowerleg01.R", "calf_r", "mixamorig:RightLeg"),
+            HumanBone::RightFoot => ("foot.R", "foot_r", "mixamorig:RightFoot"),
+            HumanBone::RightToes => ("", "ball_r", "mixamorig:RightToeBase"),
+        };
+        let name = match rig {
+            RigType::Default => default,
+            RigType::GameEngine => game_engine,
+            RigType::Mixamo => mixamo,
+            RigType::None | RigType::Custom(_) => "",
+        };
+        if name.is_empty() { None } else { Some(name.to_string()) }
+    }
+}
+
+// Bone hierarchy of one rig, built once when the rig is loaded and shared by every human using it.
+// Joints are ordered by depth then name, so indices are the same across runs and humans.
+#[derive(Debug)]
+pub struct HumanSkeletonTemplate {
+    rig: RigType,
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+    parents: Vec<Option<usize>>,
+    // None for the inserted root, animations don't move it
+    target_ids: Vec<Option<AnimationTargetId>>,
+}
+
+impl HumanSkeletonTemplate {
+    pub(crate) fn new(rig: RigType, bones: &HashMap<String, BoneData>) -> Self {
+        let depth = |name: &String| {
+            let mut depth = 0;
+            let mut parent = &bones[name].parent;
+            while !parent.is_empty() {
+                depth += 1;
+                parent = &bones[parent].parent;
+            }
+            depth
+        };
+        let mut names: Vec<String> = bones.keys().cloned().collect();
+        names.sort_by_cached_key(|name| (depth(name), name.clone()));
+
+        // Mixamo has hips as root, a bone is inserted above them as "Root"
+        let inserted_root = names.first().is_some_and(|root| root.ends_with("Hips"));
+        if inserted_root { names.insert(0, "Root".to_string()); }
+
+        let indices: HashMap<String, usize> = names.iter().cloned().enumerate().map(|(i, name)| (name, i)).collect();
+        let parents = names.iter().enumerate().map(|(i, name)| {
+            match bones.get(name).map(|bone| bone.parent.as_str()) {
+                Some("") | None => (inserted_root && i != 0).then_some(0),
+                Some(parent) => indices.get(parent).copied(),
+            }
+        }).collect();
+
+        // Paths stop below the root bone, matching the animation libraries
+        let target_ids = names.iter().map(|name| {
+            if name == "Root" { return None; }
+            let mut path = vec![Name::new(name.clone())];
+            let mut parent = &bones[name].parent;
+            while !parent.is_empty() && !parent.eq_ignore_ascii_case("root") {
+                path.push(Name::new(parent.clone()));
+                parent = &bones[parent].parent;
+            }
+            path.push(Name::new("Human.rig"));
+            Some(AnimationTargetId::from_names(path.iter().rev()))
+        }).collect();
+
+        HumanSkeletonTemplate { rig, names, indices, parents, target_ids }
+    }
+
+    pub fn rig(&self) -> &RigType {
+        &self.rig
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    // Bone names in joint order
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn joint_index(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.parents.get(index).copied().flatten()
+    }
+
+    pub(crate) fn target_id(&self, index: usize) -> Option<AnimationTargetId> {
+        self.target_ids[index]
+    }
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+// Bones of a spawned human.  Joint indices match SkinnedMesh::joints.
+#[derive(Component, Clone, Debug)]
+pub struct HumanSkeleton {
+    template: Arc<HumanSkeletonTemplate>,
+    joints: Vec<Entity>,
+    // Local space, roots are relative to the human
+    rest_transforms: Vec<Transform>,
+}
+
+impl HumanSkeleton {
+    pub(crate) fn new(
+        template: Arc<HumanSkeletonTemplate>,
+        joints: Vec<Entity>,
+        rest_transforms: Vec<Transform>,
+    ) -> Self {
+        HumanSkeleton { template, joints, rest_transforms }
+    }
+
+    // Shared by every human with the same rig
+    pub fn template(&self) -> &Arc<HumanSkeletonTemplate> {
+        &self.template
+    }
+
+    pub fn rig(&self) -> &RigType {
+        self.template.rig()
+    }
+
+    pub fn len(&self) -> usize {
+        self.joints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.joints.is_empty()
+    }
+
+    // Bone names in joint order
+    pub fn names(&self) -> &[String] {
+        self.template.names()
+    }
+
+    pub fn joints(&self) -> &[Entity] {
+        &self.joints
+    }
+
+    // Rig specific name, like "mixamorig:RightHand"
+    pub fn entity(&self, name: &str) -> Option<Entity> {
+        self.joint_index(name).map(|index| self.joints[index])
+    }
+
+    pub fn joint_index(&self, name: &str) -> Option<usize> {
+        self.template.joint_index(name)
+    }
+
+    pub fn rest_transform(&self, index: usize) -> Option<Transform> {
+        self.rest_transforms.get(index).copied()
+    }
+
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.template.parent(index)
+    }
+
+    pub fn human_bone(&self, bone: HumanBone) -> Option<Entity> {
+        bone.rig_name(self.rig()).and_then(|name| self.entity(&name))
+    }
+
+    // Rig specific names first, then HumanBone names, so "RightHand" works on every built-in rig
+    pub fn bone(&self, name: &str) -> Option<Entity> {
+        self.entity(name).or_else(|| HumanBone::from_name(name).and_then(|bone| self.human_bone(bone)))
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rigs::{ parse_rig_file, RigFile };
+
+    const RIGS: [(RigType, &[u8]); 3] = [
+        (RigType::Default, include_bytes!("../assets/rigs/rig.default.json")),
+        (RigType::GameEngine, include_bytes!("../assets/rigs/rig.game_engine.json")),
+        (RigType::Mixamo, include_bytes!("../assets/rigs/rig.mixamo.json")),
+    ];
+
+    fn template(rig: RigType, bytes: &[u8]) -> HumanSkeletonTemplate {
+        let Ok(RigFile::Bones(bones)) = parse_rig_file(bytes) else { panic!("FAILED TO PARSE {:?} RIG", rig) };
+        HumanSkeletonTemplate::new(rig, &bones)
+    }
+
+    // Joints are stand-in entities numbered by joint index
+    fn skeleton(rig: RigType, bytes: &[u8]) -> HumanSkeleton {
+        let template = template(rig, bytes);
+        let joints = (0..template.len() as u32).map(Entity::from_raw).collect();
+        let rest_transforms = vec![Transform::IDENTITY; template.len()];
+        HumanSkeleton::new(Arc::new(template), joints, rest_transforms)
+    }
+
+    #[test]
+    fn human_bones_exist_in_every_rig() {
+        for (rig, bytes) in RIGS {
+            let template = template(rig.clone(), bytes);
+            for bone in HumanBone::ALL {
+                let Some(name) = bone.rig_name(&rig) else {
+                    // Only the default rig lacks toe bones
+                    assert!(rig == RigType::Default && matches!(bone, HumanBone::LeftToes | HumanBone::RightToes));
+                    continue;
+                };
+                assert!(template.joint_index(&name).is_some(), "{:?} has no {} for {:?}", rig, name, bone);
+            }
+        }
+    }
+
+    #[test]
+    fn other_rigs_have_no_human_bones() {
+        for rig in [RigType::None, RigType::Custom("robot".to_string())] {
+            assert!(HumanBone::ALL.iter().all(|bone| bone.rig_name(&rig).is_none()));
+        }
+    }
+
+    #[test]
+    fn human_bones_round_trip_names() {
+        for bone in HumanBone::ALL {
+            assert_eq!(HumanBone::from_name(&format!("{:?}", bone)), Some(bone));
+        }
+        assert_eq!(HumanBone::from_name("righthand"), None);
+    }
+
+    #[test]
+    fn bones_are_found_by_either_name() {
+        let (rig, bytes) = RIGS[2].clone();
+        let mixamo = skeleton(rig, bytes);
+        let hand = mixamo.entity("mixamorig:RightHand").unwrap();
+        assert_eq!(mixamo.bone("mixamorig:RightHand"), Some(hand));
+        assert_eq!(mixamo.bone("RightHand"), Some(hand));
+        assert_eq!(mixamo.human_bone(HumanBone::RightHand), Some(hand));
+        assert_eq!(mixamo.bone("hand_r"), None);
+
+        let (rig, bytes) = RIGS[1].clone();
+        let game_engine = skeleton(rig, bytes);
+        assert_eq!(game_engine.bone("RightHand"), game_engine.entity("hand_r"));
+    }
+
+    #[test]
+    fn joints_order_by_depth_then_name() {
+        let json = r#"{
+            "spine": { "head": {}, "tail": {}, "parent": "root" },
+            "root": { "head": {}, "tail": {}, "parent": "" },
+            "thigh_r": { "head": {}, "tail": {}, "parent": "root" },
+            "head": { "head": {}, "tail": {}, "parent": "spine" },
+            "arm_l": { "head": {}, "tail": {}, "parent": "spine" }
+        }"#;
+        let template = template(RigType::Custom("test".to_string()), json.as_bytes());
+        assert_eq!(template.names(), ["root", "spine", "thigh_r", "arm_l", "head"]);
+        let parents: Vec<Option<usize>> = (0..template.len()).map(|i| template.parent(i)).collect();
+        assert_eq!(parents, [None, Some(0), Some(0), Some(1), Some(1)]);
+    }
+
+    #[test]
+    fn joint_order_repeats() {
+        for (rig, bytes) in RIGS {
+            // Each parse hashes the bones with a new seed
+            let first = template(rig.clone(), bytes);
+            let second = template(rig.clone(), bytes);
+            assert_eq!(first.names(), second.names());
+            for i in 0..first.len() {
+                assert!(first.parent(i).is_none_or(|parent| parent < i), "{:?} joint {} before its parent", rig, i);
+                assert_eq!(first.target_id(i), second.target_id(i));
+            }
+        }
+    }
+
+    #[test]
+    fn mixamo_gets_a_root_above_the_hips() {
+        let (rig, bytes) = RIGS[2].clone();
+        let template = template(rig, bytes);
+        assert_eq!(template.names()[..2], ["Root", "mixamorig:Hips"]);
+        assert_eq!(template.parent(1), Some(0));
+        assert!(template.target_id(0).is_none());
+        assert!(template.target_id(1).is_some());
+    }
+}
@@ -0,0 +1,179 @@
+#[cfg(feature = "render")]
+use bevy::{
+    prelude::*,
+    asset::embedded_asset,
+    ecs::system::SystemParam,
+    pbr::{
+        ExtendedMaterial,
+        MaterialExtension,
+        MaterialExtensionKey,
+        MaterialExtensionPipeline,
+    },
+    render::{
+        mesh::{ MeshVertexAttribute, MeshVertexBufferLayoutRef },
+        render_resource::{
+            AsBindGroup,
+            RenderPipelineDescriptor,
+            ShaderRef,
+            SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+    },
+};
+use serde::{ Deserialize, Serialize };
+
+#[cfg(feature = "render")]
+const SHADER_PATH: &str = "embedded://humentity/shaders/human_skinning.wgsl";
+
+// Bone influences 5 to 8, for SkinningSettings::influences above 4
+#[cfg(feature = "render")]
+pub const ATTRIBUTE_JOINT_INDEX_1: MeshVertexAttribute =
+    MeshVertexAttribute::new("Humentity_JointIndex1", 988_540_917, VertexFormat::Uint16x4);
+#[cfg(feature = "render")]
+pub const ATTRIBUTE_JOINT_WEIGHT_1: MeshVertexAttribute =
+    MeshVertexAttribute::new("Humentity_JointWeight1", 988_540_918, VertexFormat::Float32x4);
+
+#[cfg(feature = "render")]
+pub(crate) const MAX_INFLUENCES: usize = 8;
+
+/*---------+
+ |  Types  |
+ +---------*/
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SkinningSettings {
+    // Bone influences kept per vertex, up to 8.  Bevy's own skinning only reads 4.
+    pub influences: usize,
+    // Blends bones as dual quaternions, which keeps volume at wrists and shoulders
+    pub dual_quaternion: bool,
+}
+
+impl Default for SkinningSettings {
+    fn default() -> Self {
+        SkinningSettings {
+            influences: 4,
+            dual_quaternion: false,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl SkinningSettings {
+    pub(crate) fn influences(&self) -> usize {
+        self.influences.clamp(1, MAX_INFLUENCES)
+    }
+
+    // Anything bevy's skinning can't do goes through HumanSkinnedMaterial
+    pub(crate) fn needs_custom_shader(&self) -> bool {
+        self.influences() > 4 || self.dual_quaternion
+    }
+}
+
+// Main pass only.  Shadows and other prepasses still use bevy's 4 linear influences.
+#[cfg(feature = "render")]
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+#[bind_group_data(HumanSkinningKey)]
+pub struct HumanSkinning {
+    extra_influences: bool,
+    dual_quaternion: bool,
+}
+
+#[cfg(feature = "render")]
+#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+pub struct HumanSkinningKey {
+    extra_influences: bool,
+    dual_quaternion: bool,
+}
+
+#[cfg(feature = "render")]
+impl From<&HumanSkinning> for HumanSkinningKey {
+    fn from(skinning: &HumanSkinning) -> Self {
+        HumanSkinningKey {
+            extra_influences: skinning.extra_influences,
+            dual_quaternion: skinning.dual_quaternion,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl MaterialExtension for HumanSkinning {
+    fn vertex_shader() -> ShaderRef {
+        SHADER_PATH.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayoutRef,
+        key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // Prepasses keep bevy's vertex shader and layout
+        if descriptor.vertex.shader_defs.contains(&"PREPASS_PIPELINE".into()) { return Ok(()); }
+
+        let mut attributes = vec![
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_TANGENT.at_shader_location(4),
+            Mesh::ATTRIBUTE_JOINT_INDEX.at_shader_location(6),
+            Mesh::ATTRIBUTE_JOINT_WEIGHT.at_shader_location(7),
+        ];
+        if key.bind_group_data.extra_influences {
+            attributes.push(ATTRIBUTE_JOINT_INDEX_1.at_shader_location(8));
+            attributes.push(ATTRIBUTE_JOINT_WEIGHT_1.at_shader_location(9));
+            descriptor.vertex.shader_defs.push("EXTRA_INFLUENCES".into());
+        }
+        if key.bind_group_data.dual_quaternion {
+            descriptor.vertex.shader_defs.push("DUAL_QUATERNION".into());
+        }
+        descriptor.vertex.buffers = vec![layout.0.get_layout(&attributes)?];
+        Ok(())
+    }
+}
+
+#[cfg(feature = "render")]
+pub type HumanSkinnedMaterial = ExtendedMaterial<StandardMaterial, HumanSkinning>;
+
+// Material handle of one human mesh
+#[cfg(feature = "render")]
+#[derive(Clone, Debug)]
+pub(crate) enum HumanMaterial {
+    Standard(Handle<StandardMaterial>),
+    Skinned(Handle<HumanSkinnedMaterial>),
+}
+
+/*-----------------+
+ |  System Params  |
+ +-----------------*/
+#[cfg(feature = "render")]
+#[derive(SystemParam)]
+pub(crate) struct HumanMaterials<'w> {
+    standard: ResMut<'w, Assets<StandardMaterial>>,
+    skinned: ResMut<'w, Assets<HumanSkinnedMaterial>>,
+}
+
+#[cfg(feature = "render")]
+impl HumanMaterials<'_> {
+    // Unskinned humans pass None
+    pub(crate) fn add(&mut self, material: StandardMaterial, skinning: Option<&SkinningSettings>) -> HumanMaterial {
+        match skinning.filter(|s| s.needs_custom_shader()) {
+            Some(settings) => HumanMaterial::Skinned(self.skinned.add(ExtendedMaterial {
+                base: material,
+                extension: HumanSkinning {
+                    extra_influences: settings.influences() > 4,
+                    dual_quaternion: settings.dual_quaternion,
+                },
+            })),
+            None => HumanMaterial::Standard(self.standard.add(material)),
+        }
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+#[cfg(feature = "render")]
+pub(crate) fn add_skinning_material(app: &mut App) {
+    embedded_asset!(app, "shaders/human_skinning.wgsl");
+    app.add_plugins(MaterialPlugin::<HumanSkinnedMaterial>::default());
+}
@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use crate::{
+    rigs::{ get_bone_head_tail, get_bone_transform, RigData },
+    HumanBone,
+    HumanPart,
+    HumanSkeleton,
+    HumentityGlobalConfig,
+    RigType,
+    VertexGroups,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Point on a bone, measured in bone lengths so it follows the morphed body.
+// bone is a rig bone name or a HumanBone name, along runs from head (0) to tail (1).
+// offset is in the human's space (+Y up, +Z forward), attached entities start aligned with it.
+#[derive(Clone, Debug)]
+pub struct HumanSocketDef {
+    pub bone: String,
+    pub along: f32,
+    pub offset: Vec3,
+}
+
+impl HumanSocketDef {
+    pub fn new(bone: impl Into<String>, along: f32, offset: Vec3) -> Self {
+        HumanSocketDef { bone: bone.into(), along, offset }
+    }
+}
+
+/*--------------+
+ |  Components  |
+ +--------------*/
+// Parents the entity to a socket once the human's rig is built.  Its Transform becomes
+// relative to the socket.
+#[derive(Component, Clone, Debug)]
+pub struct AttachTo {
+    pub human: Entity,
+    pub socket: String,
+}
+
+// Socket entities of a spawned human, children of their bones
+#[derive(Component, Clone, Debug, Default)]
+pub struct HumanSockets(HashMap<String, Entity>);
+
+impl HumanSockets {
+    pub fn get(&self, socket: &str) -> Option<Entity> {
+        self.0.get(socket).copied()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+// Same HumanBone based sockets on every built-in rig
+pub(crate) fn default_sockets() -> HashMap<RigType, HashMap<String, HumanSocketDef>> {
+    let sockets = [
+        ("right_hand_grip", HumanSocketDef::new("RightHand", 0.5, Vec3::ZERO)),
+        ("left_hand_grip", HumanSocketDef::new("LeftHand", 0.5, Vec3::ZERO)),
+        ("head_top", HumanSocketDef::new("Head", 1.0, Vec3::ZERO)),
+        ("back", HumanSocketDef::new("UpperChest", 0.5, Vec3::new(0.0, 0.0, -1.0))),
+        ("hips", HumanSocketDef::new("Hips", 0.0, Vec3::ZERO)),
+    ];
+    [RigType::Default, RigType::Mixamo, RigType::GameEngine].into_iter()
+        .map(|rig| {
+            let defs = sockets.iter().map(|(name, def)| (name.to_string(), def.clone())).collect();
+            (rig, defs)
+        })
+        .collect()
+}
+
+// Called right after build_rig with its skeleton
+pub(crate) fn spawn_sockets(
+    commands: &mut Commands,
+    human: Entity,
+    skeleton: &HumanSkeleton,
+    rigs: &RigData,
+    global_config: &HumentityGlobalConfig,
+    vg: &VertexGroups,
+    helpers: &[Vec3],
+) {
+    let rig = skeleton.rig();
+    let Some(defs) = global_config.sockets.get(rig) else { return };
+    let Some(config_res) = rigs.configs.get(rig) else { return };
+    let mut sockets = HumanSockets::default();
+    for (name, def) in defs.iter() {
+        let bone_name = if config_res.contains_key(&def.bone) {
+            Some(def.bone.clone())
+        } else {
+            HumanBone::from_name(&def.bone).and_then(|bone| bone.rig_name(rig))
+        };
+        let Some((bone_name, bone)) = bone_name.and_then(|n| config_res.get(&n).map(|b| (n, b))) else {
+            warn!("Socket {} needs bone {} which {:?} doesn't have", name, def.bone, rig);
+            continue;
+        };
+        let Some(joint) = skeleton.entity(&bone_name) else { continue };
+
+        let (head, tail) = get_bone_head_tail(bone, vg, helpers);
+        let local = socket_transform(def, head, tail, get_bone_transform(bone, vg, helpers).rotation);
+
+        let socket = commands.spawn((
+            Name::new(format!("socket:{}", name)),
+            HumanPart(human),
+            SpatialBundle::from_transform(local),
+        )).id();
+        commands.entity(joint).add_child(socket);
+        sockets.0.insert(name.clone(), socket);
+    }
+    commands.entity(human).insert(sockets);
+}
+
+// Rest pose in the human's space, then relative to the bone
+fn socket_transform(def: &HumanSocketDef, head: Vec3, tail: Vec3, bone_rotation: Quat) -> Transform {
+    let length = (tail - head).length();
+    let position = head.lerp(tail, def.along) + def.offset * length;
+    let rotation = bone_rotation.inverse();
+    Transform {
+        translation: rotation * (position - head),
+        rotation,
+        ..default()
+    }
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+// Waits for the human's sockets, and re-parents after the human is rebuilt
+pub(crate) fn attach_to_sockets(
+    attachments: Query<(Entity, &AttachTo, Option<&Parent>)>,
+    humans: Query<&HumanSockets>,
+    mut commands: Commands,
+) {
+    for (entity, attach, parent) in attachments.iter() {
+        let Ok(sockets) = humans.get(attach.human) else { continue };
+        let Some(socket) = sockets.get(&attach.socket) else {
+            warn!("Human {:?} has no socket {}", attach.human, attach.socket);
+            commands.entity(entity).remove::<AttachTo>();
+            continue;
+        };
+        if parent.is_some_and(|parent| parent.get() == socket) { continue; }
+        commands.entity(socket).add_child(entity);
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Socket in the human's space, through a bone at the head with the given rotation
+    fn placed(def: &HumanSocketDef, head: Vec3, tail: Vec3, bone_rotation: Quat) -> Transform {
+        let bone = Transform::from_translation(head).with_rotation(bone_rotation);
+        bone * socket_transform(def, head, tail, bone_rotation)
+    }
+
+    #[test]
+    fn sockets_sit_along_the_bone() {
+        let (head, tail) = (Vec3::new(0.2, 1.0, 0.0), Vec3::new(0.2, 1.4, 0.3));
+        let rotation = Quat::from_rotation_arc(Vec3::Y, (tail - head).normalize());
+        for along in [0.0, 0.5, 1.0] {
+            let socket = placed(&HumanSocketDef::new("Head", along, Vec3::ZERO), head, tail, rotation);
+            assert!(socket.translation.distance(head.lerp(tail, along)) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn sockets_align_with_the_human() {
+        let (head, tail) = (Vec3::new(-0.3, 1.2, 0.1), Vec3::new(-0.6, 1.1, 0.1));
+        let rotation = Quat::from_euler(EulerRot::YXZ, 0.3, -1.1, 2.0);
+        let socket = placed(&HumanSocketDef::new("LeftLowerArm", 0.5, Vec3::ZERO), head, tail, rotation);
+        assert!(socket.rotation.angle_between(Quat::IDENTITY) < 1e-5);
+    }
+
+    #[test]
+    fn offsets_scale_with_bone_length() {
+        let def = HumanSocketDef::new("UpperChest", 0.0, Vec3::new(0.0, 0.0, -1.0));
+        let rotation = Quat::from_rotation_z(0.7);
+        let short = placed(&def, Vec3::ZERO, Vec3::Y * 0.2, rotation);
+        let long = placed(&def, Vec3::ZERO, Vec3::Y * 0.4, rotation);
+        assert!(short.translation.distance(Vec3::new(0.0, 0.0, -0.2)) < 1e-5);
+        assert!(long.translation.distance(Vec3::new(0.0, 0.0, -0.4)) < 1e-5);
+    }
+}
@@ -0,0 +1,373 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use std::fmt;
+#[cfg(feature = "clothing")]
+use std::collections::HashMap;
+#[cfg(feature = "clothing")]
+use crate::HumanAssetRegistry;
+#[cfg(feature = "rig")]
+use crate::RigData;
+use crate::{
+    HumanConfig,
+    HumentityGlobalConfig,
+    MorphTargets,
+    PendingHuman,
+    RigType,
+};
+
+/*---------+
+ |  Types  |
+ +---------*/
+// Suggestions are the closest known name, if any is close enough
+#[derive(Clone, Debug, PartialEq)]
+pub enum HumanConfigError {
+    UnknownTarget { name: String, suggestion: Option<String> },
+    UnknownBodyPart { name: String, suggestion: Option<String> },
+    UnknownEquipment { name: String, suggestion: Option<String> },
+    UnknownSkin { name: String, suggestion: Option<String> },
+    UnknownRig { name: String, suggestion: Option<String> },
+    // Rig files missing or unreadable
+    RigUnavailable { rig: String, reason: String },
+    // More than one body part or piece of equipment in the same slot
+    SlotConflict { slot: String, assets: Vec<String> },
+}
+
+impl fmt::Display for HumanConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, name, suggestion) = match self {
+            HumanConfigError::UnknownTarget { name, suggestion } => ("target", name, suggestion),
+            HumanConfigError::UnknownBodyPart { name, suggestion } => ("body part", name, suggestion),
+            HumanConfigError::UnknownEquipment { name, suggestion } => ("equipment", name, suggestion),
+            HumanConfigError::UnknownSkin { name, suggestion } => ("skin", name, suggestion),
+            HumanConfigError::UnknownRig { name, suggestion } => ("custom rig", name, suggestion),
+            HumanConfigError::RigUnavailable { rig, reason } => {
+                return write!(f, "rig {} can't be loaded, {}", rig, reason);
+            }
+            HumanConfigError::SlotConflict { slot, assets } => {
+                return write!(f, "slot {} is used by {}", slot, assets.join(", "));
+            }
+        };
+        write!(f, "unknown {} {}", kind, name)?;
+        if let Some(suggestion) = suggestion {
+            write!(f, ", did you mean {}?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/*----------+
+ |  Events  |
+ +----------*/
+// Sent instead of building a human whose config doesn't validate.  The entity is left as is.
+#[derive(Event)]
+pub struct HumanSpawnFailed {
+    pub human: Entity,
+    pub errors: Vec<HumanConfigError>,
+}
+
+/*----------------+
+ |  System Params  |
+ +----------------*/
+#[derive(SystemParam)]
+pub(crate) struct HumanValidationData<'w> {
+    targets: Res<'w, MorphTargets>,
+    global_config: Res<'w, HumentityGlobalConfig>,
+    #[cfg(feature = "clothing")]
+    registry: Option<Res<'w, HumanAssetRegistry>>,
+    #[cfg(feature = "rig")]
+    rigs: Option<ResMut<'w, RigData>>,
+}
+
+impl<'w> HumanValidationData<'w> {
+    // Rigs are read here, usually already while loading, so building never fails
+    fn validate(&mut self, config: &HumanConfig) -> Result<(), Vec<HumanConfigError>> {
+        #[cfg(feature = "rig")]
+        if let Some(rigs) = self.rigs.as_deref_mut() {
+            let known = match &config.rig {
+                RigType::Custom(name) => self.global_config.custom_rigs.contains_key(name),
+                _ => true,
+            };
+            // Failures are kept in the RigData for validate to report
+            if known { let _ = rigs.load(&config.rig); }
+        }
+        config.validate(
+            &self.targets,
+            &self.global_config,
+            #[cfg(feature = "clothing")] self.registry.as_deref(),
+            #[cfg(feature = "rig")] self.rigs.as_deref(),
+        )
+    }
+}
+
+/*-------------+
+ |  Functions  |
+ +-------------*/
+impl HumanConfig {
+    // Reports every problem at once rather than stopping at the first.  Body parts, equipment
+    // and skins aren't checked without a registry, rigs only once they were read.
+    pub fn validate(
+        &self,
+        targets: &MorphTargets,
+        global_config: &HumentityGlobalConfig,
+        #[cfg(feature = "clothing")] registry: Option<&HumanAssetRegistry>,
+        #[cfg(feature = "rig")] rigs: Option<&RigData>,
+    ) -> Result<(), Vec<HumanConfigError>> {
+        let mut errors = Vec::<HumanConfigError>::new();
+
+        let mut names: Vec<&String> = self.morph_targets.keys().collect();
+        names.sort();
+        for name in names {
+            if targets.0.contains_key(name) { continue; }
+            let suggestion = closest_name(name, targets.0.keys());
+            errors.push(HumanConfigError::UnknownTarget { name: name.clone(), suggestion });
+        }
+
+        if let RigType::Custom(name) = &self.rig {
+            if !global_config.custom_rigs.contains_key(name) {
+                let suggestion = closest_name(name, global_config.custom_rigs.keys());
+                errors.push(HumanConfigError::UnknownRig { name: name.clone(), suggestion });
+            }
+        }
+
+        #[cfg(feature = "rig")]
+        if let Some(reason) = rigs.and_then(|rigs| rigs.failure(&self.rig)) {
+            errors.push(HumanConfigError::RigUnavailable { rig: format!("{:?}", self.rig), reason: reason.clone() });
+        }
+
+        #[cfg(feature = "clothing")]
+        if let Some(registry) = registry {
+            errors.extend(self.validate_assets(registry));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    #[cfg(feature = "clothing")]
+    fn validate_assets(&self, registry: &HumanAssetRegistry) -> Vec<HumanConfigError> {
+        let mut errors = Vec::<HumanConfigError>::new();
+
+        let mut body_part_slots = HashMap::<&String, Vec<String>>::new();
+        for name in self.body_parts.iter() {
+            let Some(asset) = registry.body_parts.get(name) else {
+                let suggestion = closest_name(name, registry.body_parts.keys());
+                errors.push(HumanConfigError::UnknownBodyPart { name: name.clone(), suggestion });
+                continue;
+            };
+            for slot in asset.slots.iter() {
+                body_part_slots.entry(slot).or_default().push(name.clone());
+            }
+        }
+
+        let mut equipment_slots = HashMap::<&String, Vec<String>>::new();
+        for name in self.equipment.iter() {
+            let Some(asset) = registry.equipment.get(name) else {
+                let suggestion = closest_name(name, registry.equipment.keys());
+                errors.push(HumanConfigError::UnknownEquipment { name: name.clone(), suggestion });
+                continue;
+            };
+            for slot in asset.slots.iter() {
+                equipment_slots.entry(slot).or_default().push(name.clone());
+            }
+        }
+
+        // An empty skin just leaves the body untextured
+        if !self.skin_albedo.is_empty() && !registry.skins.contains(&self.skin_albedo) {
+            let suggestion = closest_name(&self.skin_albedo, registry.skins.iter());
+            errors.push(HumanConfigError::UnknownSkin { name: self.skin_albedo.clone(), suggestion });
+        }
+
+        let mut conflicts: Vec<(&String, Vec<String>)> = body_part_slots.into_iter()
+            .chain(equipment_slots)
+            .filter(|(_, assets)| assets.len() > 1)
+            .collect();
+        conflicts.sort();
+        for (slot, assets) in conflicts {
+            errors.push(HumanConfigError::SlotConflict { slot: slot.clone(), assets });
+        }
+        errors
+    }
+}
+
+// Case insensitive edit distance, at most a third of the name's length
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<String> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)))
+        .map(|(_, candidate)| candidate.clone())
+}
+
+// Levenshtein distance with a single row
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/*-----------+
+ |  Systems  |
+ +-----------*/
+// Runs before on_human_added, failed humans are never built
+pub(crate) fn validate_new_humans(
+    new_humans: Query<(Entity, &HumanConfig), With<PendingHuman>>,
+    mut data: HumanValidationData,
+    mut failed: EventWriter<HumanSpawnFailed>,
+    mut commands: Commands,
+) {
+    for (human, config) in new_humans.iter() {
+        let Err(errors) = data.validate(config) else { continue };
+        for error in errors.iter() {
+            error!("Human {:?} not spawned: {}", human, error);
+        }
+        commands.entity(human).remove::<PendingHuman>();
+        failed.send(HumanSpawnFailed { human, errors });
+    }
+}
+
+/*---------+
+ |  Tests  |
+ +---------*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets() -> MorphTargets {
+        let names = ["measure-waist-circ-incr", "measure-hips-circ-incr", "head-age-less", "head-age-more"];
+        MorphTargets(names.iter().map(|name| (name.to_string(), Default::default())).collect())
+    }
+
+    #[cfg(feature = "clothing")]
+    fn registry() -> HumanAssetRegistry {
+        HumanAssetRegistry {
+            body_parts: HashMap::new(),
+            equipment: HashMap::new(),
+            slot_body_parts: HashMap::new(),
+            slot_equipment: HashMap::new(),
+            skins: vec!["young_caucasian_female.png".to_string()],
+            proxies: HashMap::new(),
+        }
+    }
+
+    fn errors(config: &HumanConfig, global_config: &HumentityGlobalConfig) -> Vec<HumanConfigError> {
+        config.validate(
+            &targets(),
+            global_config,
+            #[cfg(feature = "clothing")] Some(&registry()),
+            #[cfg(feature = "rig")] None,
+        ).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        let names: Vec<String> = ["Mixamo", "mixamo-lite", "GameEngine"].iter().map(|n| n.to_string()).collect();
+        // Case is ignored, ties go to the first name alphabetically
+        assert_eq!(closest_name("MIXAMO", names.iter()), Some("Mixamo".to_string()));
+        assert_eq!(closest_name("mixamolite", names.iter()), Some("mixamo-lite".to_string()));
+        assert_eq!(closest_name("GameEngin", names.iter()), Some("GameEngine".to_string()));
+        assert_eq!(closest_name("Mixam", names.iter()), Some("Mixamo".to_string()));
+        // A third of the name may differ, at least one character
+        assert_eq!(closest_name("Game", names.iter()), None);
+        assert_eq!(closest_name("ab", ["ac", "bb"].map(String::from).iter()), Some("ac".to_string()));
+        assert_eq!(closest_name("ab", ["cd"].map(String::from).iter()), None);
+    }
+
+    #[test]
+    fn counts_edits() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("flaw", "lawn"), 2);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        let mut config = HumanConfig::default();
+        config.morph_targets.insert("head-age-less".to_string(), 0.5);
+        assert_eq!(errors(&config, &HumentityGlobalConfig::default()), vec![]);
+    }
+
+    #[test]
+    fn collects_every_error() {
+        let mut config = HumanConfig::default();
+        config.morph_targets.insert("measure-waist-circ-inc".to_string(), 0.5);
+        config.morph_targets.insert("head-age-les".to_string(), 0.5);
+        config.morph_targets.insert("nothing-like-it".to_string(), 0.5);
+        config.rig = RigType::Custom("robott".to_string());
+        let global_config = HumentityGlobalConfig::default()
+            .with_custom_rig("robot", "robot.mhskel".into(), "robot_weights.mhw".into());
+
+        let errors = errors(&config, &global_config);
+        let unknown_target = |name: &str, suggestion: Option<&str>| HumanConfigError::UnknownTarget {
+            name: name.to_string(),
+            suggestion: suggestion.map(String::from),
+        };
+        // Targets come sorted by name
+        assert_eq!(errors[..4], [
+            unknown_target("head-age-les", Some("head-age-less")),
+            unknown_target("measure-waist-circ-inc", Some("measure-waist-circ-incr")),
+            unknown_target("nothing-like-it", None),
+            HumanConfigError::UnknownRig { name: "robott".to_string(), suggestion: Some("robot".to_string()) },
+        ]);
+        assert_eq!(errors.len(), 4);
+    }
+
+    #[cfg(feature = "clothing")]
+    #[test]
+    fn collects_asset_errors() {
+        let config = HumanConfig {
+            body_parts: vec!["eyebrow001".to_string(), "eyebrow002".to_string()],
+            equipment: vec!["shoes01".to_string()],
+            skin_albedo: "young_caucasian_femal.png".to_string(),
+            ..default()
+        };
+
+        let errors = errors(&config, &HumentityGlobalConfig::default());
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(&errors[0], HumanConfigError::UnknownBodyPart { name, suggestion: None } if name == "eyebrow001"));
+        assert!(matches!(&errors[2], HumanConfigError::UnknownEquipment { name, .. } if name == "shoes01"));
+        assert_eq!(errors[3], HumanConfigError::UnknownSkin {
+            name: "young_caucasian_femal.png".to_string(),
+            suggestion: Some("young_caucasian_female.png".to_string()),
+        });
+        assert_eq!(errors[3].to_string(), "unknown skin young_caucasian_femal.png, did you mean young_caucasian_female.png?");
+    }
+
+    #[cfg(feature = "rig")]
+    #[test]
+    fn reports_unreadable_rigs() {
+        let mut world = World::new();
+        world.insert_resource(HumentityGlobalConfig::default().with_asset_root("/nonexistent"));
+        let mut rigs = RigData::from_world(&mut world);
+        let global_config = world.resource::<HumentityGlobalConfig>();
+        let config = HumanConfig::default();
+        let validate = |rigs: &RigData| config.validate(
+            &targets(),
+            global_config,
+            #[cfg(feature = "clothing")] None,
+            Some(rigs),
+        );
+
+        // Not read yet
+        assert_eq!(validate(&rigs), Ok(()));
+        assert!(rigs.load(&config.rig).is_err());
+        let errors = validate(&rigs).unwrap_err();
+        assert!(matches!(&errors[..], [HumanConfigError::RigUnavailable { rig, .. }] if rig == "Mixamo"));
+    }
+}